@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use log::error;
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+    options::Options,
+};
+
+/// 基于 tokio 的异步存储引擎，将读写操作放到 spawn_blocking 中执行，
+/// 避免阻塞的文件 IO 占用异步运行时的工作线程
+#[derive(Clone)]
+pub struct AsyncEngine {
+    engine: Arc<Engine>,
+}
+
+impl AsyncEngine {
+    /// 打开异步存储引擎实例
+    pub async fn open(opts: Options) -> Result<Self> {
+        let engine = run_blocking(move || Engine::open(opts)).await?;
+        Ok(Self::new(engine))
+    }
+
+    /// 使用已经打开的存储引擎实例构造异步存储引擎
+    pub fn new(engine: Engine) -> Self {
+        Self {
+            engine: Arc::new(engine),
+        }
+    }
+
+    /// 获取内部的同步存储引擎实例
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// 存储 key/value 数据，key 不能为空
+    pub async fn put(&self, key: Bytes, value: Bytes) -> Result<()> {
+        let engine = self.engine.clone();
+        run_blocking(move || engine.put(key, value)).await
+    }
+
+    /// 根据 key 获取对应的数据信息
+    pub async fn get(&self, key: Bytes) -> Result<Bytes> {
+        let engine = self.engine.clone();
+        run_blocking(move || engine.get(key)).await
+    }
+
+    /// 根据 key 删除对应的数据
+    pub async fn delete(&self, key: Bytes) -> Result<()> {
+        let engine = self.engine.clone();
+        run_blocking(move || engine.delete(key)).await
+    }
+}
+
+// 在 tokio 的阻塞线程池中执行操作
+async fn run_blocking<F, T>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(e) => {
+            error!("failed to run blocking task: {}", e);
+            Err(Errors::BlockingTaskFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::util::rand_kv::{get_test_key, get_test_value};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_async_engine_put_get_delete() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-async-engine"),
+            ..Default::default()
+        };
+        let engine = AsyncEngine::open(opts.clone())
+            .await
+            .expect("failed to open engine");
+
+        // 并发写入
+        let mut handles = Vec::new();
+        for i in 0..100 {
+            let engine = engine.clone();
+            handles.push(tokio::spawn(async move {
+                engine.put(get_test_key(i), get_test_value(i)).await
+            }));
+        }
+        for handle in handles {
+            assert!(handle.await.unwrap().is_ok());
+        }
+
+        // 并发读取
+        let mut handles = Vec::new();
+        for i in 0..100 {
+            let engine = engine.clone();
+            handles.push(tokio::spawn(async move {
+                (i, engine.get(get_test_key(i)).await)
+            }));
+        }
+        for handle in handles {
+            let (i, res) = handle.await.unwrap();
+            assert_eq!(get_test_value(i), res.unwrap());
+        }
+
+        // 删除之后读取不到
+        let del_res = engine.delete(get_test_key(10)).await;
+        assert!(del_res.is_ok());
+        let get_res = engine.get(get_test_key(10)).await;
+        assert_eq!(Errors::KeyNotFound, get_res.err().unwrap());
+        assert_eq!(
+            get_test_value(11),
+            engine.engine().get(get_test_key(11)).unwrap()
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
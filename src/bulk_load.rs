@@ -0,0 +1,153 @@
+use std::{mem, sync::atomic::Ordering};
+
+use bytes::Bytes;
+use parking_lot::MutexGuard;
+
+use crate::{
+    data::log_record::LogRecordPos,
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+/// 批量导入，写入数据时只追加到数据文件中，结束时再统一构建内存索引，
+/// 适合首次导入大量不重复的 key，导入期间持有写锁，其他线程的写入会被阻塞，读取看不到导入中的数据
+/// 导入期间不会触发合并，数据文件的数量达到 max_data_files 时写入返回 TooManyDataFiles
+pub struct BulkLoad<'a> {
+    engine: &'a Engine,
+    entries: Vec<(Vec<u8>, LogRecordPos)>, // 已经写入的数据的位置，结束时写入索引
+    _write_guards: Vec<MutexGuard<'a, ()>>,
+}
+
+impl Engine {
+    /// 开始批量导入，调用 finish 结束导入，没有调用 finish 时会在 drop 时构建索引
+    pub fn bulk_load(&self) -> Result<BulkLoad<'_>> {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        let write_guards = self.lock_all_shards();
+        self.bulk_loading.store(true, Ordering::SeqCst);
+        Ok(BulkLoad {
+            engine: self,
+            entries: Vec::new(),
+            _write_guards: write_guards,
+        })
+    }
+}
+
+impl BulkLoad<'_> {
+    /// 写入一条数据，同一个 key 写入多次时以最后一次为准
+    pub fn put(&mut self, key: Bytes, value: Bytes) -> Result<()> {
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        let version = self.engine.next_version(&key);
+        let pos = self
+            .engine
+            .append_put_record(&key, &value, version, false)?;
+        self.entries.push((key.to_vec(), pos));
+        Ok(())
+    }
+
+    /// 结束导入，构建内存索引，开启 sync_writes 时持久化数据，返回导入的数据条数
+    pub fn finish(mut self) -> Result<usize> {
+        let entries = mem::take(&mut self.entries);
+        let count = entries.len();
+        self.engine.bulk_put_index(entries)?;
+        if self.engine.options.sync_writes {
+            self.engine.sync()?;
+        }
+        Ok(count)
+    }
+}
+
+impl Drop for BulkLoad<'_> {
+    // 没有调用 finish 时，已经写入数据文件的数据同样写入索引，和重新打开数据库之后的状态保持一致
+    fn drop(&mut self) {
+        if !self.entries.is_empty() {
+            let _ = self.engine.bulk_put_index(mem::take(&mut self.entries));
+        }
+        self.engine.bulk_loading.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        options::{IteratorOptions, Options},
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_bulk_load() {
+        let opts1 = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-bulk-load"),
+            data_file_size: 64 * 1024,
+            ..Default::default()
+        };
+        let engine1 = Engine::open(opts1.clone()).expect("failed to open engine");
+
+        let mut opts2 = opts1.clone();
+        opts2.dir_path = PathBuf::from("/tmp/bitcask-rs-bulk-load-put");
+        let engine2 = Engine::open(opts2.clone()).expect("failed to open engine");
+
+        // 1.批量导入和逐条写入同样的数据，包括重复的 key
+        let mut bulk = engine1.bulk_load().unwrap();
+        for i in 0..2000 {
+            let res1 = bulk.put(get_test_key(i % 1500), get_test_value(i));
+            assert!(res1.is_ok());
+            let res2 = engine2.put(get_test_key(i % 1500), get_test_value(i));
+            assert!(res2.is_ok());
+        }
+        let res3 = bulk.put(Bytes::new(), get_test_value(1));
+        assert_eq!(Errors::KeyIsEmpty, res3.err().unwrap());
+        assert_eq!(2000, bulk.finish().unwrap());
+
+        let collect = |engine: &Engine| {
+            let mut items = Vec::new();
+            let iter = engine.iter(IteratorOptions::default());
+            while let Some(item) = iter.next() {
+                items.push(item);
+            }
+            items
+        };
+        let items1 = collect(&engine1);
+        assert_eq!(1500, items1.len());
+        assert_eq!(collect(&engine2), items1);
+        assert_eq!(
+            get_test_value(1999),
+            engine1.get(get_test_key(499)).unwrap()
+        );
+
+        // 2.没有调用 finish 时 drop 之后同样可以读取到数据
+        {
+            let mut bulk = engine1.bulk_load().unwrap();
+            let res4 = bulk.put(Bytes::from("dropped"), Bytes::from("value"));
+            assert!(res4.is_ok());
+        }
+        assert_eq!(
+            Bytes::from("value"),
+            engine1.get(Bytes::from("dropped")).unwrap()
+        );
+        std::mem::drop(engine1);
+
+        // 3.重新打开之后数据都在
+        let engine3 = Engine::open(opts1.clone()).expect("failed to open engine");
+        assert_eq!(1501, engine3.list_keys().unwrap().len());
+        assert_eq!(
+            get_test_value(1999),
+            engine3.get(get_test_key(499)).unwrap()
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts1.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(opts2.clone().dir_path).expect("failed to remove path");
+    }
+}
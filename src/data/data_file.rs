@@ -1,42 +1,247 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    iter,
+    path::PathBuf,
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use bytes::{Buf, BytesMut};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use prost::bytes;
-use prost::{decode_length_delimiter, length_delimiter_len};
+use prost::{
+    decode_length_delimiter,
+    encoding::{decode_varint, encoded_len_varint},
+    length_delimiter_len,
+};
 
 use crate::{
     errors::{Errors, Result},
-    fio::{self, new_io_manager},
+    fio::{
+        buffered_io::BufferedIO, empty_io::EmptyIO, io_cache::IOManagerCache, new_io_manager,
+        new_read_only_io_manager, IOManager,
+    },
+    options::ChecksumKind,
 };
 
 use super::log_record::{
-    self, max_log_record_header_size, LogRecord, LogRecordType, ReadLogRecord,
+    max_log_record_header_size, LogRecord, LogRecordPos, LogRecordType, ReadLogRecord,
+    PADDING_FLAG, VERSION_FLAG,
 };
 
 pub const DATA_FILE_NAME_SUFFIX: &str = ".data";
+
+// footer 的 value 部分，8 字节的记录数量和 4 字节的 crc32
+const FOOTER_VALUE_SIZE: usize = 12;
+/// footer 编码后的长度，type、key size 和 value size 各 1 字节，最后是 4 字节的 crc
+pub(crate) const FOOTER_SIZE: u64 = 3 + FOOTER_VALUE_SIZE as u64 + 4;
+// 整体读取文件时每次读取的数据大小
+const SCAN_CHUNK_SIZE: u64 = 64 * 1024;
+
 /// 数据文件
 pub struct DataFile {
-    file_id: Arc<RwLock<u32>>,           // 数据文件id
-    write_off: Arc<RwLock<u64>>,         // 当前写偏移，记录数据文件写到哪个位置了
-    io_manager: Box<dyn fio::IOManager>, // io管理接口
+    file_id: Arc<RwLock<u64>>,                   // 数据文件id
+    write_off: Arc<RwLock<u64>>,                 // 当前写偏移，记录数据文件写到哪个位置了
+    size: RwLock<Option<u64>>,                   // 文件大小，第一次读取时获取，之后写入和截断时更新
+    file_name: PathBuf,                          // 数据文件的路径
+    io: DataFileIO,                              // io管理接口
+    write_buf: RwLock<Vec<u8>>,                  // 写缓冲区，存放还没有写入 io管理接口的数据
+    write_buffer_size: usize,                    // 写缓冲区大小，为 0 时不进行缓冲
+    preallocated: bool, // 是否预分配了文件空间，预分配时文件大小和 write_off 不一致
+    read_ahead: Mutex<ReadAhead>, // 预读缓冲区，顺序读取时一次从文件中读取一大块数据
+    digest: Mutex<Option<Digest>>, // 文件中已经写入的数据的摘要，不知道之前写入的数据时为 None，不写入 footer
+    predecessor_sync: Mutex<Option<SyncHandle>>, // 上一个数据文件在后台的持久化，持久化这个文件之前需要先等待完成
+    #[cfg(test)]
+    io_read_count: AtomicUsize,  // 调用 io管理接口读取数据的次数，只在测试中使用
+    #[cfg(test)]
+    pub(crate) sync_count: AtomicUsize, // 持久化的次数，只在测试中使用
+}
+
+// 数据文件的 io管理接口，旧的数据文件可以通过文件句柄缓存按需打开
+enum DataFileIO {
+    Owned(Arc<dyn IOManager>),   // 一直保持打开
+    Cached(Arc<IOManagerCache>), // 访问时从缓存中获取，长时间不访问时会被关闭
+}
+
+// 预读缓冲区，保存文件中从 offset 开始的一段数据
+#[derive(Default)]
+struct ReadAhead {
+    size: usize, // 每次预读的大小，为 0 时不进行预读
+    offset: u64, // 缓冲区中的数据在文件中的起始位置
+    buf: Vec<u8>,
+}
+
+/// 在后台持久化数据文件的线程
+pub type SyncHandle = JoinHandle<Result<()>>;
+
+// 数据文件中已经写入的数据的摘要，写入 footer 时使用
+#[derive(Default)]
+struct Digest {
+    hasher: crc32fast::Hasher, // 文件中所有数据的 crc32，包括之前写入的 footer
+    records: u64,              // 写入的记录数量，不包括 footer
+    footer_end: u64,           // 最后一个 footer 的结束位置，没有写入过 footer 时为 0
 }
 
 impl DataFile {
     // 创建或打开一个新的数据文件
-    pub fn new(dir_path: PathBuf, file_id: u32) -> Result<DataFile> {
+    pub fn new(dir_path: PathBuf, file_id: u64) -> Result<DataFile> {
         // 根据 path 和 id 构造出完整的文件名称
         let file_name = get_data_file_name(dir_path, file_id);
         // 初始化 IOManager
-        let io_manager = new_io_manager(file_name)?;
-        //
+        let io_manager = new_io_manager(file_name.clone())?;
+        Self::with_io_manager(file_name, file_id, Arc::new(io_manager))
+    }
+
+    /// 以只读方式打开已有的数据文件，不会创建文件，只读模式下加载数据文件时使用
+    pub fn open_read_only(dir_path: PathBuf, file_id: u64) -> Result<DataFile> {
+        let file_name = get_data_file_name(dir_path, file_id);
+        let io_manager = new_read_only_io_manager(file_name.clone())?;
+        Self::with_io_manager(file_name, file_id, Arc::new(io_manager))
+    }
+
+    fn with_io_manager(
+        file_name: PathBuf,
+        file_id: u64,
+        io_manager: Arc<dyn IOManager>,
+    ) -> Result<DataFile> {
+        // 新创建的文件从头开始计算摘要，已有数据的文件由 resume_digest 恢复
+        let digest = (io_manager.size()? == 0).then(Digest::default);
         Ok(DataFile {
             file_id: Arc::new(RwLock::new(file_id)),
             write_off: Arc::new(RwLock::new(0)),
-            io_manager: Box::new(io_manager),
+            size: RwLock::new(None),
+            file_name,
+            io: DataFileIO::Owned(io_manager),
+            write_buf: RwLock::new(Vec::new()),
+            write_buffer_size: 0,
+            preallocated: false,
+            read_ahead: Mutex::new(ReadAhead::default()),
+            digest: Mutex::new(digest),
+            predecessor_sync: Mutex::new(None),
+            #[cfg(test)]
+            io_read_count: AtomicUsize::new(0),
+            #[cfg(test)]
+            sync_count: AtomicUsize::new(0),
         })
     }
 
+    /// 创建一个不对应磁盘上的文件的空数据文件，不能写入，只读模式下没有数据文件时使用
+    pub fn new_empty(dir_path: PathBuf, file_id: u64) -> DataFile {
+        DataFile {
+            file_id: Arc::new(RwLock::new(file_id)),
+            write_off: Arc::new(RwLock::new(0)),
+            size: RwLock::new(None),
+            file_name: get_data_file_name(dir_path, file_id),
+            io: DataFileIO::Owned(Arc::new(EmptyIO)),
+            write_buf: RwLock::new(Vec::new()),
+            write_buffer_size: 0,
+            preallocated: false,
+            read_ahead: Mutex::new(ReadAhead::default()),
+            digest: Mutex::new(None),
+            predecessor_sync: Mutex::new(None),
+            #[cfg(test)]
+            io_read_count: AtomicUsize::new(0),
+            #[cfg(test)]
+            sync_count: AtomicUsize::new(0),
+        }
+    }
+
+    /// 打开一个旧的数据文件，文件句柄由 io_cache 管理，读取时才会打开文件
+    pub fn new_cached(dir_path: PathBuf, file_id: u64, io_cache: Arc<IOManagerCache>) -> DataFile {
+        DataFile {
+            file_id: Arc::new(RwLock::new(file_id)),
+            write_off: Arc::new(RwLock::new(0)),
+            size: RwLock::new(None),
+            file_name: get_data_file_name(dir_path, file_id),
+            io: DataFileIO::Cached(io_cache),
+            write_buf: RwLock::new(Vec::new()),
+            write_buffer_size: 0,
+            preallocated: false,
+            read_ahead: Mutex::new(ReadAhead::default()),
+            digest: Mutex::new(None),
+            predecessor_sync: Mutex::new(None),
+            #[cfg(test)]
+            io_read_count: AtomicUsize::new(0),
+            #[cfg(test)]
+            sync_count: AtomicUsize::new(0),
+        }
+    }
+
+    /// 关闭文件句柄，之后通过 io_cache 按需打开，只能用于不会再写入的旧的数据文件
+    /// 调用之前需要先持久化数据文件，并截断预分配的空间
+    pub fn use_io_cache(&mut self, io_cache: Arc<IOManagerCache>) {
+        self.preallocated = false;
+        self.io = DataFileIO::Cached(io_cache);
+    }
+
+    // 获取 io管理接口
+    fn io_manager(&self) -> Result<Arc<dyn IOManager>> {
+        match &self.io {
+            DataFileIO::Owned(io_manager) => Ok(io_manager.clone()),
+            DataFileIO::Cached(io_cache) => io_cache.get(&self.file_name),
+        }
+    }
+
+    /// 预分配文件空间，文件大小不足 size 时用 0 填充，之后的数据写入到 write_off 的位置
+    /// 末尾填充的 0 在读取时会被当作文件的结尾
+    pub fn preallocate(&mut self, size: u64) -> Result<()> {
+        if self.io_manager()?.size()? < size {
+            self.set_len(size)?;
+        }
+        self.preallocated = true;
+        Ok(())
+    }
+
+    /// 将预分配的文件截断到 write_off，去掉末尾没有使用的空间
+    pub fn truncate_preallocated(&self) -> Result<()> {
+        if !self.preallocated {
+            return Ok(());
+        }
+        self.flush_write_buf(&mut self.write_buf.write())?;
+        self.set_len(self.get_write_off())
+    }
+
+    /// 将文件截断到 write_off，去掉末尾不完整的记录，之后的数据从 write_off 开始写入
+    pub fn truncate_to_write_off(&self) -> Result<()> {
+        if self.io_manager()?.size()? > self.get_write_off() {
+            self.set_len(self.get_write_off())?;
+        }
+        Ok(())
+    }
+
+    // 修改文件的大小，同时更新记录的文件大小
+    fn set_len(&self, size: u64) -> Result<()> {
+        let mut cached = self.size.write();
+        self.io_manager()?.set_len(size)?;
+        *cached = Some(size);
+        Ok(())
+    }
+
+    /// 设置 io管理接口的写缓冲区大小，为 0 时不缓冲，需要在写入数据之前调用
+    /// 和写缓冲区不同，读取之前会先把缓冲区中的数据写入文件，flush 时只写入操作系统而不持久化
+    pub fn set_io_buffer_size(&mut self, size: usize) {
+        if let (DataFileIO::Owned(io_manager), true) = (&self.io, size > 0) {
+            self.io = DataFileIO::Owned(Arc::new(BufferedIO::new(io_manager.clone(), size)));
+        }
+    }
+
+    /// 设置写缓冲区大小，写入的数据会先攒在缓冲区中，缓冲区满了或者 sync 时才写入文件
+    pub fn set_write_buffer_size(&mut self, size: usize) {
+        self.write_buffer_size = size;
+    }
+
+    /// 设置预读的大小，之后从文件中读取数据时一次读取 size 大小，后续的读取直接从缓冲区中返回
+    /// 只适合从头到尾顺序读取文件，读取完毕后需要设置为 0 关闭预读并释放缓冲区
+    pub fn set_read_ahead(&self, size: usize) {
+        let mut read_ahead = self.read_ahead.lock();
+        *read_ahead = ReadAhead {
+            size,
+            ..Default::default()
+        };
+    }
+
     pub fn get_write_off(&self) -> u64 {
         let read_guard = self.write_off.read();
         *read_guard
@@ -47,84 +252,529 @@ impl DataFile {
         *write_guard = offset;
     }
 
-    pub fn get_file_id(&self) -> u32 {
+    pub fn get_file_id(&self) -> u64 {
         let read_guard = self.file_id.read();
         *read_guard
     }
 
     /// 根据 offset 从数据文件中读取 LogRecord
     pub fn read_log_record(&self, offset: u64) -> Result<ReadLogRecord> {
+        let (read_record, crc) = self.read_log_record_with_crc(offset)?;
+        if crc != read_record.crc {
+            return Err(Errors::InvalidLogRecordCrc);
+        }
+        Ok(read_record)
+    }
+
+    /// 从文件开头依次读取所有的记录以及记录的位置，包括删除记录和 footer，读取到文件末尾时结束
+    /// 记录损坏时返回错误，之后不再继续读取
+    pub fn records(&self) -> impl Iterator<Item = Result<(LogRecordPos, LogRecord)>> + '_ {
+        let mut offset = 0;
+        let mut done = false;
+        iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            match self.read_log_record(offset) {
+                Ok(read_record) => {
+                    let record = read_record.record;
+                    let pos = LogRecordPos {
+                        file_id: self.get_file_id(),
+                        offset,
+                        inline: None,
+                        version: record.version,
+                    };
+                    offset += read_record.size as u64;
+                    Some(Ok((pos, record)))
+                }
+                Err(Errors::ReadDataFileEOF) => {
+                    done = true;
+                    None
+                }
+                Err(e) => {
+                    done = true;
+                    Some(Err(e))
+                }
+            }
+        })
+    }
+
+    /// 根据 offset 从数据文件中读取 LogRecord 以及文件中存储的 crc 值，不对 crc 进行校验
+    pub fn read_log_record_with_crc(&self, offset: u64) -> Result<(ReadLogRecord, u32)> {
         // 先读取出 header 部分的数据
         let mut header_buf = BytesMut::zeroed(max_log_record_header_size());
 
-        self.io_manager.read(&mut header_buf, offset)?;
-        println!("offset{}", offset);
+        let write_buf = self.write_buf.read();
+        let flushed_size = self.flushed_size(&write_buf)?;
+        self.read_at(&write_buf, flushed_size, &mut header_buf, offset)?;
+
         // 取出 type，在第一个字节
         let rec_type = header_buf.get_u8();
-        // println!("rec_type{}", rec_type);
 
-        // 取出 key 和 value 的长度
-        let key_size = decode_length_delimiter(&mut header_buf).unwrap();
-        let value_size = decode_length_delimiter(&mut header_buf).unwrap();
+        // 取出 key 和 value 的长度，长度字段损坏时无法解码
+        let key_size = match decode_length_delimiter(&mut header_buf) {
+            Ok(size) => size,
+            Err(_) => return Err(Errors::InvalidLogRecordCrc),
+        };
+        let value_size = match decode_length_delimiter(&mut header_buf) {
+            Ok(size) => size,
+            Err(_) => return Err(Errors::InvalidLogRecordCrc),
+        };
+        // 带有版本的记录，header 中记录了 key 的版本
+        let version = match rec_type & VERSION_FLAG {
+            0 => None,
+            _ => match decode_varint(&mut header_buf) {
+                Ok(version) => Some(version),
+                Err(_) => return Err(Errors::InvalidLogRecordCrc),
+            },
+        };
+        // 对齐的记录末尾有填充，header 中记录了填充的长度
+        let padding = match rec_type & PADDING_FLAG {
+            0 => None,
+            _ => match decode_length_delimiter(&mut header_buf) {
+                Ok(size) => Some(size),
+                Err(_) => return Err(Errors::InvalidLogRecordCrc),
+            },
+        };
 
         // 如果 key 和 value 均为空，则说明读取到了文件的末尾，返回
         if key_size == 0 && value_size == 0 {
             return Err(Errors::ReadDataFileEOF);
         }
 
+        // type 字段损坏，低 3 位是记录类型，第 4 位是填充标志，第 5、6 位是校验算法，第 7 位是版本标志
+        let checksum = match ChecksumKind::from_u8((rec_type & !VERSION_FLAG) >> 4) {
+            Some(checksum) => checksum,
+            None => return Err(Errors::InvalidLogRecordCrc),
+        };
+        let rec_type = match LogRecordType::from_u8(rec_type & 0x07) {
+            Some(rec_type) => rec_type,
+            None => return Err(Errors::InvalidLogRecordCrc),
+        };
+
         // 获取实际的 header 大小
-        let actual_header_size =
-            length_delimiter_len(key_size) + length_delimiter_len(value_size) + 1;
+        let actual_header_size = length_delimiter_len(key_size)
+            + length_delimiter_len(value_size)
+            + version.map_or(0, encoded_len_varint)
+            + padding.map_or(0, length_delimiter_len)
+            + 1;
+        let padding = padding.unwrap_or(0);
 
-        // 读取实际的 key 和 value，最后的四个字节是 crc 校验值
-        let mut kv_buf: BytesMut = BytesMut::zeroed(key_size + value_size + 4);
-        self.io_manager
-            .read(&mut kv_buf, offset + actual_header_size as u64)?;
+        // 写入时长度都不会超过 u32，超过说明 header 损坏，计算记录的长度时也不能溢出
+        let max_size = u32::MAX as usize;
+        if key_size > max_size || value_size > max_size || padding > max_size {
+            return Err(Errors::InvalidLogRecordCrc);
+        }
+        let record_size = [key_size, value_size, 4, padding]
+            .into_iter()
+            .try_fold(actual_header_size as u64, |acc, size| {
+                acc.checked_add(size as u64)
+            });
+
+        // 记录的长度不能超过文件中剩余的字节数，避免损坏的 header 导致分配过大的内存
+        let record_size = match record_size {
+            Some(size) => match offset.checked_add(size) {
+                Some(end) if end <= flushed_size + write_buf.len() as u64 => size,
+                _ => return Err(Errors::InvalidLogRecordCrc),
+            },
+            None => return Err(Errors::InvalidLogRecordCrc),
+        };
+
+        // 读取完整的记录，包括 header、key、value 以及最后四个字节的 crc 校验值，不包括末尾的填充
+        let body_size = actual_header_size + key_size + value_size;
+        let mut rec_buf: BytesMut = BytesMut::zeroed(body_size + 4);
+        self.read_at(&write_buf, flushed_size, &mut rec_buf, offset)?;
 
         // 构造 LogRecord
         let log_record = LogRecord {
-            key: kv_buf.get(..key_size).unwrap().to_vec(),
-            value: kv_buf.get(key_size..kv_buf.len() - 4).unwrap().to_vec(),
-            rec_type: LogRecordType::from_u8(rec_type),
+            key: rec_buf[actual_header_size..actual_header_size + key_size].to_vec(),
+            value: rec_buf[actual_header_size + key_size..body_size].to_vec(),
+            rec_type,
+            checksum,
+            version: version.unwrap_or(0),
         };
 
+        // 校验值根据读取到的完整 header 计算，填充标志和填充的长度损坏时也能发现
+        let actual_crc = checksum.checksum(&rec_buf[..body_size]);
+
         // 向前移动到最后的 4 个字节，就是 crc 的值
-        kv_buf.advance(key_size + value_size);
+        rec_buf.advance(body_size);
+        let crc = rec_buf.get_u32();
 
-        if kv_buf.get_u32() != log_record.get_crc() {
-            return Err(Errors::InvalidLogRecordCrc);
+        // 构造结果并且返回
+        Ok((
+            ReadLogRecord {
+                record: log_record,
+                size: record_size as usize,
+                crc: actual_crc,
+            },
+            crc,
+        ))
+    }
+
+    // 已经写入文件的数据大小
+    // 写缓冲区中有数据时，说明是活跃文件，缓冲区之前的数据就是已经写入文件的数据
+    // 否则使用记录的文件大小，只在第一次读取时从文件中获取，避免每次读取都查询文件的元数据
+    fn flushed_size(&self, write_buf: &[u8]) -> Result<u64> {
+        if !write_buf.is_empty() {
+            return Ok(self.get_write_off() - write_buf.len() as u64);
         }
+        if let Some(size) = *self.size.read() {
+            return Ok(size);
+        }
+        let mut cached = self.size.write();
+        let size = match *cached {
+            Some(size) => size,
+            None => self.io_manager()?.size()?,
+        };
+        *cached = Some(size);
+        Ok(size)
+    }
 
-        // 构造结果并且返回
-        Ok(ReadLogRecord {
-            record: log_record,
-            size: actual_header_size + key_size + value_size + 4,
-        })
+    // 从 offset 处读取数据，flushed_size 之前的部分从文件中读取，之后的部分从写缓冲区中读取
+    fn read_at(
+        &self,
+        write_buf: &[u8],
+        flushed_size: u64,
+        buf: &mut [u8],
+        offset: u64,
+    ) -> Result<()> {
+        let mut n = 0;
+        if offset < flushed_size {
+            let len = buf.len().min((flushed_size - offset) as usize);
+            self.read_file(&mut buf[..len], flushed_size, offset)?;
+            n = len;
+        }
+
+        if n == buf.len() {
+            return Ok(());
+        }
+
+        let buf_offset = (offset + n as u64 - flushed_size) as usize;
+        if buf_offset < write_buf.len() {
+            let len = (buf.len() - n).min(write_buf.len() - buf_offset);
+            buf[n..n + len].copy_from_slice(&write_buf[buf_offset..buf_offset + len]);
+        }
+        Ok(())
     }
 
+    // 从文件的 offset 处读取数据，开启预读时优先从预读缓冲区中读取
+    fn read_file(&self, buf: &mut [u8], flushed_size: u64, offset: u64) -> Result<()> {
+        let mut read_ahead = self.read_ahead.lock();
+        if read_ahead.size == 0 {
+            self.io_read(buf, offset)?;
+            return Ok(());
+        }
+
+        // 缓冲区中没有完整的数据，从 offset 处重新预读一块数据
+        let end = offset + buf.len() as u64;
+        if offset < read_ahead.offset || end > read_ahead.offset + read_ahead.buf.len() as u64 {
+            let len = (read_ahead.size.max(buf.len()) as u64).min(flushed_size - offset);
+            let mut chunk = std::mem::take(&mut read_ahead.buf);
+            chunk.resize(len as usize, 0);
+            let n = self.io_read(&mut chunk, offset)?;
+            chunk.truncate(n);
+            read_ahead.offset = offset;
+            read_ahead.buf = chunk;
+        }
+
+        let start = (offset - read_ahead.offset) as usize;
+        let len = buf.len().min(read_ahead.buf.len() - start);
+        buf[..len].copy_from_slice(&read_ahead.buf[start..start + len]);
+        Ok(())
+    }
+
+    fn io_read(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        #[cfg(test)]
+        self.io_read_count.fetch_add(1, Ordering::SeqCst);
+        self.io_manager()?.read(buf, offset)
+    }
+
+    /// 写入一条编码后的记录
     pub fn write(&self, buf: &[u8]) -> Result<usize> {
-        let n_bytes = self.io_manager.write(buf)?;
+        self.append(buf, false)
+    }
+
+    // 追加写入数据并更新文件的摘要
+    fn append(&self, buf: &[u8], is_footer: bool) -> Result<usize> {
+        let n_bytes = match self.write_buffer_size {
+            0 => self.io_write(buf, self.get_write_off())?,
+            size => {
+                let mut write_buf = self.write_buf.write();
+                // 缓冲区放不下时先把已有的数据写入文件
+                if write_buf.len() + buf.len() > size {
+                    self.flush_write_buf(&mut write_buf)?;
+                }
+                if buf.len() >= size {
+                    self.write_all(buf, self.get_write_off())?;
+                } else {
+                    write_buf.extend_from_slice(buf);
+                }
+                buf.len()
+            }
+        };
         // 更新 write_off 字段
         let mut write_off = self.write_off.write();
         *write_off += n_bytes as u64;
 
+        if let Some(digest) = self.digest.lock().as_mut() {
+            digest.hasher.update(&buf[..n_bytes]);
+            match is_footer {
+                true => digest.footer_end = *write_off,
+                false => digest.records += 1,
+            }
+        }
+
         Ok(n_bytes)
     }
 
+    /// 在文件末尾写入 footer，记录之前写入的记录数量和文件中所有数据的 crc32，verify 时可以整体校验文件
+    /// 不知道文件中之前写入的数据，或者上一个 footer 之后没有写入新的数据时不写入
+    pub fn write_footer(&self) -> Result<()> {
+        let footer = match self.digest.lock().as_ref() {
+            Some(digest) if self.get_write_off() > digest.footer_end => {
+                encode_footer(digest.records, digest.hasher.clone().finalize())
+            }
+            _ => return Ok(()),
+        };
+        if self.append(&footer, true)? != footer.len() {
+            return Err(Errors::FailedWriteToDataFile);
+        }
+        Ok(())
+    }
+
+    /// 重新打开活跃文件之后，根据 write_off 之前的 footer 恢复文件的摘要，之后写入 footer 时可以继续计算
+    /// 文件末尾不是 footer 时无法得到之前数据的摘要，之后不再写入 footer
+    pub fn resume_digest(&self) -> Result<()> {
+        let end = self.get_write_off();
+        let digest = match end {
+            0 => Some(Digest::default()),
+            _ => self.read_footer(end)?.map(|(records, crc)| {
+                let mut hasher = crc32fast::Hasher::new_with_initial_len(crc, end - FOOTER_SIZE);
+                hasher.update(&encode_footer(records, crc));
+                Digest {
+                    hasher,
+                    records,
+                    footer_end: end,
+                }
+            }),
+        };
+        *self.digest.lock() = digest;
+        Ok(())
+    }
+
+    /// 使用文件末尾的 footer 整体校验文件中的数据，校验通过时返回文件中的记录数量，不包括 footer
+    /// 文件末尾不是 footer，或者数据和 footer 中的校验值不一致时返回 None，需要逐条校验记录
+    pub fn verify_footer(&self) -> Result<Option<u64>> {
+        // 预分配的文件末尾是没有使用的空间，数据在 write_off 处结束
+        let end = match self.preallocated {
+            true => self.get_write_off(),
+            false => self.io_manager()?.size()?.max(self.get_write_off()),
+        };
+        let (records, crc) = match self.read_footer(end)? {
+            Some(footer) => footer,
+            None => return Ok(None),
+        };
+        match self.checksum_prefix(end - FOOTER_SIZE)? == crc {
+            true => Ok(Some(records)),
+            false => Ok(None),
+        }
+    }
+
+    // 读取在 end 处结束的 footer，返回其中的记录数量和校验值，不是 footer 时返回 None
+    fn read_footer(&self, end: u64) -> Result<Option<(u64, u32)>> {
+        if end < FOOTER_SIZE {
+            return Ok(None);
+        }
+        let read_record = match self.read_log_record(end - FOOTER_SIZE) {
+            Ok(read_record) => read_record,
+            Err(Errors::ReadDataFileEOF) | Err(Errors::InvalidLogRecordCrc) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let record = read_record.record;
+        if record.rec_type != LogRecordType::FOOTER
+            || read_record.size as u64 != FOOTER_SIZE
+            || !record.key.is_empty()
+            || record.value.len() != FOOTER_VALUE_SIZE
+        {
+            return Ok(None);
+        }
+        let (records, crc) = record.value.split_at(8);
+        Ok(Some((
+            u64::from_be_bytes(records.try_into().unwrap()),
+            u32::from_be_bytes(crc.try_into().unwrap()),
+        )))
+    }
+
+    /// 文件中 offset 之后是否有不为 0 的数据，预分配时填充的 0 不算作数据
+    pub fn has_data_after(&self, offset: u64) -> Result<bool> {
+        let io_manager = self.io_manager()?;
+        let size = io_manager.size()?;
+        let mut buf = vec![0u8; SCAN_CHUNK_SIZE.min(size.saturating_sub(offset)) as usize];
+        let mut offset = offset;
+        while offset < size {
+            let n = SCAN_CHUNK_SIZE.min(size - offset) as usize;
+            let n = io_manager.read(&mut buf[..n], offset)?;
+            if n == 0 {
+                break;
+            }
+            if buf[..n].iter().any(|b| *b != 0) {
+                return Ok(true);
+            }
+            offset += n as u64;
+        }
+        Ok(false)
+    }
+
+    // 计算文件中 len 之前的数据的 crc32
+    fn checksum_prefix(&self, len: u64) -> Result<u32> {
+        let write_buf = self.write_buf.read();
+        let flushed_size = self.flushed_size(&write_buf)?;
+
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0u8; SCAN_CHUNK_SIZE.min(len) as usize];
+        let mut offset = 0;
+        while offset < len {
+            let n = SCAN_CHUNK_SIZE.min(len - offset) as usize;
+            self.read_at(&write_buf, flushed_size, &mut buf[..n], offset)?;
+            hasher.update(&buf[..n]);
+            offset += n as u64;
+        }
+        Ok(hasher.finalize())
+    }
+
+    /// 将写缓冲区中的数据写入操作系统，不进行持久化，进程崩溃时数据不会丢失，但机器掉电时可能丢失
+    pub fn flush(&self) -> Result<()> {
+        self.flush_write_buf(&mut self.write_buf.write())?;
+        self.io_manager()?.flush()
+    }
+
+    /// 持久化数据文件，之前的数据文件在后台持久化时先等待其完成，保证数据按照写入的顺序持久化
     pub fn sync(&self) -> Result<()> {
-        self.io_manager.sync()
+        #[cfg(test)]
+        self.sync_count.fetch_add(1, Ordering::SeqCst);
+        self.wait_predecessor_sync()?;
+        self.flush_write_buf(&mut self.write_buf.write())?;
+        self.io_manager()?.sync()
+    }
+
+    /// 在后台线程中持久化数据文件，之前的数据文件的后台持久化完成之后才会开始，持久化成功之后调用 on_synced
+    /// 调用之后文件不能再写入，返回的线程需要交给下一个数据文件的 set_predecessor_sync
+    pub fn sync_in_background(
+        &self,
+        on_synced: impl FnOnce() + Send + 'static,
+    ) -> Result<SyncHandle> {
+        self.flush_write_buf(&mut self.write_buf.write())?;
+        let io_manager = self.io_manager()?;
+        let predecessor = self.predecessor_sync.lock().take();
+        Ok(thread::spawn(move || {
+            if let Some(predecessor) = predecessor {
+                join_sync(predecessor)?;
+            }
+            io_manager.sync()?;
+            on_synced();
+            Ok(())
+        }))
+    }
+
+    /// 设置上一个数据文件在后台的持久化
+    pub fn set_predecessor_sync(&self, handle: SyncHandle) {
+        *self.predecessor_sync.lock() = Some(handle);
+    }
+
+    // 等待上一个数据文件在后台的持久化完成
+    fn wait_predecessor_sync(&self) -> Result<()> {
+        match self.predecessor_sync.lock().take() {
+            Some(handle) => join_sync(handle),
+            None => Ok(()),
+        }
+    }
+
+    // 将写缓冲区中的数据写入文件
+    fn flush_write_buf(&self, write_buf: &mut Vec<u8>) -> Result<()> {
+        if !write_buf.is_empty() {
+            let offset = self.get_write_off() - write_buf.len() as u64;
+            self.write_all(write_buf, offset)?;
+            write_buf.clear();
+        }
+        Ok(())
+    }
+
+    fn write_all(&self, mut buf: &[u8], mut offset: u64) -> Result<()> {
+        while !buf.is_empty() {
+            let n = self.io_write(buf, offset)?;
+            if n == 0 {
+                return Err(Errors::FailedWriteToDataFile);
+            }
+            buf = &buf[n..];
+            offset += n as u64;
+        }
+        Ok(())
+    }
+
+    // 将数据写入到文件的 offset 处，预分配的文件需要指定写入位置，否则直接追加写入
+    fn io_write(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        let n = match self.preallocated {
+            true => self.io_manager()?.write_at(buf, offset)?,
+            false => self.io_manager()?.write(buf)?,
+        };
+        // 还没有获取过文件大小时不需要更新，第一次读取时会从文件中获取
+        if let Some(size) = self.size.write().as_mut() {
+            *size = (*size).max(offset + n as u64);
+        }
+        Ok(n)
+    }
+
+    /// 获取数据文件在磁盘上的大小
+    pub fn file_size(&self) -> Result<u64> {
+        self.io_manager()?.size()
+    }
+}
+
+impl Drop for DataFile {
+    // 关闭数据文件时将写缓冲区中剩余的数据写入文件
+    // 预分配的文件同时截断末尾没有使用的空间
+    fn drop(&mut self) {
+        let _ = self.flush_write_buf(&mut self.write_buf.write());
+        let _ = self.truncate_preallocated();
+    }
+}
+
+// 等待后台持久化的线程结束并返回持久化的结果
+fn join_sync(handle: SyncHandle) -> Result<()> {
+    match handle.join() {
+        Ok(res) => res,
+        Err(_) => Err(Errors::FailedSyncDataFile),
     }
 }
 
+// 编码 footer，footer 固定使用 crc32 校验
+fn encode_footer(records: u64, crc: u32) -> Vec<u8> {
+    let mut value = Vec::with_capacity(FOOTER_VALUE_SIZE);
+    value.extend_from_slice(&records.to_be_bytes());
+    value.extend_from_slice(&crc.to_be_bytes());
+    LogRecord {
+        key: Vec::new(),
+        value,
+        rec_type: LogRecordType::FOOTER,
+        checksum: ChecksumKind::Crc32,
+        version: 0,
+    }
+    .encode()
+}
+
 /// 获取文件名称
-fn get_data_file_name(dir_path: PathBuf, file_id: u32) -> PathBuf {
+/// 文件 id 至少填充到 9 位，和之前使用 u32 作为文件 id 时的文件名保持一致，更大的 id 直接使用完整的数字
+pub(crate) fn get_data_file_name(dir_path: PathBuf, file_id: u64) -> PathBuf {
     let name = std::format!("{:09}", file_id) + DATA_FILE_NAME_SUFFIX;
     dir_path.join(name)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::data::{self, data_file};
+    use bytes::BufMut;
+    use prost::encode_length_delimiter;
 
     use super::*;
 
@@ -159,15 +809,15 @@ mod tests {
 
         let write_res1 = data_file1.write("aaa".as_bytes());
         assert!(write_res1.is_ok());
-        assert_eq!(write_res1.unwrap(), 3 as usize);
+        assert_eq!(write_res1.unwrap(), 3);
 
         let write_res2 = data_file1.write("bbb".as_bytes());
         assert!(write_res2.is_ok());
-        assert_eq!(write_res2.unwrap(), 3 as usize);
+        assert_eq!(write_res2.unwrap(), 3);
 
         let write_res3 = data_file1.write("ccc".as_bytes());
         assert!(write_res3.is_ok());
-        assert_eq!(write_res3.unwrap(), 3 as usize);
+        assert_eq!(write_res3.unwrap(), 3);
     }
 
     #[test]
@@ -182,6 +832,204 @@ mod tests {
         assert!(sync_res.is_ok());
     }
 
+    #[test]
+    fn test_data_file_size() {
+        let dir_path = std::env::temp_dir();
+        let data_file_res1 = DataFile::new(dir_path.clone(), 900);
+        assert!(data_file_res1.is_ok());
+        let data_file1 = data_file_res1.unwrap();
+        assert_eq!(0, data_file1.file_size().unwrap());
+
+        let write_res1 = data_file1.write("aaa".as_bytes());
+        assert!(write_res1.is_ok());
+        assert_eq!(3, data_file1.file_size().unwrap());
+
+        let remove_res = std::fs::remove_file(get_data_file_name(dir_path, 900));
+        assert!(remove_res.is_ok());
+    }
+
+    #[test]
+    fn test_data_file_write_buffer() {
+        let dir_path = std::env::temp_dir();
+        let data_file_res1 = DataFile::new(dir_path.clone(), 910);
+        assert!(data_file_res1.is_ok());
+        let mut data_file1 = data_file_res1.unwrap();
+        data_file1.set_write_buffer_size(64);
+
+        // 缓冲区较小，记录会分布在文件和缓冲区中
+        let mut offsets = Vec::new();
+        for i in 0..20 {
+            let enc = LogRecord {
+                key: format!("key-{}", i).into_bytes(),
+                value: format!("value-{}", i).into_bytes(),
+                rec_type: LogRecordType::NORMAL,
+                checksum: ChecksumKind::Crc32,
+                version: 0,
+            };
+            offsets.push(data_file1.get_write_off());
+            let write_res = data_file1.write(&enc.encode());
+            assert!(write_res.is_ok());
+        }
+        assert!(data_file1.file_size().unwrap() < data_file1.get_write_off());
+
+        // sync 之前所有的记录都能读取到
+        for (i, offset) in offsets.iter().enumerate() {
+            let read_res = data_file1.read_log_record(*offset);
+            assert!(read_res.is_ok());
+            let record = read_res.unwrap().record;
+            assert_eq!(format!("value-{}", i).into_bytes(), record.value);
+        }
+        let read_res = data_file1.read_log_record(data_file1.get_write_off());
+        assert_eq!(Errors::ReadDataFileEOF, read_res.err().unwrap());
+
+        // flush 之后缓冲区中的数据全部写入文件，但不进行持久化
+        let flush_res = data_file1.flush();
+        assert!(flush_res.is_ok());
+        assert_eq!(data_file1.get_write_off(), data_file1.file_size().unwrap());
+        assert_eq!(0, data_file1.sync_count.load(Ordering::SeqCst));
+
+        // sync 之后缓冲区中的数据全部写入文件
+        let write_res = data_file1.write("aaa".as_bytes());
+        assert!(write_res.is_ok());
+        let sync_res = data_file1.sync();
+        assert!(sync_res.is_ok());
+        assert_eq!(data_file1.get_write_off(), data_file1.file_size().unwrap());
+        assert_eq!(1, data_file1.sync_count.load(Ordering::SeqCst));
+
+        let remove_res = std::fs::remove_file(get_data_file_name(dir_path, 910));
+        assert!(remove_res.is_ok());
+    }
+
+    #[test]
+    fn test_data_file_read_ahead() {
+        let dir_path = std::env::temp_dir();
+        let data_file1 = DataFile::new(dir_path.clone(), 940).unwrap();
+
+        let mut offsets = Vec::new();
+        for i in 0..1000 {
+            let enc = LogRecord {
+                key: format!("key-{}", i).into_bytes(),
+                value: format!("value-{}", i).into_bytes(),
+                rec_type: LogRecordType::NORMAL,
+                checksum: ChecksumKind::Crc32,
+                version: 0,
+            };
+            offsets.push(data_file1.get_write_off());
+            let write_res = data_file1.write(&enc.encode());
+            assert!(write_res.is_ok());
+        }
+
+        // 顺序读取所有的记录，返回读取的次数
+        let scan = |data_file: &DataFile| {
+            let before = data_file.io_read_count.load(Ordering::SeqCst);
+            let mut offset = 0;
+            let mut i = 0;
+            loop {
+                match data_file.read_log_record(offset) {
+                    Ok(read_record) => {
+                        assert_eq!(offsets[i], offset);
+                        assert_eq!(
+                            format!("value-{}", i).into_bytes(),
+                            read_record.record.value
+                        );
+                        offset += read_record.size as u64;
+                        i += 1;
+                    }
+                    Err(e) => {
+                        assert_eq!(Errors::ReadDataFileEOF, e);
+                        break;
+                    }
+                }
+            }
+            assert_eq!(1000, i);
+            data_file.io_read_count.load(Ordering::SeqCst) - before
+        };
+
+        // 不开启预读时每条记录至少读取两次
+        let reads1 = scan(&data_file1);
+        assert!(reads1 >= 2000);
+
+        // 开启预读之后读取的次数大大减少
+        data_file1.set_read_ahead(4 * 1024);
+        let reads2 = scan(&data_file1);
+        assert!(reads2 < 20);
+
+        // 关闭预读之后仍然可以随机读取
+        data_file1.set_read_ahead(0);
+        let read_res = data_file1.read_log_record(offsets[500]);
+        assert_eq!(
+            "value-500".as_bytes().to_vec(),
+            read_res.unwrap().record.value
+        );
+
+        let remove_res = std::fs::remove_file(get_data_file_name(dir_path, 940));
+        assert!(remove_res.is_ok());
+    }
+
+    #[test]
+    fn test_data_file_checksum_kind() {
+        let dir_path = std::env::temp_dir();
+
+        // 每种校验算法写入的记录都能读取出来
+        let kinds = [
+            ChecksumKind::Crc32,
+            ChecksumKind::Crc32c,
+            ChecksumKind::XxHash64,
+        ];
+        for (i, kind) in kinds.iter().enumerate() {
+            let file_id = 920 + i as u64;
+            let data_file1 = DataFile::new(dir_path.clone(), file_id).unwrap();
+            let enc1 = LogRecord {
+                key: "name".as_bytes().to_vec(),
+                value: "bitcask-rs-kv".as_bytes().to_vec(),
+                rec_type: LogRecordType::NORMAL,
+                checksum: *kind,
+                version: 0,
+            };
+            let write_res1 = data_file1.write(&enc1.encode());
+            assert!(write_res1.is_ok());
+
+            let read_res1 = data_file1.read_log_record(0);
+            assert!(read_res1.is_ok());
+            let read_enc1 = read_res1.ok().unwrap().record;
+            assert_eq!(enc1.value, read_enc1.value);
+            assert_eq!(*kind, read_enc1.checksum);
+            assert_eq!(enc1.get_crc(), read_enc1.get_crc());
+
+            let remove_res = std::fs::remove_file(get_data_file_name(dir_path.clone(), file_id));
+            assert!(remove_res.is_ok());
+        }
+
+        // 记录中的算法标识被修改为其他算法，校验失败
+        let data_file2 = DataFile::new(dir_path.clone(), 930).unwrap();
+        let mut enc2 = LogRecord {
+            key: "name".as_bytes().to_vec(),
+            value: "bitcask-rs-kv".as_bytes().to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            checksum: ChecksumKind::Crc32c,
+            version: 0,
+        }
+        .encode();
+        enc2[0] = LogRecordType::NORMAL as u8 | (ChecksumKind::XxHash64 as u8) << 4;
+        let write_res2 = data_file2.write(&enc2);
+        assert!(write_res2.is_ok());
+        let read_res2 = data_file2.read_log_record(0);
+        assert_eq!(Errors::InvalidLogRecordCrc, read_res2.err().unwrap());
+
+        // 未知的算法标识
+        let data_file3 = DataFile::new(dir_path.clone(), 931).unwrap();
+        enc2[0] = LogRecordType::NORMAL as u8 | 0x0f << 4;
+        let write_res3 = data_file3.write(&enc2);
+        assert!(write_res3.is_ok());
+        let read_res3 = data_file3.read_log_record(0);
+        assert_eq!(Errors::InvalidLogRecordCrc, read_res3.err().unwrap());
+
+        for file_id in [930, 931] {
+            let remove_res = std::fs::remove_file(get_data_file_name(dir_path.clone(), file_id));
+            assert!(remove_res.is_ok());
+        }
+    }
+
     #[test]
     fn test_data_file_read_log_record() {
         let dir_path = std::env::temp_dir();
@@ -194,6 +1042,8 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: "bitcask-rs-kv".as_bytes().to_vec(),
             rec_type: LogRecordType::NORMAL,
+            checksum: ChecksumKind::Crc32,
+            version: 0,
         };
         let write_res1 = data_file1.write(&enc1.encode());
         assert!(write_res1.is_ok());
@@ -211,6 +1061,8 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: "new-value".as_bytes().to_vec(),
             rec_type: LogRecordType::NORMAL,
+            checksum: ChecksumKind::Crc32,
+            version: 0,
         };
         let write_res2 = data_file1.write(&enc2.encode());
         assert!(write_res2.is_ok());
@@ -227,6 +1079,8 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: Default::default(),
             rec_type: LogRecordType::DELETED,
+            checksum: ChecksumKind::Crc32,
+            version: 0,
         };
         let write_res3 = data_file1.write(&enc3.encode());
         assert!(write_res3.is_ok());
@@ -238,4 +1092,256 @@ mod tests {
         assert_eq!(enc3.value, read_enc3.value);
         assert_eq!(enc3.rec_type, read_enc3.rec_type);
     }
+
+    #[test]
+    fn test_data_file_footer() {
+        let dir_path = std::env::temp_dir();
+        let data_file1 = DataFile::new(dir_path.clone(), 950).unwrap();
+        for i in 0..10 {
+            let enc = LogRecord {
+                key: format!("key-{}", i).into_bytes(),
+                value: "bitcask-rs".as_bytes().to_vec(),
+                rec_type: LogRecordType::NORMAL,
+                checksum: ChecksumKind::Crc32,
+                version: 0,
+            };
+            let write_res = data_file1.write(&enc.encode());
+            assert!(write_res.is_ok());
+        }
+
+        // 1.没有 footer 时需要逐条校验
+        assert_eq!(None, data_file1.verify_footer().unwrap());
+
+        // 2.写入 footer 之后整体校验通过，读取记录时 footer 是单独的类型
+        let write_off = data_file1.get_write_off();
+        let footer_res = data_file1.write_footer();
+        assert!(footer_res.is_ok());
+        assert_eq!(write_off + FOOTER_SIZE, data_file1.get_write_off());
+        assert_eq!(Some(10), data_file1.verify_footer().unwrap());
+        let read_footer = data_file1.read_log_record(write_off).unwrap();
+        assert_eq!(LogRecordType::FOOTER, read_footer.record.rec_type);
+
+        // 3.重新打开之后从 footer 恢复摘要，继续写入的数据也可以整体校验
+        std::mem::drop(data_file1);
+        let data_file2 = DataFile::new(dir_path.clone(), 950).unwrap();
+        data_file2.set_write_off(write_off + FOOTER_SIZE);
+        let resume_res = data_file2.resume_digest();
+        assert!(resume_res.is_ok());
+        let enc = LogRecord {
+            key: "key-10".as_bytes().to_vec(),
+            value: Default::default(),
+            rec_type: LogRecordType::DELETED,
+            checksum: ChecksumKind::Crc32,
+            version: 0,
+        };
+        assert!(data_file2.write(&enc.encode()).is_ok());
+        assert!(data_file2.write_footer().is_ok());
+        assert_eq!(Some(11), data_file2.verify_footer().unwrap());
+        std::mem::drop(data_file2);
+
+        // 4.篡改一个字节之后整体校验失败
+        let file_name = get_data_file_name(dir_path.clone(), 950);
+        let mut content = std::fs::read(&file_name).unwrap();
+        content[20] ^= 0x01;
+        std::fs::write(&file_name, content).unwrap();
+        let data_file3 = DataFile::new(dir_path.clone(), 950).unwrap();
+        assert_eq!(None, data_file3.verify_footer().unwrap());
+
+        let remove_res = std::fs::remove_file(file_name);
+        assert!(remove_res.is_ok());
+
+        // 5.长度和 footer 相同但 value 长度不同的记录不是 footer
+        let data_file4 = DataFile::new(dir_path.clone(), 951).unwrap();
+        let enc = LogRecord {
+            key: Vec::new(),
+            value: vec![1; FOOTER_VALUE_SIZE - 1],
+            rec_type: LogRecordType::FOOTER,
+            checksum: ChecksumKind::Crc32,
+            version: 1,
+        };
+        let buf = enc.encode();
+        assert_eq!(FOOTER_SIZE, buf.len() as u64);
+        assert!(data_file4.write(&buf).is_ok());
+        assert_eq!(None, data_file4.verify_footer().unwrap());
+
+        // 6.footer 之后没有写入新的数据时不会重复写入
+        assert!(data_file4.write_footer().is_ok());
+        let write_off = data_file4.get_write_off();
+        assert!(data_file4.write_footer().is_ok());
+        assert_eq!(write_off, data_file4.get_write_off());
+        assert_eq!(Some(1), data_file4.verify_footer().unwrap());
+
+        let remove_res = std::fs::remove_file(get_data_file_name(dir_path.clone(), 951));
+        assert!(remove_res.is_ok());
+    }
+
+    #[test]
+    fn test_data_file_aligned_log_record() {
+        let dir_path = std::env::temp_dir();
+        let data_file1 = DataFile::new(dir_path.clone(), 960).unwrap();
+
+        // 对齐的记录和没有对齐的记录可以混合写入
+        let mut offsets = Vec::new();
+        for (i, alignment) in [8, 8, 1, 64, 8].into_iter().enumerate() {
+            let enc = LogRecord {
+                key: format!("key-{}", i).into_bytes(),
+                value: "bitcask-rs".repeat(i).into_bytes(),
+                rec_type: LogRecordType::NORMAL,
+                checksum: ChecksumKind::Crc32,
+                version: 0,
+            };
+            let offset = data_file1.get_write_off();
+            let write_res = data_file1.write(&enc.encode_aligned(offset, alignment));
+            assert!(write_res.is_ok());
+            if alignment > 1 {
+                assert_eq!(0, data_file1.get_write_off() % alignment);
+            } else {
+                assert_eq!(
+                    enc.encode().len() as u64,
+                    data_file1.get_write_off() - offset
+                );
+            }
+            offsets.push((offset, enc));
+        }
+
+        // 读取时跳过填充，记录的大小包括填充，可以得到下一条记录的位置
+        for (i, (offset, enc)) in offsets.iter().enumerate() {
+            let read_res = data_file1.read_log_record(*offset);
+            assert!(read_res.is_ok());
+            let read_record = read_res.unwrap();
+            assert_eq!(enc.key, read_record.record.key);
+            assert_eq!(enc.value, read_record.record.value);
+            let next = match offsets.get(i + 1) {
+                Some((next, _)) => *next,
+                None => data_file1.get_write_off(),
+            };
+            assert_eq!(next, offset + read_record.size as u64);
+        }
+
+        let remove_res = std::fs::remove_file(get_data_file_name(dir_path, 960));
+        assert!(remove_res.is_ok());
+    }
+
+    #[test]
+    fn test_data_file_aligned_log_record_corrupted_padding() {
+        let dir_path = std::env::temp_dir();
+        let data_file1 = DataFile::new(dir_path.clone(), 961).unwrap();
+
+        // 对齐的记录 header 为 type、key size、value size 和填充的长度，填充的长度在第 4 个字节
+        let enc = LogRecord {
+            key: "name".as_bytes().to_vec(),
+            value: "bitcask-rs".as_bytes().to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            checksum: ChecksumKind::Crc32,
+            version: 0,
+        };
+        let mut buf = enc.encode_aligned(0, 8);
+        assert_eq!(PADDING_FLAG, buf[0] & PADDING_FLAG);
+        assert_eq!(buf.len() - enc.encode().len() - 1, buf[3] as usize);
+        assert!(buf[3] > 0);
+
+        // 篡改填充的长度，校验值包括填充的长度，读取时校验失败
+        buf[3] = 0;
+        let write_res = data_file1.write(&buf);
+        assert!(write_res.is_ok());
+        let read_res = data_file1.read_log_record(0);
+        assert_eq!(Errors::InvalidLogRecordCrc, read_res.err().unwrap());
+
+        let remove_res = std::fs::remove_file(get_data_file_name(dir_path, 961));
+        assert!(remove_res.is_ok());
+    }
+
+    #[test]
+    fn test_data_file_read_oversized_log_record() {
+        let dir_path = std::env::temp_dir();
+        let data_file_res1 = DataFile::new(dir_path.clone(), 800);
+        assert!(data_file_res1.is_ok());
+        let data_file1 = data_file_res1.unwrap();
+        assert_eq!(data_file1.get_file_id(), 800);
+
+        // 构造一个 key size 被篡改成超大值的 header
+        let mut buf = BytesMut::new();
+        buf.put_u8(LogRecordType::NORMAL as u8);
+        encode_length_delimiter(u32::MAX as usize, &mut buf).unwrap();
+        encode_length_delimiter(10, &mut buf).unwrap();
+        buf.extend_from_slice("name".as_bytes());
+        let write_res1 = data_file1.write(&buf);
+        assert!(write_res1.is_ok());
+
+        let read_res1 = data_file1.read_log_record(0);
+        assert_eq!(Errors::InvalidLogRecordCrc, read_res1.err().unwrap());
+
+        // key size 和 value size 是 10 字节的 varint，接近 u64::MAX，相加时会溢出
+        let offset = data_file1.get_write_off();
+        let mut buf = BytesMut::new();
+        buf.put_u8(LogRecordType::NORMAL as u8);
+        encode_length_delimiter(u64::MAX as usize - 1, &mut buf).unwrap();
+        encode_length_delimiter(u64::MAX as usize - 1, &mut buf).unwrap();
+        assert_eq!(21, buf.len());
+        buf.extend_from_slice("name".as_bytes());
+        let write_res2 = data_file1.write(&buf);
+        assert!(write_res2.is_ok());
+
+        let read_res2 = data_file1.read_log_record(offset);
+        assert_eq!(Errors::InvalidLogRecordCrc, read_res2.err().unwrap());
+
+        let remove_res = std::fs::remove_file(get_data_file_name(dir_path, 800));
+        assert!(remove_res.is_ok());
+    }
+
+    #[test]
+    fn test_data_file_records() {
+        let dir_path = std::env::temp_dir();
+        let data_file1 = DataFile::new(dir_path.clone(), 970).unwrap();
+
+        // 空文件没有记录
+        assert_eq!(0, data_file1.records().count());
+
+        let mut offsets = Vec::new();
+        for i in 0..10 {
+            let enc = LogRecord {
+                key: format!("key-{}", i).into_bytes(),
+                value: format!("value-{}", i).into_bytes(),
+                rec_type: match i % 3 {
+                    0 => LogRecordType::DELETED,
+                    _ => LogRecordType::NORMAL,
+                },
+                checksum: ChecksumKind::Crc32,
+                version: i,
+            };
+            offsets.push(data_file1.get_write_off());
+            let write_res = data_file1.write(&enc.encode());
+            assert!(write_res.is_ok());
+        }
+
+        // 按照写入的顺序返回所有的记录和位置
+        let records: Vec<_> = data_file1.records().map(|res| res.unwrap()).collect();
+        assert_eq!(10, records.len());
+        for (i, (pos, record)) in records.iter().enumerate() {
+            assert_eq!(970, pos.file_id());
+            assert_eq!(offsets[i], pos.offset());
+            assert_eq!(i as u64, pos.version());
+            assert_eq!(format!("key-{}", i).as_bytes(), record.key());
+            assert_eq!(format!("value-{}", i).as_bytes(), record.value());
+            let rec_type = match i % 3 {
+                0 => LogRecordType::DELETED,
+                _ => LogRecordType::NORMAL,
+            };
+            assert_eq!(rec_type, record.rec_type());
+        }
+
+        // 记录损坏时返回错误之后结束
+        let io_manager = data_file1.io_manager().unwrap();
+        let res = io_manager.write_at(&[0xff], offsets[5] + 5);
+        assert!(res.is_ok());
+        let mut results: Vec<_> = data_file1.records().collect();
+        assert_eq!(6, results.len());
+        assert_eq!(
+            Errors::InvalidLogRecordCrc,
+            results.pop().unwrap().err().unwrap()
+        );
+
+        let remove_res = std::fs::remove_file(get_data_file_name(dir_path, 970));
+        assert!(remove_res.is_ok());
+    }
 }
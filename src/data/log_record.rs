@@ -1,6 +1,13 @@
-use bytes::{BufMut, BytesMut};
-use prost::{encode_length_delimiter, length_delimiter_len};
+use bytes::BufMut;
+use prost::{
+    encode_length_delimiter,
+    encoding::{encode_varint, encoded_len_varint},
+    length_delimiter_len,
+};
 
+use crate::options::ChecksumKind;
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum LogRecordType {
     // 正常 put 的数据
@@ -8,6 +15,9 @@ pub enum LogRecordType {
 
     // 被删除的数据标识，墓碑值
     DELETED = 2,
+
+    // 数据文件的 footer，记录文件中之前所有数据的校验值，不对应任何 key
+    FOOTER = 3,
 }
 /// LogRecord 写入到数据文件的记录
 /// 之所以叫日志，是因为数据文件中的数据是追加写入的，类似日志的格式
@@ -15,61 +25,195 @@ pub struct LogRecord {
     pub(crate) key: Vec<u8>,
     pub(crate) value: Vec<u8>,
     pub(crate) rec_type: LogRecordType,
+    pub(crate) checksum: ChecksumKind,
+    pub(crate) version: u64, // key 的版本，每次 put 递增，为 0 时不写入，删除记录和 footer 没有版本
 }
 
 /// 数据位置索引信息，描述数据存储到了哪个位置
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct LogRecordPos {
-    pub(crate) file_id: u32, // 文件 id，表示将数据存储在了哪个文件中
+    pub(crate) file_id: u64, // 文件 id，表示将数据存储在了哪个文件中
     pub(crate) offset: u64,  // 偏移，表示将数据存储在了数据文件的哪个位置
+    pub(crate) inline: Option<InlineValue>, // 内联在索引中的 value，读取时不需要访问数据文件
+    pub(crate) version: u64, // 记录中 key 的版本，旧格式的记录为 0
+}
+
+/// 记录对齐的最大大小，填充的长度不超过一个字节的变长编码
+pub(crate) const MAX_RECORD_ALIGNMENT: u64 = 128;
+
+/// type 字节中表示记录末尾有填充的标志位，设置时 header 中 value size 之后是填充的长度
+pub(crate) const PADDING_FLAG: u8 = 0x08;
+
+/// type 字节中表示记录带有版本的标志位，设置时 header 中 value size 之后是 key 的版本
+pub(crate) const VERSION_FLAG: u8 = 0x40;
+
+/// 可以内联在索引中的 value 的最大长度
+pub(crate) const MAX_INLINE_VALUE_SIZE: usize = 16;
+
+/// 内联在索引中的 value，数据文件中仍然会写入完整的记录
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct InlineValue {
+    len: u8,
+    buf: [u8; MAX_INLINE_VALUE_SIZE],
+}
+
+impl InlineValue {
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len as usize]
+    }
+}
+
+impl LogRecordPos {
+    /// value 的长度不超过 threshold 时，将 value 内联到索引中，threshold 为 0 时不内联
+    pub(crate) fn with_inline_value(mut self, value: &[u8], threshold: usize) -> Self {
+        if threshold > 0 && value.len() <= threshold.min(MAX_INLINE_VALUE_SIZE) {
+            let mut buf = [0u8; MAX_INLINE_VALUE_SIZE];
+            buf[..value.len()].copy_from_slice(value);
+            self.inline = Some(InlineValue {
+                len: value.len() as u8,
+                buf,
+            });
+        }
+        self
+    }
+
+    /// 数据所在的文件 id
+    pub fn file_id(&self) -> u64 {
+        self.file_id
+    }
+
+    /// 数据在文件中的偏移
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// 数据对应的 key 的版本
+    pub fn version(&self) -> u64 {
+        self.version
+    }
 }
 
 /// 从数据文件中读取的 log_record 信息，包含其 size
 pub struct ReadLogRecord {
     pub(crate) record: LogRecord,
     pub(crate) size: usize,
+    pub(crate) crc: u32, // 根据读取到的 header、key 和 value 计算的校验值，对齐的记录包括填充的长度
 }
 
 impl LogRecord {
+    /// 记录的 key
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// 记录的 value，删除记录的 value 为空
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// 记录的类型
+    pub fn rec_type(&self) -> LogRecordType {
+        self.rec_type
+    }
+
+    /// 记录中 key 的版本，没有版本的记录为 0
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     // encode 对 LogRecord 进行编码，返回字节数组及长度
     //
-    // +-------------+-------------+------------+-----------+------------+
-    // |  type 类型   |  key size   | value size |   key     |    value   |
-    // +-------------+-------------+------------+-----------+------------+
-    //      1字节         变长（最大5）  变长（最大5）   变长          变长
+    // +-------------+-------------+------------+--------------+-----------+------------+-----------+
+    // |  type 类型   |  key size   | value size |    version   |   key     |    value   |  checksum |
+    // +-------------+-------------+------------+--------------+-----------+------------+-----------+
+    //      1字节         变长（最大5）  变长（最大5）  变长（最大10）     变长          变长         4字节
+    //
+    // type 字节的低 3 位存放记录类型，第 4 位是填充标志，第 5、6 位存放校验算法，CRC32 为 0，和之前的格式兼容
+    // 第 7 位是版本标志，version 为 0 时不设置标志也不写入 version，校验值包括 version
     pub fn encode(&self) -> Vec<u8> {
         let (enc_buf, _) = self.encode_and_get_crc();
         enc_buf
     }
 
-    pub fn get_crc(&self) -> u32 {
-        let (_, crc_value) = self.encode_and_get_crc();
-        crc_value
+    // encode_into 和 encode 的编码格式相同，编码结果写入 buf 中，返回校验值
+    // 会先清空 buf，之后重复使用同一个 buf 时，容量足够的话不需要重新分配内存
+    pub fn encode_into(&self, buf: &mut Vec<u8>) -> u32 {
+        self.encode_with_padding(None, buf)
     }
 
-    fn encode_and_get_crc(&self) -> (Vec<u8>, u32) {
-        // 初始化字节数组，存放编码数据
-        let mut buf = BytesMut::new();
-        buf.reserve(self.encoded_length());
+    // encode_aligned 对 LogRecord 进行编码，并在末尾填充 0，使下一条记录从 alignment 对齐的位置开始
+    //
+    // +--------------+----------+------------+--------------+-----+-------+----------+--------+
+    // | type（含标志） | key size | value size | [version] | padding size | key | value | checksum | 填充的 0 |
+    // +--------------+----------+------------+-----------+--------------+-----+-------+----------+--------+
+    //
+    // 校验值包括完整的 header，也就是包括填充标志和填充的长度，不包括末尾填充的 0
+    // alignment 为 1 时不填充，和 encode 的结果一致
+    pub fn encode_aligned(&self, offset: u64, alignment: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_aligned_into(offset, alignment, &mut buf);
+        buf
+    }
 
-        // 第一个字节存放 Type 类型
-        buf.put_u8(self.rec_type as u8);
+    // encode_aligned_into 和 encode_aligned 的编码格式相同，编码结果写入 buf 中，会先清空 buf
+    pub fn encode_aligned_into(&self, offset: u64, alignment: u64, buf: &mut Vec<u8>) {
+        if alignment <= 1 {
+            self.encode_into(buf);
+            return;
+        }
+
+        // 填充的长度小于 MAX_RECORD_ALIGNMENT，变长编码只占一个字节
+        let end = offset + self.encoded_length() as u64 + 1;
+        let padding = ((alignment - end % alignment) % alignment) as usize;
+        self.encode_with_padding(Some(padding), buf);
+        buf.resize(buf.len() + padding, 0);
+    }
 
-        // 再存储 key 和 value 的长度
-        encode_length_delimiter(self.key.len(), &mut buf).unwrap();
-        encode_length_delimiter(self.value.len(), &mut buf).unwrap();
+    // 按照 encode 的格式编码，padding 不为 None 时设置填充标志并在 header 的末尾写入填充的长度
+    // 校验值包括 header 中的所有字段，不包括末尾填充的 0，由调用方在之后追加
+    fn encode_with_padding(&self, padding: Option<usize>, buf: &mut Vec<u8>) -> u32 {
+        buf.clear();
+        buf.reserve(self.encoded_length() + padding.map_or(0, |p| p + 1));
+
+        // 第一个字节存放 Type 类型、校验算法、填充标志和版本标志
+        let mut rec_type = self.rec_type as u8 | (self.checksum as u8) << 4;
+        if self.version > 0 {
+            rec_type |= VERSION_FLAG;
+        }
+        if padding.is_some() {
+            rec_type |= PADDING_FLAG;
+        }
+        buf.put_u8(rec_type);
+
+        // 再存储 key 和 value 的长度，以及 key 的版本和填充的长度
+        encode_length_delimiter(self.key.len(), buf).unwrap();
+        encode_length_delimiter(self.value.len(), buf).unwrap();
+        if self.version > 0 {
+            encode_varint(self.version, buf);
+        }
+        if let Some(padding) = padding {
+            encode_length_delimiter(padding, buf).unwrap();
+        }
 
         // 存储 key 和 value
         buf.extend_from_slice(&self.key);
         buf.extend_from_slice(&self.value);
 
-        // 计算并存储 CRC 校验值
-        let mut hasher = crc32fast::Hasher::new();
-        hasher.update(&buf);
-        let crc = hasher.finalize();
+        // 计算并存储校验值
+        let crc = self.checksum.checksum(buf);
         buf.put_u32(crc);
+        crc
+    }
+
+    pub fn get_crc(&self) -> u32 {
+        let (_, crc_value) = self.encode_and_get_crc();
+        crc_value
+    }
 
-        (buf.to_vec(), crc)
+    fn encode_and_get_crc(&self) -> (Vec<u8>, u32) {
+        let mut buf = Vec::new();
+        let crc = self.encode_into(&mut buf);
+        (buf, crc)
     }
 
     // LogRecord 编码后的长度
@@ -77,27 +221,59 @@ impl LogRecord {
         std::mem::size_of::<u8>()
             + length_delimiter_len(self.key.len())
             + length_delimiter_len(self.value.len())
+            + self.version_len()
             + self.key.len()
             + self.value.len()
             + 4
     }
+
+    // header 中 version 的长度，version 为 0 时不写入
+    fn version_len(&self) -> usize {
+        match self.version {
+            0 => 0,
+            v => encoded_len_varint(v),
+        }
+    }
 }
 
 impl LogRecordType {
-    pub fn from_u8(v: u8) -> Self {
-        // println!("v:{}", v);
+    pub fn from_u8(v: u8) -> Option<Self> {
         match v {
-            1 => LogRecordType::NORMAL,
-            2 => LogRecordType::DELETED,
-            _ => panic!("unknown log record type"),
+            1 => Some(LogRecordType::NORMAL),
+            2 => Some(LogRecordType::DELETED),
+            3 => Some(LogRecordType::FOOTER),
+            _ => None,
         }
     }
 }
 
-/// rust 中的处理方式是把 CRC字段放在了最后面，前面也就只有 Type,KeySize,Value_size三个字段
+impl ChecksumKind {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(ChecksumKind::Crc32),
+            1 => Some(ChecksumKind::Crc32c),
+            2 => Some(ChecksumKind::XxHash64),
+            _ => None,
+        }
+    }
+
+    // 使用对应的算法计算校验值
+    pub(crate) fn checksum(&self, buf: &[u8]) -> u32 {
+        match self {
+            ChecksumKind::Crc32 => crc32fast::hash(buf),
+            ChecksumKind::Crc32c => crc32c::crc32c(buf),
+            ChecksumKind::XxHash64 => xxhash_rust::xxh64::xxh64(buf, 0) as u32,
+        }
+    }
+}
+
+/// rust 中的处理方式是把 CRC字段放在了最后面，前面有 Type,KeySize,Value_size,Version 以及填充长度几个字段
 /// 获取 LogRecord header 部分的最大长度
 pub fn max_log_record_header_size() -> usize {
-    std::mem::size_of::<u8>() + length_delimiter_len(std::u32::MAX as usize) * 2
+    std::mem::size_of::<u8>()
+        + length_delimiter_len(u32::MAX as usize) * 2
+        + encoded_len_varint(u64::MAX)
+        + 1
 }
 
 #[cfg(test)]
@@ -111,6 +287,8 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: "bitcask-rs".as_bytes().to_vec(),
             rec_type: LogRecordType::NORMAL,
+            checksum: ChecksumKind::Crc32,
+            version: 0,
         };
         let enc1 = rec1.encode();
         assert!(enc1.len() > 5);
@@ -121,6 +299,8 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: Default::default(),
             rec_type: LogRecordType::NORMAL,
+            checksum: ChecksumKind::Crc32,
+            version: 0,
         };
         let enc2 = rec2.encode();
         assert!(enc2.len() > 5);
@@ -131,9 +311,69 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: "bitcask-rs".as_bytes().to_vec(),
             rec_type: LogRecordType::DELETED,
+            checksum: ChecksumKind::Crc32,
+            version: 0,
         };
         let enc3 = rec3.encode();
         assert!(enc3.len() > 5);
         assert_eq!(1867197446, rec3.get_crc());
+
+        // 带有版本的记录，设置版本标志，校验值包括版本
+        let rec4 = LogRecord {
+            key: "name".as_bytes().to_vec(),
+            value: "bitcask-rs".as_bytes().to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            checksum: ChecksumKind::Crc32,
+            version: 3,
+        };
+        let enc4 = rec4.encode();
+        assert_eq!(enc1.len() + 1, enc4.len());
+        assert_eq!(VERSION_FLAG, enc4[0] & VERSION_FLAG);
+        assert_eq!(3, enc4[3]);
+        assert_ne!(rec1.get_crc(), rec4.get_crc());
+    }
+
+    #[test]
+    fn test_log_record_encode_into_reuses_buffer() {
+        let rec = |i: usize| LogRecord {
+            key: format!("key-{:09}", i).into_bytes(),
+            value: format!("value-{}", i).into_bytes(),
+            rec_type: LogRecordType::NORMAL,
+            checksum: ChecksumKind::Crc32,
+            version: i as u64 + 1,
+        };
+
+        // 编码结果和 encode、encode_aligned 一致
+        let mut buf = Vec::new();
+        let crc = rec(1).encode_into(&mut buf);
+        assert_eq!(rec(1).encode(), buf);
+        assert_eq!(rec(1).get_crc(), crc);
+        for alignment in [1, 8, 64] {
+            rec(1).encode_aligned_into(3, alignment, &mut buf);
+            assert_eq!(rec(1).encode_aligned(3, alignment), buf);
+            assert_eq!(0, (3 + buf.len() as u64) % alignment);
+        }
+
+        // 对齐的记录校验值包括填充标志和填充的长度，和不填充时不同，填充的长度在版本之后
+        let aligned = rec(1).encode_aligned(3, 8);
+        let crc_end = aligned.len() - aligned[4] as usize;
+        let aligned_crc = u32::from_be_bytes(aligned[crc_end - 4..crc_end].try_into().unwrap());
+        assert_eq!(
+            ChecksumKind::Crc32.checksum(&aligned[..crc_end - 4]),
+            aligned_crc
+        );
+        assert_ne!(rec(1).get_crc(), aligned_crc);
+
+        // 写入 1 万条记录，每次 encode 都分配新的内存，encode_into 只在容量不够时重新分配
+        let mut buf = Vec::new();
+        let mut allocations = 0;
+        for i in 0..10000 {
+            let capacity = buf.capacity();
+            rec(i).encode_aligned_into(i as u64, 8, &mut buf);
+            if buf.capacity() != capacity {
+                allocations += 1;
+            }
+        }
+        assert!(allocations <= 4, "allocations: {}", allocations);
     }
 }
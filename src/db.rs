@@ -1,50 +1,169 @@
-use std::{collections::HashMap, fs, path::PathBuf, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap},
+    fs,
+    hash::{Hash, Hasher},
+    num::NonZeroUsize,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+use std::sync::atomic::AtomicUsize;
 
 use bytes::Bytes;
-use log::warn;
-use parking_lot::RwLock;
+use log::{info, warn};
+use lru::LruCache;
+use parking_lot::{Mutex, MutexGuard, RwLock, RwLockReadGuard};
 
 use crate::{
     data::{
-        data_file::{DataFile, DATA_FILE_NAME_SUFFIX},
+        data_file::{get_data_file_name, DataFile, DATA_FILE_NAME_SUFFIX},
         log_record::{LogRecord, LogRecordPos, LogRecordType},
     },
     errors::{Errors, Result},
+    file_pins::FilePins,
+    fio::{available_space, io_cache::IOManagerCache, try_lock_exclusive},
+    group_commit::GroupCommit,
     index,
-    options::Options,
+    meta::{check_meta, UnsealedFiles},
+    options::{check_options, Options},
+    util::key::encode_u64_be,
 };
 
-const INITIAL_FILE_ID: u32 = 0;
+const INITIAL_FILE_ID: u64 = 0;
+
+// 数据库目录的文件锁，同一时间只允许一个进程写入数据库
+const FILE_LOCK_NAME: &str = "flock";
+
+// 获取文件锁失败之后重试的间隔
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+// 编码缓冲区保留的最大容量，写入较大的 value 之后不一直占用内存
+const MAX_ENCODE_BUF_CAPACITY: usize = 1024 * 1024;
+
+thread_local! {
+    // 每个线程复用的编码缓冲区，写入数据时不需要每次都分配内存
+    static ENCODE_BUF: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// 存储引擎的统计信息
+#[derive(Debug)]
+pub struct Stat {
+    pub data_file_num: usize, // 数据文件数量
+    pub disk_size: u64,       // 数据文件占用的磁盘空间大小
+}
+
+/// 单个数据文件的信息
+#[derive(Debug)]
+pub struct FileInfo {
+    pub file_id: u64,     // 数据文件 id
+    pub size: u64,        // 数据文件的大小
+    pub reclaimable: u64, // 合并这个文件可以回收的空间大小
+}
+
+/// 数据校验结果
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub valid_records: usize,              // 校验通过的记录数量
+    pub invalid_records: usize,            // 校验失败的记录数量
+    pub first_invalid: Option<(u64, u64)>, // 第一条校验失败的记录所在的文件 id 和偏移
+}
+
+/// 数据库启动时加载数据的结果
+#[derive(Debug)]
+pub struct OpenReport {
+    pub data_file_num: usize,    // 加载的数据文件数量
+    pub record_num: usize,       // 重放的记录数量，包括删除记录
+    pub load_duration: Duration, // 打开数据库的耗时
+}
 
 /// bitcask 存储引擎实例结构体
 pub struct Engine {
-    options: Arc<Options>,
-    active_file: Arc<RwLock<DataFile>>, // 当前活跃数据文件
-    older_files: Arc<RwLock<HashMap<u32, DataFile>>>, // 旧的数据文件
-    index: Box<dyn index::Indexer>,     // 数据内存索引
-    file_ids: Vec<u32>, // 数据库启动时的文件 id，只用于加载索引时使用，不能在其他的地方更新或使用
+    pub(crate) options: Arc<Options>,
+    pub(crate) active_file: Arc<RwLock<DataFile>>, // 当前活跃数据文件，也是第一个写入分片的活跃文件
+    shard_files: Vec<Arc<RwLock<DataFile>>>,       // 其他写入分片的活跃文件，只有一个写入分片时为空
+    pub(crate) older_files: Arc<RwLock<HashMap<u64, DataFile>>>, // 旧的数据文件
+    pub(crate) index: Box<dyn index::Indexer>,     // 数据内存索引
+    file_ids: Vec<u64>, // 数据库启动时的文件 id，只用于加载索引时使用，不能在其他的地方更新或使用
+    write_locks: Vec<Mutex<()>>, // 每个写入分片的写操作锁，保证读取索引、写入数据和更新索引作为一个整体执行
+    next_file_id: AtomicU64, // 下一个新创建的数据文件的 id，多个写入分片切换活跃文件时不会使用相同的 id
+    sequence: AtomicU64,     // 修改序号，每次成功写入、删除或清空数据时递增
+    pub(crate) clear_epoch: AtomicU64, // 清空数据库的次数，之前创建的迭代器读取到的位置已经失效，不再返回数据
+    staged: Mutex<Vec<(Bytes, Bytes)>>, // put_buffered 暂存的数据，commit_buffered 时写入
+    read_cache: Option<Mutex<LruCache<LogRecordPos, Bytes>>>, // 读缓存，key 覆盖写入后旧的位置不会再被读取，会被自然淘汰
+    pub(crate) io_cache: Option<Arc<IOManagerCache>>,         // 旧的数据文件的文件句柄缓存
+    pub(crate) group_commit: Option<GroupCommit>, // 批量持久化，开启时 put 和 delete 由后台线程持久化
+    pub(crate) _lock_file: Option<fs::File>, // 数据库目录的文件锁，engine 释放时关闭文件并释放锁，只读模式下不加锁，为 None
+    pub(crate) file_pins: FilePins, // 迭代器和快照读取期间保留被合并的数据文件，全部释放之后再删除
+    unsealed_files: Arc<UnsealedFiles>, // 可能没有封存的数据文件，崩溃之后重新打开时只截断这些文件末尾不完整的记录
+    merging: AtomicBool, // 是否正在合并旧的数据文件，合并的过程中切换活跃文件不受 max_data_files 的限制
+    pub(crate) bulk_loading: AtomicBool, // 是否正在批量导入，导入的数据还没有写入索引，不能合并
+    #[cfg(test)]
+    pub(crate) sync_count: AtomicUsize, // 写入数据时持久化的次数，只在测试中使用
+    #[cfg(test)]
+    pub(crate) dir_sync_count: AtomicUsize, // 创建数据文件后持久化目录的次数，只在测试中使用
+    #[cfg(test)]
+    pub(crate) read_count: AtomicUsize, // 从数据文件中读取 value 的次数，只在测试中使用
 }
 
 impl Engine {
     // 打开 bicask 存储引擎实例
     pub fn open(opts: Options) -> Result<Self> {
+        Self::open_with_report(opts).map(|(engine, _)| engine)
+    }
+
+    /// 打开 bitcask 存储引擎实例，同时返回加载数据的结果
+    pub fn open_with_report(opts: Options) -> Result<(Self, OpenReport)> {
+        let start = Instant::now();
         // 校验用户传递过来的配置项
         if let Some(e) = check_options(&opts) {
             return Err(e);
         }
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "open",
+            dir = ?opts.dir_path,
+            data_files = tracing::field::Empty,
+            records = tracing::field::Empty,
+        )
+        .entered();
 
         let options = opts.clone();
-        // 判断数据目录是否存在，如果不存在的话就创建这个目录
+        // 校验数据库目录和数据文件目录，目录不存在的话就创建
         let dir_path = options.dir_path.clone();
-        if !dir_path.is_dir() {
-            if let Err(e) = fs::create_dir_all(dir_path.as_path()) {
-                warn!("create database directory err: {}", e);
-                return Err(Errors::FailedToCreateDatabaseDir);
-            }
+        prepare_dir(&dir_path, options.read_only)?;
+        for data_dir in options.get_data_dirs().iter() {
+            prepare_dir(data_dir, options.read_only)?;
         }
 
-        // 加载数据文件
-        let mut data_files = load_data_files(dir_path.clone())?;
+        // 获取数据库目录的文件锁，避免多个进程同时写入
+        // 只读模式下不加锁，可以在其他进程写入的同时打开
+        let lock_file = match options.read_only {
+            true => None,
+            false => Some(lock_dir(&dir_path, options.lock_timeout)?),
+        };
+
+        // 校验配置项和数据库创建时的是否一致
+        check_meta(&options)?;
+        // 上一次打开时可能没有封存的数据文件
+        let (unsealed_files, last_unsealed) = UnsealedFiles::load(&dir_path)?;
+
+        // 加载数据文件，限制打开的文件数量时，旧的数据文件在访问时才打开
+        let io_cache = NonZeroUsize::new(options.max_open_files)
+            .map(|capacity| Arc::new(IOManagerCache::new(capacity, options.read_only)));
+        let mut data_files = load_data_files(
+            options.get_data_dirs(),
+            &io_cache,
+            options.skip_unparsable_files,
+            options.read_only,
+        )?;
 
         // 设置 file_id 信息
         let mut file_ids = Vec::new();
@@ -58,93 +177,1008 @@ impl Engine {
         // 将旧的数据文件保存到 older_files 中
         let mut older_files = HashMap::new();
         if data_files.len() > 1 {
-            for _ in 0..=data_files.len() - 1 {
+            for _ in 0..data_files.len() - 1 {
                 let file = data_files.pop().unwrap();
                 older_files.insert(file.get_file_id(), file);
             }
         }
 
         // 拿到当前活跃文件，即列表中的最后一个文件
-        let active_file = match data_files.pop() {
+        let mut active_file = match data_files.pop() {
             Some(v) => v,
-            None => DataFile::new(dir_path.clone(), INITIAL_FILE_ID)?,
+            // 只读模式下不创建数据文件，使用空的数据文件代替
+            None if options.read_only => {
+                DataFile::new_empty(options.get_data_dir(INITIAL_FILE_ID), INITIAL_FILE_ID)
+            }
+            None => {
+                let data_dir = options.get_data_dir(INITIAL_FILE_ID);
+                let data_file = DataFile::new(data_dir.clone(), INITIAL_FILE_ID)?;
+                if options.sync_dir {
+                    sync_dir(&data_dir)?;
+                }
+                data_file
+            }
+        };
+        active_file.set_write_buffer_size(options.write_buffer_size);
+        active_file.set_io_buffer_size(options.io_buffer_size);
+        let next_file_id = active_file.get_file_id() + 1;
+
+        // 只读模式下不会写入，只需要一个写入分片
+        let write_shards = match options.read_only {
+            true => 1,
+            false => options.write_shards,
         };
 
         // 构造存储引擎实例
-        let engine = Self {
+        let mut engine = Self {
             options: Arc::new(opts),
             active_file: Arc::new(RwLock::new(active_file)),
+            shard_files: Vec::new(),
             older_files: Arc::new(RwLock::new(older_files)),
-            index: Box::new(index::new_indexer(options.index_type)),
+            index: index::new_indexer(options.index_type, options.comparator.clone()),
             file_ids,
+            write_locks: (0..write_shards).map(|_| Mutex::new(())).collect(),
+            next_file_id: AtomicU64::new(next_file_id),
+            sequence: AtomicU64::new(0),
+            clear_epoch: AtomicU64::new(0),
+            staged: Mutex::new(Vec::new()),
+            read_cache: NonZeroUsize::new(options.read_cache_size)
+                .map(|size| Mutex::new(LruCache::new(size))),
+            file_pins: FilePins::new(io_cache.clone()),
+            unsealed_files: Arc::new(unsealed_files),
+            io_cache,
+            group_commit: None,
+            _lock_file: lock_file,
+            merging: AtomicBool::new(false),
+            bulk_loading: AtomicBool::new(false),
+            #[cfg(test)]
+            sync_count: AtomicUsize::new(0),
+            #[cfg(test)]
+            dir_sync_count: AtomicUsize::new(0),
+            #[cfg(test)]
+            read_count: AtomicUsize::new(0),
+        };
+
+        // 从数据文件中加载索引
+        let record_num = engine.load_index_from_data_files(&last_unsealed)?;
+
+        // 加载完成之后 write_off 已经确定，再对活跃文件预分配空间
+        if options.preallocate && !options.read_only {
+            engine
+                .active_file
+                .write()
+                .preallocate(options.data_file_size)?;
+        }
+
+        // 其他的写入分片使用新创建的活跃文件，创建之前先记录所有分片会写入的数据文件
+        // 上一次没有封存的数据文件已经截断并持久化，之后按照正常的数据文件读取
+        let shard_file_ids: Vec<u64> = (1..write_shards)
+            .map(|_| engine.next_file_id.fetch_add(1, Ordering::SeqCst))
+            .collect();
+        if !options.read_only {
+            let active_file_id = engine.active_file.read().get_file_id();
+            engine.unsealed_files.update(|file_ids| {
+                *file_ids = shard_file_ids
+                    .iter()
+                    .copied()
+                    .chain([active_file_id])
+                    .collect();
+            })?;
+        }
+        for file_id in shard_file_ids {
+            let data_file = engine.new_active_file(file_id)?;
+            engine.shard_files.push(Arc::new(RwLock::new(data_file)));
+        }
+
+        // 开启批量持久化时，启动后台持久化线程
+        if options.sync_writes && options.group_commit && !options.read_only {
+            let active_files = engine.active_files().cloned().collect();
+            engine.group_commit = Some(GroupCommit::start(active_files));
+        }
+
+        let report = OpenReport {
+            data_file_num: engine.file_ids.len(),
+            record_num,
+            load_duration: start.elapsed(),
+        };
+        #[cfg(feature = "tracing")]
+        {
+            span.record("data_files", report.data_file_num);
+            span.record("records", report.record_num);
+        }
+        info!(
+            "opened database {:?}, loaded {} data files and {} records in {:?}",
+            engine.options.dir_path, report.data_file_num, report.record_num, report.load_duration
+        );
+
+        Ok((engine, report))
+    }
+
+    /// 关闭数据库，释放相应资源
+    pub fn close(&self) -> Result<()> {
+        for active_file in self.active_files() {
+            // 写入 footer，需要和其他的写入互斥
+            let write_guard = active_file.write();
+            if !self.options.read_only {
+                write_guard.write_footer()?;
+            }
+            write_guard.sync()?;
+            // 去掉预分配的文件末尾没有使用的空间
+            write_guard.truncate_preallocated()?;
+        }
+        Ok(())
+    }
+
+    /// 将活跃文件缓冲区中的数据写入操作系统，不进行持久化，比 sync 的代价小
+    /// 之后进程崩溃时数据不会丢失，但机器掉电时仍然可能丢失
+    pub fn flush(&self) -> Result<()> {
+        self.active_files()
+            .try_for_each(|active_file| active_file.read().flush())
+    }
+
+    /// 持久化当前活跃文件，有多个写入分片时持久化每个分片的活跃文件
+    pub fn sync(&self) -> Result<()> {
+        self.active_files()
+            .try_for_each(|active_file| active_file.read().sync())
+    }
+
+    /// 持久化指定的数据文件，文件不存在时返回 DataFileNotFound
+    /// 用于只修改了部分数据文件的场景，不需要持久化其他的数据文件
+    pub fn sync_file(&self, file_id: u64) -> Result<()> {
+        for active_file in self.read_active_files() {
+            if active_file.get_file_id() == file_id {
+                return active_file.sync();
+            }
+        }
+
+        let older_files = self.older_files.read();
+        match older_files.get(&file_id) {
+            Some(data_file) => data_file.sync(),
+            None => Err(Errors::DataFileNotFound),
+        }
+    }
+
+    /// 估算内存索引占用的内存大小，用于规划机器的内存，需要遍历整个索引
+    pub fn index_memory_usage(&self) -> usize {
+        self.index.memory_usage()
+    }
+
+    /// 获取存储引擎的统计信息
+    /// 磁盘空间大小由已经打开的数据文件累加得到，不需要重新遍历数据目录
+    pub fn stat(&self) -> Result<Stat> {
+        let active_files = self.read_active_files();
+        let older_files = self.older_files.read();
+
+        let mut disk_size = 0;
+        for data_file in active_files
+            .iter()
+            .map(|f| &**f)
+            .chain(older_files.values())
+        {
+            disk_size += data_file.file_size()?;
+        }
+
+        Ok(Stat {
+            data_file_num: older_files.len() + active_files.len(),
+            disk_size,
+        })
+    }
+
+    /// 获取所有数据文件的信息，包括活跃文件，按照文件 id 从小到大排列
+    /// 可以回收的空间和 merge_partial 选择文件时的统计方式一致，需要扫描所有的数据文件
+    pub fn files_info(&self) -> Result<Vec<FileInfo>> {
+        let active_files = self.read_active_files();
+        let older_files = self.older_files.read();
+        let retention_fid =
+            min_file_id(&active_files).saturating_sub(self.options.tombstone_retention);
+
+        let mut data_files: Vec<&DataFile> = older_files.values().collect();
+        data_files.extend(active_files.iter().map(|f| &**f));
+        data_files.sort_by_key(|f| f.get_file_id());
+
+        let mut infos = Vec::with_capacity(data_files.len());
+        for data_file in data_files {
+            let file_id = data_file.get_file_id();
+            let size = data_file.file_size()?;
+            let (live_size, _) = self.read_live_records(data_file, file_id >= retention_fid)?;
+            infos.push(FileInfo {
+                file_id,
+                size,
+                reclaimable: size.saturating_sub(live_size),
+            });
+        }
+        Ok(infos)
+    }
+
+    /// 校验所有数据文件中的记录，重新计算每条记录的 crc，不会修改内存索引
+    /// 文件以 footer 结尾时先整体计算一次文件的 crc，和 footer 一致时不再逐条校验记录
+    /// 如果记录的 header 已经损坏，无法确定下一条记录的位置，则该文件后续的数据不再校验
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let active_files = self.read_active_files();
+        let older_files = self.older_files.read();
+
+        // 按照文件 id 从小到大依次校验
+        let mut data_files: Vec<&DataFile> = older_files.values().collect();
+        data_files.extend(active_files.iter().map(|f| &**f));
+        data_files.sort_by_key(|f| f.get_file_id());
+
+        let mut report = VerifyReport::default();
+        for data_file in data_files {
+            // 文件末尾的 footer 和文件中的数据一致时不需要再逐条校验
+            if let Some(records) = data_file.verify_footer()? {
+                report.valid_records += records as usize;
+                continue;
+            }
+
+            let file_id = data_file.get_file_id();
+            let mut offset = 0;
+            loop {
+                let (valid, size, is_footer) = match data_file.read_log_record_with_crc(offset) {
+                    Ok((read_record, crc)) => (
+                        crc == read_record.crc,
+                        Some(read_record.size),
+                        read_record.record.rec_type == LogRecordType::FOOTER,
+                    ),
+                    Err(Errors::ReadDataFileEOF) => break,
+                    Err(Errors::InvalidLogRecordCrc) => (false, None, false),
+                    Err(e) => return Err(e),
+                };
+
+                // footer 不计入记录数量
+                if valid {
+                    if !is_footer {
+                        report.valid_records += 1;
+                    }
+                } else {
+                    report.invalid_records += 1;
+                    if report.first_invalid.is_none() {
+                        report.first_invalid = Some((file_id, offset));
+                    }
+                }
+
+                match size {
+                    Some(size) => offset += size as u64,
+                    None => break,
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// 扫描所有的数据文件，按照文件 id 和偏移从小到大返回 key 的每一条记录的位置，包括删除记录
+    /// 只用于排查问题，代价和数据文件的大小成正比，crc 校验失败的记录不会返回
+    pub fn locate_key(&self, key: Bytes) -> Result<Vec<LogRecordPos>> {
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        let mut positions = Vec::new();
+        self.for_each_record(|pos, record| {
+            if record.key == key {
+                positions.push(pos);
+            }
+        })?;
+        Ok(positions)
+    }
+
+    // 按照文件 id 和偏移从小到大遍历所有数据文件中的记录，包括删除记录，不包括 footer
+    // crc 校验失败的记录会被跳过，header 损坏时跳过这个文件后续的数据
+    pub(crate) fn for_each_record<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(LogRecordPos, LogRecord),
+    {
+        let active_files = self.read_active_files();
+        let older_files = self.older_files.read();
+
+        let mut data_files: Vec<&DataFile> = older_files.values().collect();
+        data_files.extend(active_files.iter().map(|f| &**f));
+        data_files.sort_by_key(|f| f.get_file_id());
+
+        for data_file in data_files {
+            let mut offset = 0;
+            loop {
+                let (read_record, crc) = match data_file.read_log_record_with_crc(offset) {
+                    Ok(res) => res,
+                    Err(Errors::ReadDataFileEOF) | Err(Errors::InvalidLogRecordCrc) => break,
+                    Err(e) => return Err(e),
+                };
+
+                let pos = LogRecordPos {
+                    file_id: data_file.get_file_id(),
+                    offset,
+                    inline: None,
+                    version: read_record.record.version,
+                };
+                offset += read_record.size as u64;
+                if crc == read_record.crc && read_record.record.rec_type != LogRecordType::FOOTER {
+                    f(pos, read_record.record);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 读取数据文件中仍然有效的记录，返回有效记录占用的空间大小和这些记录
+    // 最新的位置在这个文件中的记录是有效的，keep_deleted 为 true 时还会返回 key 不在索引中的删除记录
+    fn read_live_records(
+        &self,
+        data_file: &DataFile,
+        keep_deleted: bool,
+    ) -> Result<(u64, Vec<LogRecord>)> {
+        let file_id = data_file.get_file_id();
+        let mut live_size = 0;
+        let mut records = Vec::new();
+        let mut offset = 0;
+        loop {
+            let (read_record, crc) = match data_file.read_log_record_with_crc(offset) {
+                Ok(res) => res,
+                Err(Errors::ReadDataFileEOF) | Err(Errors::InvalidLogRecordCrc) => break,
+                Err(e) => return Err(e),
+            };
+            let record_offset = offset;
+            offset += read_record.size as u64;
+            if crc != read_record.crc {
+                continue;
+            }
+
+            // 每个封存的文件都有 footer，不算作可以回收的空间，也不需要重新写入
+            let record = read_record.record;
+            if record.rec_type == LogRecordType::FOOTER {
+                live_size += read_record.size as u64;
+                continue;
+            }
+            let live = match (record.rec_type, self.index.get(record.key.clone())) {
+                (LogRecordType::NORMAL, Some(pos)) => {
+                    pos.file_id == file_id && pos.offset == record_offset
+                }
+                (LogRecordType::DELETED, None) => keep_deleted,
+                _ => false,
+            };
+            if live {
+                live_size += read_record.size as u64;
+                records.push(record);
+            }
+        }
+        Ok((live_size, records))
+    }
+
+    /// 获取当前的修改序号，每次成功写入、删除或清空数据时加一，可用于判断两次读取之间数据是否发生了变化
+    /// 序号不会持久化，每次打开数据库时从 0 开始
+    pub fn sequence(&self) -> u64 {
+        self.sequence.load(Ordering::SeqCst)
+    }
+
+    /// 获取当前活跃文件的 id，可以作为 changes_since 的起点
+    /// 有多个写入分片时返回最小的活跃文件 id，之后的写入都在 id 不小于它的数据文件中
+    pub fn active_file_id(&self) -> u64 {
+        min_file_id(&self.read_active_files())
+    }
+
+    /// 获取 id 大于等于 file_id 的数据文件中修改过的 key，以及每个 key 在这些文件中最新的 value
+    ///
+    /// 数据文件是追加写入的，文件 id 越大、文件中的偏移越大，写入的时间就越晚，
+    /// 因此按照文件 id 和偏移顺序重放记录即可得到每个 key 最新的状态。
+    /// 在这些文件中最后一次操作是删除的 key 不会出现在结果中，结果按照 key 排序
+    pub fn changes_since(&self, file_id: u64) -> Result<Vec<(Bytes, Bytes)>> {
+        let active_files = self.read_active_files();
+        let older_files = self.older_files.read();
+
+        let mut data_files: Vec<&DataFile> = older_files
+            .values()
+            .chain(active_files.iter().map(|f| &**f))
+            .filter(|f| f.get_file_id() >= file_id)
+            .collect();
+        data_files.sort_by_key(|f| f.get_file_id());
+
+        let mut changes = BTreeMap::new();
+        for data_file in data_files {
+            let mut offset = 0;
+            loop {
+                let read_record = match data_file.read_log_record(offset) {
+                    Ok(read_record) => read_record,
+                    Err(Errors::ReadDataFileEOF) => break,
+                    Err(e) => return Err(e),
+                };
+                offset += read_record.size as u64;
+
+                let record = read_record.record;
+                match record.rec_type {
+                    LogRecordType::NORMAL => changes.insert(record.key, record.value),
+                    LogRecordType::DELETED => changes.remove(&record.key),
+                    LogRecordType::FOOTER => None,
+                };
+            }
+        }
+
+        Ok(changes
+            .into_iter()
+            .map(|(k, v)| (Bytes::from(k), Bytes::from(v)))
+            .collect())
+    }
+
+    /// 持久化并封存当前活跃文件，不论其大小，之后的写入都会追加到新的活跃文件中
+    /// 有多个写入分片时封存每个分片的活跃文件，数据文件数量达到 max_data_files 时先合并旧的数据文件
+    pub fn rotate(&self) -> Result<()> {
+        // 只读模式下不允许创建新的数据文件
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        let _write_guards = self.lock_all_shards();
+        for active_file in self.active_files() {
+            if self.reached_max_data_files() {
+                self.merge_for_max_data_files()?;
+            }
+            let mut active_file = active_file.write();
+            self.rotate_active_file(&mut active_file)?;
+        }
+        Ok(())
+    }
+
+    /// 清空数据库，删除所有的数据文件和内存索引，并使用新的文件 id 创建活跃文件
+    pub fn clear(&self) -> Result<()> {
+        // 只读模式下不允许删除数据
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 清空的过程中不允许有其他的写入
+        let _write_guards = self.lock_all_shards();
+        // 先清空索引，之后的读取不会再返回清空之前的数据，已经创建的迭代器在下一次读取时结束
+        self.index.clear();
+        self.clear_epoch.fetch_add(1, Ordering::SeqCst);
+
+        let mut active_files: Vec<_> = self.active_files().map(|f| f.write()).collect();
+        let mut older_files = self.older_files.write();
+        older_files.clear();
+        self.file_pins.clear();
+
+        for (file_id, dir_path) in list_data_files(
+            self.options.get_data_dirs(),
+            self.options.skip_unparsable_files,
+        )? {
+            let file_name = get_data_file_name(dir_path, file_id);
+            if fs::remove_file(&file_name).is_err() {
+                return Err(Errors::FailedToRemoveDataFile);
+            }
+            // 之后会创建同名的数据文件，不能继续使用已经删除的文件的句柄
+            if let Some(io_cache) = &self.io_cache {
+                io_cache.remove(&file_name);
+            }
+        }
+
+        // 每个写入分片使用新的文件 id 创建活跃文件，文件 id 不会复用，
+        // 清空之前创建的迭代器和快照中保存的位置不会指向之后写入的数据，读取时找不到数据文件
+        let file_ids: Vec<u64> = active_files
+            .iter()
+            .map(|_| self.next_file_id.fetch_add(1, Ordering::SeqCst))
+            .collect();
+        self.unsealed_files
+            .update(|unsealed| *unsealed = file_ids.iter().copied().collect())?;
+        for (active_file, file_id) in active_files.iter_mut().zip(file_ids) {
+            **active_file = self.new_active_file(file_id)?;
+        }
+        // 缓存的数据已经被删除，释放占用的内存
+        if let Some(read_cache) = &self.read_cache {
+            read_cache.lock().clear();
+        }
+
+        self.sequence.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// 存储 key/value 数据，key 不能为空
+    /// 编码后的记录大小不能超过 data_file_size，否则返回 ValueTooLarge
+    pub fn put(&self, key: Bytes, value: Bytes) -> Result<()> {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        // 开启批量持久化时，释放写锁之后再等待持久化，其他线程的写入可以一起持久化
+        if let Some(group_commit) = &self.group_commit {
+            {
+                let _write_guard = self.lock_shard(&key);
+                self.put_record(key, value, false)?;
+            }
+            return group_commit.commit();
+        }
+
+        let _write_guard = self.lock_shard(&key);
+        self.put_record(key, value, self.options.sync_writes)
+    }
+
+    /// 存储 key/value 数据，写入之后立即持久化，不受 sync_writes 配置项的影响
+    /// 用于关闭 sync_writes 时，保证部分重要的数据写入之后不会丢失
+    pub fn put_synced(&self, key: Bytes, value: Bytes) -> Result<()> {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        let _write_guard = self.lock_shard(&key);
+        self.put_record(key, value, true)
+    }
+
+    /// 当 key 不存在（或已被删除）时才存储 key/value 数据
+    /// 写入成功返回 true，key 已存在有效的 value 时不写入并返回 false
+    pub fn put_if_absent(&self, key: Bytes, value: Bytes) -> Result<bool> {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        // 持有写锁，保证检查索引和写入数据之间不会有其他的写操作
+        let _write_guard = self.lock_shard(&key);
+        if self.index.get(key.to_vec()).is_some() {
+            return Ok(false);
+        }
+        self.put_record(key, value, self.options.sync_writes)?;
+
+        Ok(true)
+    }
+
+    /// 写入一个新的 key，key 已经存在有效的 value 时不写入并返回 KeyAlreadyExists
+    /// 和 put 不同，不会覆盖已有的数据，key 被删除之后可以重新写入
+    pub fn insert(&self, key: Bytes, value: Bytes) -> Result<()> {
+        match self.put_if_absent(key, value)? {
+            true => Ok(()),
+            false => Err(Errors::KeyAlreadyExists),
+        }
+    }
+
+    /// 返回 key 对应的 value，key 不存在时调用 f 计算 value 并写入，返回写入的 value
+    /// 检查和写入之间持有写锁，多个线程同时调用时只有一个线程会调用 f，其他线程读取到写入的 value
+    pub fn get_or_insert_with<F>(&self, key: Bytes, f: F) -> Result<Bytes>
+    where
+        F: FnOnce() -> Bytes,
+    {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        let _write_guard = self.lock_shard(&key);
+        if let Some(pos) = self.index.get(key.to_vec()) {
+            return self.get_value_by_position(&pos);
+        }
+        let value = f();
+        self.put_record(key, value.clone(), self.options.sync_writes)?;
+
+        Ok(value)
+    }
+
+    /// 存储 key/value 数据，并返回被覆盖的旧的 value，key 不存在时返回 None
+    pub fn swap(&self, key: Bytes, value: Bytes) -> Result<Option<Bytes>> {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        // 持有写锁，保证读取旧的 value 和写入数据之间不会有其他的写操作
+        let _write_guard = self.lock_shard(&key);
+        let old_value = match self.index.get(key.to_vec()) {
+            Some(pos) => Some(self.get_value_by_position(&pos)?),
+            None => None,
+        };
+        self.put_record(key, value, self.options.sync_writes)?;
+
+        Ok(old_value)
+    }
+
+    /// 将 from 的 value 移动到 to，之后 from 不再存在，to 已经存在时会被覆盖，from 不存在时返回 KeyNotFound
+    /// 持有写锁，其他的写操作不会看到中间状态。先写入 to 再删除 from，开启 sync_writes 时在最后持久化一次，
+    /// 写入过程中崩溃时重启之后 from 和 to 可能同时存在，但不会丢失数据
+    pub fn rename(&self, from: Bytes, to: Bytes) -> Result<()> {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 判断 key 的有效性
+        if from.is_empty() || to.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        let _write_guards = self.lock_all_shards();
+        let pos = match self.index.get(from.to_vec()) {
+            Some(pos) => pos,
+            None => return Err(Errors::KeyNotFound),
+        };
+        if from == to {
+            return Ok(());
+        }
+        let value = self.get_value_by_position(&pos)?;
+        self.put_record(to, value, false)?;
+        self.delete_record(from, false)?;
+
+        if self.options.sync_writes {
+            self.sync()?;
+            #[cfg(test)]
+            self.sync_count.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    /// 将 key 最新的 value 重新写入到活跃文件中，旧的数据文件中的记录之后就不再有效
+    /// key 已经在活跃文件中时不会重复写入，key 不存在时返回 KeyNotFound，重新写入不改变 key 的版本
+    pub fn compact_key(&self, key: Bytes) -> Result<()> {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        // 持有写锁，保证读取 value 和重新写入之间不会有其他的写操作
+        let _write_guard = self.lock_shard(&key);
+        let pos = match self.index.get(key.to_vec()) {
+            Some(pos) => pos,
+            None => return Err(Errors::KeyNotFound),
         };
+        if pos.file_id == self.shard_active_file(&key).read().get_file_id() {
+            return Ok(());
+        }
+        let value = self.get_value_by_position(&pos)?;
+        self.put_record_with_version(key, value, pos.version, self.options.sync_writes)
+    }
+
+    /// 只合并无效数据最多的 max_files 个旧的数据文件，返回处理的文件数量
+    ///
+    /// 选中的文件中仍然有效的记录会重新写入到活跃文件中并更新索引，持久化之后再删除原来的文件。
+    /// 如果更早的文件没有被一起删除，删除记录也需要重新写入，否则重启之后被删除的 key 会重新出现。
+    /// 文件 id 不小于活跃文件 id 减去 tombstone_retention 的文件中的删除记录也会重新写入，
+    /// changes_since 从这些文件开始读取时仍然可以看到删除。
+    /// 其他的删除记录会被丢弃：key 在更早的文件中的记录已经全部删除，重放时不会再出现，
+    /// 而比保留范围更早的文件中的删除不再有读取方需要。
+    /// 选择文件时需要扫描所有旧的数据文件，合并的过程中持有写锁，其他的写操作需要等待合并结束
+    pub fn merge_partial(&self, max_files: usize) -> Result<usize> {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        let _write_guards = self.lock_all_shards();
+        self.merge_files(max_files)
+    }
+
+    // 合并旧的数据文件，合并的过程中切换活跃文件不受 max_data_files 的限制，调用方需要持有写锁
+    fn merge_files(&self, max_files: usize) -> Result<usize> {
+        self.merging.store(true, Ordering::SeqCst);
+        let res = self.merge_files_inner(max_files);
+        self.merging.store(false, Ordering::SeqCst);
+        res
+    }
+
+    fn merge_files_inner(&self, max_files: usize) -> Result<usize> {
+        #[cfg(feature = "tracing")]
+        let span =
+            tracing::info_span!("merge", max_files, file_ids = tracing::field::Empty).entered();
+        // id 大于等于 retention_fid 的文件中的删除记录需要保留
+        let retention_fid = self
+            .active_file_id()
+            .saturating_sub(self.options.tombstone_retention);
+
+        // 统计每个旧的数据文件中可以回收的空间，选出可以回收的空间最多的文件
+        let mut candidates = Vec::new();
+        {
+            let older_files = self.older_files.read();
+            for data_file in older_files.values() {
+                let keep_deleted = data_file.get_file_id() >= retention_fid;
+                let (live_size, _) = self.read_live_records(data_file, keep_deleted)?;
+                let reclaimable = data_file.file_size()?.saturating_sub(live_size);
+                if reclaimable > 0 {
+                    candidates.push((reclaimable, data_file.get_file_id()));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.cmp(a));
+        candidates.truncate(max_files);
+        if candidates.is_empty() {
+            return Ok(0);
+        }
+
+        let mut file_ids: Vec<u64> = candidates.into_iter().map(|(_, fid)| fid).collect();
+        file_ids.sort();
+        #[cfg(feature = "tracing")]
+        span.record("file_ids", tracing::field::debug(&file_ids));
+
+        for &file_id in &file_ids {
+            // 重新写入时活跃文件可能会切换，读取完成之后再释放旧的数据文件的锁
+            let records = {
+                let older_files = self.older_files.read();
+                let keep_deleted = file_id >= retention_fid
+                    || older_files
+                        .keys()
+                        .any(|fid| *fid < file_id && !file_ids.contains(fid));
+                match older_files.get(&file_id) {
+                    Some(data_file) => self.read_live_records(data_file, keep_deleted)?.1,
+                    None => return Err(Errors::DataFileNotFound),
+                }
+            };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(file_id, records = records.len(), "rewriting live records");
+
+            for mut record in records {
+                // 重新写入的记录和新写入的数据使用相同的校验算法
+                record.checksum = self.options.checksum;
+                let pos = self.append_log_record(&mut record, false)?;
+                if record.rec_type == LogRecordType::NORMAL {
+                    let pos =
+                        pos.with_inline_value(&record.value, self.options.inline_value_threshold);
+                    if !self.index.put(record.key, pos) {
+                        return Err(Errors::IndexUpdateFailed);
+                    }
+                }
+            }
+        }
+
+        // 重新写入的数据持久化之后才能删除原来的文件
+        self.sync()?;
+
+        // 还有迭代器或快照在读取时，等到它们释放之后再删除文件
+        // 持有 older_files 的写锁直到文件交给 file_pins，读取时不会两边都找不到文件
+        let mut older_files = self.older_files.write();
+        let mut merged_files = Vec::with_capacity(file_ids.len());
+        for &file_id in &file_ids {
+            if let Some(data_file) = older_files.remove(&file_id) {
+                let file_name = get_data_file_name(self.options.get_data_dir(file_id), file_id);
+                merged_files.push((data_file, file_name));
+            }
+        }
+        self.file_pins.retire(merged_files)?;
+
+        Ok(file_ids.len())
+    }
+
+    /// 暂存 key/value 数据，调用 commit_buffered 时才会写入，暂存的数据在写入之前读取不到
+    pub fn put_buffered(&self, key: Bytes, value: Bytes) -> Result<()> {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        self.staged.lock().push((key, value));
+        Ok(())
+    }
 
-        // 从数据文件中加载索引
-        engine.load_index_from_data_files()?;
+    /// 写入所有暂存的数据，返回写入的数量
+    /// 只获取一次写锁，开启 sync_writes 时也只在最后持久化一次，用于提升批量写入的性能
+    /// 不保证原子性，写入过程中出错时，之前写入的数据仍然有效，之后的数据被丢弃
+    pub fn commit_buffered(&self) -> Result<usize> {
+        let _write_guards = self.lock_all_shards();
+        let staged = std::mem::take(&mut *self.staged.lock());
+        if staged.is_empty() {
+            return Ok(0);
+        }
 
-        Ok(engine)
-    }
+        let count = staged.len();
+        for (key, value) in staged {
+            self.put_record(key, value, false)?;
+        }
 
-    /// 关闭数据库，释放相应资源
-    pub fn close(&self) -> Result<()> {
-        let read_guard = self.active_file.read();
-        read_guard.sync()
+        if self.options.sync_writes {
+            self.sync()?;
+            #[cfg(test)]
+            self.sync_count.fetch_add(1, Ordering::SeqCst);
+        }
+
+        Ok(count)
     }
 
-    /// 持久化当前活跃文件
-    pub fn sync(&self) -> Result<()> {
-        let read_guard = self.active_file.read();
-        read_guard.sync()
+    // 写入数据并更新内存索引，key 的版本在之前的版本上加 1，调用方需要持有写锁
+    fn put_record(&self, key: Bytes, value: Bytes, sync: bool) -> Result<()> {
+        let version = self.next_version(&key);
+        self.put_record_with_version(key, value, version, sync)
     }
 
-    /// 存储 key/value 数据，key 不能为空
-    pub fn put(&self, key: Bytes, value: Bytes) -> Result<()> {
-        // 判断 key 的有效性
-        if key.is_empty() {
-            return Err(Errors::KeyIsEmpty);
+    // 使用指定的版本写入数据并更新内存索引，调用方需要持有写锁
+    fn put_record_with_version(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        version: u64,
+        sync: bool,
+    ) -> Result<()> {
+        let log_record_pos = self.append_put_record(&key, &value, version, sync)?;
+
+        // 更新内存索引
+        let ok = self.index.put(key.to_vec(), log_record_pos);
+        if !ok {
+            return Err(Errors::IndexUpdateFailed);
         }
 
+        self.sequence.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // key 下一次写入时的版本，key 不存在或者被删除之后从 1 开始
+    pub(crate) fn next_version(&self, key: &[u8]) -> u64 {
+        self.index
+            .get(key.to_vec())
+            .map_or(1, |pos| pos.version.saturating_add(1))
+    }
+
+    // 写入数据但不更新内存索引，返回数据的位置，调用方需要持有写锁
+    pub(crate) fn append_put_record(
+        &self,
+        key: &Bytes,
+        value: &Bytes,
+        version: u64,
+        sync: bool,
+    ) -> Result<LogRecordPos> {
         // 构造 Logecord
         let mut record = LogRecord {
             key: key.to_vec(),
             value: value.to_vec(),
-            rec_type: crate::data::log_record::LogRecordType::NORMAL,
+            rec_type: LogRecordType::NORMAL,
+            checksum: self.options.checksum,
+            version,
         };
 
-        // 追加写到活跃数据文件中
-        let log_record_pos = self.append_log_record(&mut record)?;
+        // 追加写到活跃数据文件中，较小的 value 同时内联到索引中
+        let log_record_pos = self
+            .append_log_record(&mut record, sync)?
+            .with_inline_value(value, self.options.inline_value_threshold);
 
-        // 更新内存索引
-        let ok = self.index.put(key.to_vec(), log_record_pos);
-        if !ok {
-            return Err(Errors::IndexUpdateFailed);
+        if let Some(observer) = &self.options.observer {
+            observer.on_put(key.len(), value.len());
         }
+        Ok(log_record_pos)
+    }
 
+    // 批量更新内存索引，用于先写入数据、之后再统一构建索引的场景，调用方需要持有写锁
+    pub(crate) fn bulk_put_index(&self, entries: Vec<(Vec<u8>, LogRecordPos)>) -> Result<()> {
+        let count = entries.len() as u64;
+        if !self.index.bulk_put(entries) {
+            return Err(Errors::IndexUpdateFailed);
+        }
+        self.sequence.fetch_add(count, Ordering::SeqCst);
         Ok(())
     }
 
     // 根据 key 删除对应的数据
     pub fn delete(&self, key: Bytes) -> Result<()> {
+        // 只读模式下不允许删除
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
         // 判断 key 的有效性
         if key.is_empty() {
             return Err(Errors::KeyIsEmpty);
         }
 
+        let write_guard = self.lock_shard(&key);
+
         // 从内存共享索引中取出对应的数据，不存在的直接返回
         let pos = self.index.get(key.to_vec());
         if pos.is_none() {
             return Ok(());
         }
 
+        // 开启批量持久化时，释放写锁之后再等待持久化
+        if let Some(group_commit) = &self.group_commit {
+            self.delete_record(key, false)?;
+            drop(write_guard);
+            return group_commit.commit();
+        }
+
+        self.delete_record(key, self.options.sync_writes)
+    }
+
+    /// 读取 key 当前的 value 交给 f 处理，并写入 f 返回的新 value，f 返回 None 时删除 key
+    /// 读取和写入之间持有写锁，多个线程同时更新同一个 key 时不会丢失更新
+    pub fn update<F>(&self, key: Bytes, f: F) -> Result<()>
+    where
+        F: FnOnce(Option<Bytes>) -> Option<Bytes>,
+    {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        let _write_guard = self.lock_shard(&key);
+        let pos = self.index.get(key.to_vec());
+        let old_value = match pos {
+            Some(pos) => Some(self.get_value_by_position(&pos)?),
+            None => None,
+        };
+
+        match f(old_value) {
+            Some(value) => self.put_record(key, value, self.options.sync_writes),
+            None if pos.is_some() => self.delete_record(key, self.options.sync_writes),
+            None => Ok(()),
+        }
+    }
+
+    /// 遍历所有的 key/value，把 f 返回的新 value 写入数据库，f 返回 None 时删除 key，返回处理的 key 的数量
+    ///
+    /// 遍历的是开始时的快照，整个过程持有写锁，f 看到的都是重写之前的 value，不会读到已经重写过的数据。
+    /// 读操作不会被阻塞，重写的过程中可以读到部分已经重写的 key。新的 value 追加写入到活跃文件中，
+    /// 旧的记录之后都可以被 merge_partial 回收。开启 sync_writes 时只在最后持久化一次
+    pub fn rewrite<F>(&self, f: F) -> Result<usize>
+    where
+        F: Fn(&[u8], &[u8]) -> Option<Vec<u8>>,
+    {
+        // 只读模式下不允许写入
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        let _write_guards = self.lock_all_shards();
+        let (snapshot, _order) = self.index.snapshot();
+        for (index_key, pos) in snapshot.iter() {
+            let value = self.get_value_by_position(pos)?;
+            let key = Bytes::copy_from_slice(&index_key.key);
+            match f(&key, &value) {
+                Some(value) => self.put_record(key, Bytes::from(value), false)?,
+                None => self.delete_record(key, false)?,
+            }
+        }
+
+        if self.options.sync_writes {
+            self.sync()?;
+            #[cfg(test)]
+            self.sync_count.fetch_add(1, Ordering::SeqCst);
+        }
+
+        Ok(snapshot.len())
+    }
+
+    // 写入删除记录并删除内存索引，调用方需要持有写锁
+    fn delete_record(&self, key: Bytes, sync: bool) -> Result<()> {
         // 构造 LogRecord，表示其是可以被删除的
         let mut record = LogRecord {
             key: key.to_vec(),
             value: Default::default(),
             rec_type: LogRecordType::DELETED,
+            checksum: self.options.checksum,
+            version: 0,
         };
 
         // 写入到数据文件当中
-        self.append_log_record(&mut record)?;
+        self.append_log_record(&mut record, sync)?;
 
         // 删除内存索引中对应的 key
         let ok = self.index.delete(key.to_vec());
@@ -152,11 +1186,29 @@ impl Engine {
             return Err(Errors::IndexUpdateFailed);
         }
 
+        if let Some(observer) = &self.options.observer {
+            observer.on_delete();
+        }
+        self.sequence.fetch_add(1, Ordering::SeqCst);
         Ok(())
     }
 
     // 根据 key 获取对应的数据信息
     pub fn get(&self, key: Bytes) -> Result<Bytes> {
+        self.get_versioned(key).map(|(value, _)| value)
+    }
+
+    /// 根据 key 获取对应的 value 以及 key 的版本，版本在每次 put 时递增，删除之后重新从 1 开始
+    /// 旧格式的数据文件中写入的 key 版本为 0
+    pub fn get_versioned(&self, key: Bytes) -> Result<(Bytes, u64)> {
+        let res = self.get_value(key);
+        if let Some(observer) = &self.options.observer {
+            observer.on_get(res.is_ok());
+        }
+        res
+    }
+
+    fn get_value(&self, key: Bytes) -> Result<(Bytes, u64)> {
         // 判断 key 的有效性
         if key.is_empty() {
             return Err(Errors::KeyIsEmpty);
@@ -169,152 +1221,756 @@ impl Engine {
             return Err(Errors::KeyNotFound);
         }
 
-        // 从对应的数据文件中获取对应的 LogRecord
-        let log_record_pos = pos.unwrap();
-        let active_file = self.active_file.read();
+        // 从对应的数据文件中获取对应的 value
+        let pos = pos.unwrap();
+        match self.read_value(&pos, Some(&key)) {
+            Ok(value) => Ok((value, pos.version)),
+            Err(Errors::InvalidLogRecordCrc) if self.options.repair_on_read => {
+                let older = self.find_older_value(&key, &pos)?;
+                warn!(
+                    "record of key {:?} at file {} offset {} is corrupted, returned an older version",
+                    key, pos.file_id, pos.offset
+                );
+                Ok(older)
+            }
+            Err(Errors::DataFileNotFound) if self.options.heal_dangling_index => {
+                // 持有写锁再次确认索引没有被更新，避免删除并发写入的新数据
+                let _write_guard = self.lock_shard(&key);
+                if self.index.get(key.to_vec()) == Some(pos) {
+                    self.index.delete(key.to_vec());
+                    warn!(
+                        "data file {} of key {:?} is not found, removed the dangling index",
+                        pos.file_id, key
+                    );
+                }
+                Err(Errors::KeyNotFound)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // 在 pos 之前的记录中查找 key 最新的有效版本，pos 处的记录已经损坏
+    // 中间的记录被删除过或者找不到有效的版本时，仍然返回 crc 校验失败
+    // 返回找到的 value 以及对应记录中的版本
+    fn find_older_value(&self, key: &[u8], pos: &LogRecordPos) -> Result<(Bytes, u64)> {
+        let active_files = self.read_active_files();
         let older_files = self.older_files.read();
-        let log_record = match active_file.get_file_id() == log_record_pos.file_id {
-            true => active_file.read_log_record(log_record_pos.offset)?.record,
-            false => {
-                let data_file = older_files.get(&log_record_pos.file_id);
-                if data_file.is_none() {
-                    // 找不到对应的数据文件，返回错误
-                    return Err(Errors::DataFileNotFound);
+
+        let mut data_files: Vec<&DataFile> = older_files
+            .values()
+            .chain(active_files.iter().map(|f| &**f))
+            .filter(|f| f.get_file_id() <= pos.file_id)
+            .collect();
+        data_files.sort_by_key(|f| f.get_file_id());
+
+        let mut value = None;
+        for data_file in data_files {
+            let mut offset = 0;
+            while data_file.get_file_id() < pos.file_id || offset < pos.offset {
+                let (read_record, crc) = match data_file.read_log_record_with_crc(offset) {
+                    Ok(res) => res,
+                    // header 损坏时无法确定下一条记录的位置，跳过这个文件后续的数据
+                    Err(Errors::ReadDataFileEOF) | Err(Errors::InvalidLogRecordCrc) => break,
+                    Err(e) => return Err(e),
+                };
+                offset += read_record.size as u64;
+
+                let record = read_record.record;
+                if record.key != key || crc != read_record.crc {
+                    continue;
+                }
+                value = match record.rec_type {
+                    LogRecordType::NORMAL => Some((record.value, record.version)),
+                    LogRecordType::DELETED => None,
+                    LogRecordType::FOOTER => continue,
+                };
+            }
+        }
+
+        match value {
+            Some((value, version)) => Ok((value.into(), version)),
+            None => Err(Errors::InvalidLogRecordCrc),
+        }
+    }
+
+    /// 写入 key/value 数据，key 和 value 可以是任意能转换为 Bytes 的类型
+    ///
+    /// ```
+    /// use bitcask_rs::{db::Engine, options::Options};
+    ///
+    /// let mut opts = Options::default();
+    /// opts.dir_path = std::env::temp_dir().join("bitcask-rs-doc-put-kv");
+    /// let engine = Engine::open(opts.clone()).unwrap();
+    ///
+    /// engine.put_kv("name", "bitcask-rs").unwrap();
+    /// engine.put_kv(b"version".to_vec(), vec![1u8, 0]).unwrap();
+    /// engine.put_kv(&b"lang"[..], &b"rust"[..]).unwrap();
+    /// assert_eq!("bitcask-rs", engine.get_kv("name").unwrap());
+    /// assert_eq!(vec![1u8, 0], engine.get_kv(b"version".to_vec()).unwrap());
+    /// assert_eq!(&b"rust"[..], engine.get_kv(&b"lang"[..]).unwrap());
+    ///
+    /// engine.delete_kv("name").unwrap();
+    /// assert!(engine.get_kv("name").is_err());
+    /// # std::mem::drop(engine);
+    /// # std::fs::remove_dir_all(opts.dir_path).unwrap();
+    /// ```
+    pub fn put_kv<K: Into<Bytes>, V: Into<Bytes>>(&self, key: K, value: V) -> Result<()> {
+        self.put(key.into(), value.into())
+    }
+
+    /// 根据 key 获取对应的数据，key 可以是任意能转换为 Bytes 的类型
+    pub fn get_kv<K: Into<Bytes>>(&self, key: K) -> Result<Bytes> {
+        self.get(key.into())
+    }
+
+    /// 使用大端序编码的 u64 作为 key 存储数据，数值顺序和 key 的字节顺序一致，
+    /// 可以和 iter、scan 等按照 key 顺序遍历的接口一起使用，遍历得到的 key 用 decode_u64_be 解码
+    pub fn put_u64_key(&self, key: u64, value: Bytes) -> Result<()> {
+        self.put(Bytes::copy_from_slice(&encode_u64_be(key)), value)
+    }
+
+    /// 根据 put_u64_key 写入的 u64 key 获取对应的数据
+    pub fn get_u64_key(&self, key: u64) -> Result<Bytes> {
+        self.get(Bytes::copy_from_slice(&encode_u64_be(key)))
+    }
+
+    /// 根据 key 获取对应的数据，返回的 value 只能按照字节切片读取
+    ///
+    /// 从读缓存中命中时，返回的 value 和缓存共享同一块内存，不会拷贝数据，其他情况下持有一份新读取的数据。
+    /// 返回的 value 通过引用计数持有数据，不借用存储引擎，之后覆盖写入、删除这个 key 或者关闭数据库都不会影响它
+    pub fn get_ref(&self, key: Bytes) -> Result<impl Deref<Target = [u8]>> {
+        self.get(key)
+    }
+
+    /// 根据 key 删除对应的数据，key 可以是任意能转换为 Bytes 的类型
+    pub fn delete_kv<K: Into<Bytes>>(&self, key: K) -> Result<()> {
+        self.delete(key.into())
+    }
+
+    // 根据索引信息获取对应的 value
+    pub(crate) fn get_value_by_position(&self, log_record_pos: &LogRecordPos) -> Result<Bytes> {
+        self.read_value(log_record_pos, None)
+    }
+
+    // 根据索引信息获取对应的 value，传入 key 时校验数据文件中记录的 key 和传入的 key 是否一致
+    // 索引中的位置损坏时可能指向另一条 crc 校验通过的记录，只校验 crc 无法发现
+    // 内联的 value 和读缓存中的 value 不会访问数据文件，不做校验
+    fn read_value(&self, log_record_pos: &LogRecordPos, key: Option<&[u8]>) -> Result<Bytes> {
+        // 内联在索引中的 value 直接返回
+        if let Some(inline) = &log_record_pos.inline {
+            return Ok(Bytes::copy_from_slice(inline.as_slice()));
+        }
+
+        // 先从读缓存中查找，数据文件是追加写入的，同一个位置的记录不会改变
+        if let Some(read_cache) = &self.read_cache {
+            if let Some(value) = read_cache.lock().get(log_record_pos) {
+                return Ok(value.clone());
+            }
+        }
+
+        // 从对应的数据文件中获取对应的 LogRecord，活跃文件切换之后会放到旧的数据文件中，先查找活跃文件
+        let log_record = match self.read_active_record(log_record_pos)? {
+            Some(log_record) => log_record,
+            None => {
+                let older_files = self.older_files.read();
+                match older_files.get(&log_record_pos.file_id) {
+                    Some(data_file) => self.read_value_record(data_file, log_record_pos.offset)?,
+                    // 已经被合并的数据文件在之前创建的迭代器和快照释放之前仍然可以读取
+                    None => match self.file_pins.retired().get(&log_record_pos.file_id) {
+                        Some(data_file) => {
+                            self.read_value_record(data_file, log_record_pos.offset)?
+                        }
+                        // 找不到对应的数据文件，返回错误
+                        None => return Err(Errors::DataFileNotFound),
+                    },
                 }
-                data_file
-                    .unwrap()
-                    .read_log_record(log_record_pos.offset)?
-                    .record
             }
         };
 
+        #[cfg(test)]
+        self.read_count.fetch_add(1, Ordering::SeqCst);
+
+        // 使用了自定义的比较函数时，比较函数认为相等的 key 可能写入了不同的字节
+        if let Some(key) = key {
+            let matched = match &self.options.comparator {
+                Some(comparator) => comparator(key, &log_record.key) == std::cmp::Ordering::Equal,
+                None => key == log_record.key.as_slice(),
+            };
+            if !matched {
+                return Err(Errors::KeyMismatch);
+            }
+        }
+
         // 判断 Logrecord 的类型
         if log_record.rec_type == LogRecordType::DELETED {
             return Err(Errors::KeyNotFound);
         }
 
         // 返回对应的 value 信息
-        Ok(log_record.value.into())
+        let value: Bytes = log_record.value.into();
+        if let Some(read_cache) = &self.read_cache {
+            read_cache.lock().put(*log_record_pos, value.clone());
+        }
+        Ok(value)
+    }
+
+    // 记录在某个写入分片的活跃文件中时读取这条记录，否则返回 None
+    fn read_active_record(&self, log_record_pos: &LogRecordPos) -> Result<Option<LogRecord>> {
+        for active_file in self.active_files() {
+            let active_file = active_file.read();
+            if active_file.get_file_id() == log_record_pos.file_id {
+                return Ok(Some(
+                    self.read_value_record(&active_file, log_record_pos.offset)?,
+                ));
+            }
+        }
+        Ok(None)
+    }
+
+    // 读取 value 所在的记录，关闭了 verify_crc_on_read 时不计算记录的 crc
+    fn read_value_record(&self, data_file: &DataFile, offset: u64) -> Result<LogRecord> {
+        match self.options.verify_crc_on_read {
+            true => Ok(data_file.read_log_record(offset)?.record),
+            false => Ok(data_file.read_log_record_with_crc(offset)?.0.record),
+        }
     }
 
-    // 追加写数据到当前活跃文件中
-    fn append_log_record(&self, log_record: &mut LogRecord) -> Result<LogRecordPos> {
-        let dir_path = self.options.dir_path.clone();
+    // 追加写数据到当前活跃文件中，使用当前线程的编码缓冲区
+    fn append_log_record(&self, log_record: &mut LogRecord, sync: bool) -> Result<LogRecordPos> {
+        if self.options.read_only {
+            return Err(Errors::ReadOnlyDatabase);
+        }
+
+        // 合并时会嵌套写入，取出缓冲区之后嵌套的写入使用新的缓冲区
+        let mut enc_buf = ENCODE_BUF.with(|buf| buf.take());
+        let res = self.append_log_record_with_buf(log_record, &mut enc_buf, sync);
+        if enc_buf.capacity() <= MAX_ENCODE_BUF_CAPACITY {
+            ENCODE_BUF.with(|buf| *buf.borrow_mut() = enc_buf);
+        }
+        res
+    }
 
+    fn append_log_record_with_buf(
+        &self,
+        log_record: &mut LogRecord,
+        enc_record: &mut Vec<u8>,
+        sync: bool,
+    ) -> Result<LogRecordPos> {
         // 输入数据进行编码
-        let enc_record = log_record.encode();
-        let record_len = enc_record.len() as u64;
+        log_record.encode_into(enc_record);
 
-        // 获取到当前活跃文件
-        let mut active_file = self.active_file.write();
+        // 一条记录不会跨越多个数据文件，编码后超过数据文件大小的记录直接拒绝写入
+        // 大小恰好等于数据文件大小的记录会单独占用一个数据文件，末尾对齐的填充不计入记录的大小
+        if enc_record.len() as u64 > self.options.data_file_size {
+            return Err(Errors::ValueTooLarge);
+        }
 
-        if active_file.get_write_off() + record_len > self.options.data_file_size {
-            active_file.sync()?;
+        // 获取到 key 所在的写入分片的活跃文件
+        let shard_file = self.shard_active_file(&log_record.key);
+        let mut active_file = shard_file.write();
 
-            let current_fid = active_file.get_file_id();
-            // 旧的数据文件存储到 map 中
-            let mut older_files = self.older_files.write();
-            let old_file = DataFile::new(dir_path.clone(), current_fid)?;
-            older_files.insert(current_fid, old_file);
+        // 对齐时记录末尾需要填充，填充的长度和写入的位置有关
+        let alignment = self.options.record_alignment;
+        if alignment > 1 {
+            log_record.encode_aligned_into(active_file.get_write_off(), alignment, enc_record);
+        }
+        // 空的活跃文件直接写入，填充之后超过数据文件大小的记录也不需要切换
+        let write_off = active_file.get_write_off();
+        if write_off > 0 && write_off + enc_record.len() as u64 > self.options.data_file_size {
+            // 切换之后数据文件的数量会超过上限时先合并，合并时重新写入的数据可能已经切换了活跃文件
+            if self.reached_max_data_files() {
+                drop(active_file);
+                self.merge_for_max_data_files()?;
+                active_file = shard_file.write();
+                if alignment > 1 {
+                    log_record.encode_aligned_into(
+                        active_file.get_write_off(),
+                        alignment,
+                        enc_record,
+                    );
+                }
+            }
+        }
+        let write_off = active_file.get_write_off();
+        if write_off > 0 && write_off + enc_record.len() as u64 > self.options.data_file_size {
+            self.rotate_active_file(&mut active_file)?;
+            if alignment > 1 {
+                log_record.encode_aligned_into(active_file.get_write_off(), alignment, enc_record);
+            }
+        }
+        let record_len = enc_record.len() as u64;
 
-            // 打开新的数据文件
-            let new_file = DataFile::new(dir_path.clone(), current_fid + 1)?;
-            *active_file = new_file;
+        // 磁盘空间不足时拒绝写入，避免写入一半的记录
+        if self.options.min_free_bytes > 0 {
+            let data_dir = self.options.get_data_dir(active_file.get_file_id());
+            if let Some(available) = available_space(&data_dir)? {
+                if available < record_len.saturating_add(self.options.min_free_bytes) {
+                    return Err(Errors::InsufficientDiskSpace);
+                }
+            }
         }
 
         // 追加写数据到当前活跃文件中
         let write_off = active_file.get_write_off();
-        active_file.write(&enc_record)?;
+        active_file.write(enc_record)?;
 
         // 根据配置项决定是否持久化
-        if self.options.sync_writes {
+        if sync {
             active_file.sync()?;
+            #[cfg(test)]
+            self.sync_count.fetch_add(1, Ordering::SeqCst);
         }
 
         // 构造数据索引信息
         Ok(LogRecordPos {
             file_id: active_file.get_file_id(),
             offset: write_off,
+            inline: None,
+            version: log_record.version,
         })
     }
 
-    /// 从数据文件中加载内存索引
+    // 所有写入分片的活跃文件，第一个是 active_file
+    fn active_files(&self) -> impl Iterator<Item = &Arc<RwLock<DataFile>>> {
+        std::iter::once(&self.active_file).chain(self.shard_files.iter())
+    }
+
+    // 获取所有写入分片的活跃文件的读锁
+    fn read_active_files(&self) -> Vec<RwLockReadGuard<'_, DataFile>> {
+        self.active_files().map(|f| f.read()).collect()
+    }
+
+    // key 所在的写入分片，同一个 key 总是写入同一个分片，同一个 key 的记录在数据文件中仍然按照文件 id 和偏移有序
+    pub(crate) fn shard_of(&self, key: &[u8]) -> usize {
+        let shards = self.write_locks.len();
+        if shards == 1 {
+            return 0;
+        }
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % shards as u64) as usize
+    }
+
+    // key 所在的写入分片的活跃文件
+    fn shard_active_file(&self, key: &[u8]) -> &Arc<RwLock<DataFile>> {
+        match self.shard_of(key) {
+            0 => &self.active_file,
+            shard => &self.shard_files[shard - 1],
+        }
+    }
+
+    // 获取 key 所在的写入分片的写锁，只写入这个 key 的操作使用
+    pub(crate) fn lock_shard(&self, key: &[u8]) -> MutexGuard<'_, ()> {
+        self.write_locks[self.shard_of(key)].lock()
+    }
+
+    // 按照顺序获取所有写入分片的写锁，写入多个 key 或者修改数据文件的操作使用
+    pub(crate) fn lock_all_shards(&self) -> Vec<MutexGuard<'_, ()>> {
+        self.write_locks.iter().map(|lock| lock.lock()).collect()
+    }
+
+    // 再创建一个数据文件是否会超过 max_data_files，合并的过程中不限制
+    fn reached_max_data_files(&self) -> bool {
+        let max_data_files = self.options.max_data_files;
+        max_data_files > 0
+            && !self.merging.load(Ordering::SeqCst)
+            && self.older_files.read().len() + self.write_locks.len() >= max_data_files
+    }
+
+    // 数据文件的数量达到上限时合并所有可以回收空间的旧的数据文件，合并之后仍然达到上限时返回错误
+    // 批量导入的数据还没有写入索引，合并时会被当作无效的数据丢弃，所以批量导入时直接返回错误，调用方需要持有写锁
+    fn merge_for_max_data_files(&self) -> Result<()> {
+        if self.bulk_loading.load(Ordering::SeqCst) {
+            return Err(Errors::TooManyDataFiles);
+        }
+        self.merge_files(usize::MAX)?;
+        if self.reached_max_data_files() {
+            return Err(Errors::TooManyDataFiles);
+        }
+        Ok(())
+    }
+
+    // 持久化当前活跃文件并转换为旧的数据文件，然后打开新的活跃文件
+    fn rotate_active_file(&self, active_file: &mut DataFile) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("rollover", file_id = active_file.get_file_id()).entered();
+        // 封存的文件不会再写入，写入 footer 之后 verify 可以整体校验这个文件
+        active_file.write_footer()?;
+        let current_fid = active_file.get_file_id();
+        let background_sync =
+            match self.options.async_rollover_sync {
+                true => {
+                    // 后台持久化完成之后才算封存
+                    let unsealed_files = self.unsealed_files.clone();
+                    Some(active_file.sync_in_background(move || {
+                        unsealed_files.seal_in_background(current_fid)
+                    })?)
+                }
+                false => {
+                    active_file.sync()?;
+                    None
+                }
+            };
+        active_file.truncate_preallocated()?;
+
+        // 打开新的数据文件，数据文件在多个目录之间轮流存放
+        // 创建之前先记录新的数据文件，同步持久化的旧文件已经封存
+        let new_fid = self.next_file_id.fetch_add(1, Ordering::SeqCst);
+        let sealed = background_sync.is_none();
+        self.unsealed_files.update(|file_ids| {
+            if sealed {
+                file_ids.remove(&current_fid);
+            }
+            file_ids.insert(new_fid);
+        })?;
+        let new_file = self.new_active_file(new_fid)?;
+        if let Some(handle) = background_sync {
+            new_file.set_predecessor_sync(handle);
+        }
+
+        // 旧的数据文件存储到 map 中
+        let mut older_files = self.older_files.write();
+        let mut old_file = std::mem::replace(active_file, new_file);
+        if let Some(io_cache) = &self.io_cache {
+            old_file.use_io_cache(io_cache.clone());
+        }
+        older_files.insert(current_fid, old_file);
+
+        if let Some(observer) = &self.options.observer {
+            observer.on_rollover(new_fid);
+        }
+        Ok(())
+    }
+
+    // 创建新的活跃文件，并根据配置项设置写缓冲区和预分配文件空间
+    fn new_active_file(&self, file_id: u64) -> Result<DataFile> {
+        let data_dir = self.options.get_data_dir(file_id);
+        let mut data_file = DataFile::new(data_dir.clone(), file_id)?;
+        if self.options.sync_dir {
+            sync_dir(&data_dir)?;
+            #[cfg(test)]
+            self.dir_sync_count.fetch_add(1, Ordering::SeqCst);
+        }
+        data_file.set_write_buffer_size(self.options.write_buffer_size);
+        data_file.set_io_buffer_size(self.options.io_buffer_size);
+        if self.options.preallocate {
+            data_file.preallocate(self.options.data_file_size)?;
+        }
+        Ok(data_file)
+    }
+
+    /// 从数据文件中加载内存索引，返回加载的记录数量
     /// 遍历数据文件中的内容，并依次处理其中的记录
-    fn load_index_from_data_files(&self) -> Result<()> {
+    /// last_unsealed 是上一次打开时可能没有封存的数据文件，没有记录时只有最后一个数据文件可能没有封存
+    fn load_index_from_data_files(&self, last_unsealed: &Option<BTreeSet<u64>>) -> Result<usize> {
         // 数据文件为空，直接返回
         if self.file_ids.is_empty() {
-            return Ok(());
+            return Ok(0);
         }
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "load_index_from_data_files",
+            data_files = self.file_ids.len(),
+            records = tracing::field::Empty,
+        )
+        .entered();
+        let mut record_num = 0;
 
         let active_file = self.active_file.read();
         let older_files = self.older_files.read();
 
-        // 遍历每个文件 id，取出对应的数据文件，并加载其中的数据
-        for (i, file_id) in self.file_ids.iter().enumerate() {
-            let mut offset = 0;
-            loop {
-                let log_record_res = match *file_id == active_file.get_file_id() {
-                    true => active_file.read_log_record(offset),
-                    false => {
-                        let data_file = older_files.get(file_id).unwrap();
-                        data_file.read_log_record(offset)
-                    }
-                };
+        // 取出每个文件 id 对应的数据文件
+        let data_files: Vec<&DataFile> = self
+            .file_ids
+            .iter()
+            .map(|file_id| match *file_id == active_file.get_file_id() {
+                true => &*active_file,
+                false => older_files.get(file_id).unwrap(),
+            })
+            .collect();
 
-                let (log_record, size) = match log_record_res {
-                    Ok(result) => (result.record, result.size),
-                    Err(e) => {
-                        if e == Errors::ReadDataFileEOF {
-                            break;
-                        }
-                        return Err(e);
-                    }
-                };
+        // 每次并行读取 load_threads 个数据文件，再按照文件 id 的顺序依次更新索引
+        // 没有封存的数据文件末尾可能有崩溃时没有写完的记录，读取时忽略这部分数据
+        // 有多个写入分片时，每个分片的活跃文件都可能是崩溃时正在写入的文件，文件 id 是所有分片共用的，
+        // 不能根据 id 判断，需要使用记录的数据文件，其他数据文件中无法解析的记录都当作数据损坏
+        let active_file_id = active_file.get_file_id();
+        let is_unsealed = |file_id: u64| match last_unsealed {
+            Some(file_ids) => file_ids.contains(&file_id),
+            None => file_id == active_file_id,
+        };
+        let load_threads = self
+            .options
+            .load_threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        for chunk in data_files.chunks(load_threads) {
+            let results: Vec<Result<(Vec<IndexEntry>, u64, bool)>> = match chunk {
+                [data_file] => vec![self.read_index_entries(data_file, is_unsealed)],
+                _ => thread::scope(|s| {
+                    let handles: Vec<_> = chunk
+                        .iter()
+                        .map(|data_file| {
+                            s.spawn(|| self.read_index_entries(data_file, is_unsealed))
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|handle| handle.join().expect("failed to load data file"))
+                        .collect()
+                }),
+            };
 
-                // 构建内存索引
-                let log_record_pos = LogRecordPos {
-                    file_id: *file_id,
-                    offset,
-                };
+            for (data_file, res) in chunk.iter().zip(results) {
+                let (entries, offset, unsealed) = res?;
+                record_num += entries.len();
+                self.apply_index_entries(entries)?;
 
-                let ok = match log_record.rec_type {
-                    LogRecordType::NORMAL => {
-                        self.index.put(log_record.key.to_vec(), log_record_pos)
+                // 设置活跃文件的 offset，并截断末尾不完整的记录
+                if data_file.get_file_id() == active_file_id {
+                    #[cfg(feature = "tracing")]
+                    let _span = tracing::info_span!(
+                        "recover_active_file",
+                        file_id = active_file_id,
+                        offset
+                    )
+                    .entered();
+                    self.truncate_unsealed_file(&active_file, offset)?;
+                    active_file.resume_digest()?;
+                } else if unsealed {
+                    // 其他写入分片之前的活跃文件成为了旧的数据文件，同样截断末尾不完整的记录，
+                    // 截断之后持久化，之后不再记录为没有封存的数据文件
+                    self.truncate_unsealed_file(data_file, offset)?;
+                    if !self.options.read_only {
+                        data_file.sync()?;
                     }
-                    LogRecordType::DELETED => self.index.delete(log_record.key.to_vec()),
-                };
-
-                if !ok {
-                    return Err(Errors::IndexUpdateFailed);
                 }
+            }
+        }
 
-                // 递增 offset，下一次读取的时候从新的位置开始
-                offset += size as u64;
+        #[cfg(feature = "tracing")]
+        span.record("records", record_num);
+        Ok(record_num)
+    }
+
+    // 设置崩溃时可能正在写入的数据文件的 offset，并截断 offset 之后无法解析的数据，只读模式下不截断
+    fn truncate_unsealed_file(&self, data_file: &DataFile, offset: u64) -> Result<()> {
+        data_file.set_write_off(offset);
+        if !self.options.read_only {
+            // 不截断的话之后追加写入的数据会和末尾无法解析的数据混在一起
+            if !self.options.truncate_on_recovery && data_file.has_data_after(offset)? {
+                return Err(Errors::TrailingDataInActiveFile);
             }
+            data_file.truncate_to_write_off()?;
+        }
+        Ok(())
+    }
+
+    // 读取一个数据文件中所有的记录，返回记录对应的索引、文件中有效数据的末尾位置以及文件是否没有封存
+    // 没有封存的文件末尾不完整的记录会被忽略
+    fn read_index_entries(
+        &self,
+        data_file: &DataFile,
+        is_unsealed: impl Fn(u64) -> bool,
+    ) -> Result<(Vec<IndexEntry>, u64, bool)> {
+        let unsealed = is_unsealed(data_file.get_file_id());
+        // 顺序读取整个文件，开启预读减少读取文件的次数，读取完毕后关闭
+        data_file.set_read_ahead(self.options.read_ahead_size);
+        let res = read_data_file_entries(data_file, unsealed, self.options.inline_value_threshold);
+        data_file.set_read_ahead(0);
+        let (entries, offset) = res?;
+        Ok((entries, offset, unsealed))
+    }
 
-            // 设置活跃文件的 offset
-            if i == self.file_ids.len() - 1 {
-                active_file.set_write_off(offset);
+    // 按照记录的先后顺序更新内存索引
+    fn apply_index_entries(&self, entries: Vec<IndexEntry>) -> Result<()> {
+        let mut batch = Vec::new();
+        for (key, pos) in entries {
+            // 正常数据先攒到批次中，遇到删除记录时先把批次写入索引，保证记录的先后顺序
+            match pos {
+                Some(pos) => batch.push((key, pos)),
+                None => {
+                    if !self.index.bulk_put(std::mem::take(&mut batch)) {
+                        return Err(Errors::IndexUpdateFailed);
+                    }
+                    // merge_partial 删除了更早的文件之后，删除记录对应的 key 可能已经不在索引中
+                    self.index.delete(key);
+                }
             }
         }
 
+        // 文件读取完毕后批量写入索引
+        if !self.index.bulk_put(batch) {
+            return Err(Errors::IndexUpdateFailed);
+        }
         Ok(())
     }
 }
 
-// 从数据目录中加载数据文件
-fn load_data_files(dir_path: PathBuf) -> Result<Vec<DataFile>> {
-    // 读取数据目录
-    let dir = fs::read_dir(dir_path.clone());
-    if dir.is_err() {
-        return Err(Errors::FailedToReadDatabaseDir);
+// 所有写入分片的活跃文件中最小的文件 id
+fn min_file_id(active_files: &[RwLockReadGuard<'_, DataFile>]) -> u64 {
+    active_files
+        .iter()
+        .map(|f| f.get_file_id())
+        .min()
+        .unwrap_or(INITIAL_FILE_ID)
+}
+
+// 数据文件中一条记录对应的索引，删除记录的位置为 None
+type IndexEntry = (Vec<u8>, Option<LogRecordPos>);
+
+// 从一个数据文件中读取所有记录对应的索引，返回文件中有效数据的末尾位置
+// is_active 为 true 时，把不完整的记录当作崩溃时没有写完的数据，之后的内容全部忽略
+// 预分配的文件中 crc 校验失败并且之后全部是 0 的记录同样当作没有写完的数据
+fn read_data_file_entries(
+    data_file: &DataFile,
+    is_active: bool,
+    inline_value_threshold: usize,
+) -> Result<(Vec<IndexEntry>, u64)> {
+    let file_id = data_file.get_file_id();
+    let mut offset = 0;
+    let mut entries = Vec::new();
+    loop {
+        let (log_record, size) = match data_file.read_log_record_with_crc(offset) {
+            // 完整的记录 crc 校验失败说明数据损坏，不能当作没有写完的记录忽略
+            // 预分配的活跃文件末尾填充了 0，没有写完的记录也能读出完整的长度，
+            // 记录之后没有其他数据时同样当作没有写完的记录
+            Ok((result, crc)) if crc != result.crc => {
+                if is_active && !data_file.has_data_after(offset + result.size as u64)? {
+                    warn!(
+                        "ignore torn log record at the end of data file {}, offset {}",
+                        file_id, offset
+                    );
+                    break;
+                }
+                return Err(Errors::InvalidLogRecordCrc);
+            }
+            Ok((result, _)) => (result.record, result.size),
+            Err(Errors::ReadDataFileEOF) => break,
+            // header 无法解析或者记录超出了文件的末尾
+            Err(Errors::InvalidLogRecordCrc) if is_active => {
+                warn!(
+                    "ignore incomplete log record at the end of data file {}, offset {}",
+                    file_id, offset
+                );
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+
+        // 构建内存索引
+        let log_record_pos = LogRecordPos {
+            file_id,
+            offset,
+            inline: None,
+            version: log_record.version,
+        };
+        match log_record.rec_type {
+            LogRecordType::NORMAL => {
+                let pos =
+                    log_record_pos.with_inline_value(&log_record.value, inline_value_threshold);
+                entries.push((log_record.key, Some(pos)))
+            }
+            LogRecordType::DELETED => entries.push((log_record.key, None)),
+            LogRecordType::FOOTER => {}
+        }
+
+        // 递增 offset，下一次读取的时候从新的位置开始
+        offset += size as u64;
+    }
+
+    Ok((entries, offset))
+}
+
+// 校验目录，如果目录不存在的话就创建这个目录
+fn prepare_dir(dir_path: &Path, read_only: bool) -> Result<()> {
+    // 路径已经存在但不是目录，直接返回错误
+    if dir_path.exists() && !dir_path.is_dir() {
+        return Err(Errors::DirPathIsNotDirectory);
+    }
+
+    // 只读模式下不创建目录，目录不存在时直接返回错误
+    if !dir_path.is_dir() {
+        if read_only {
+            return Err(Errors::FailedToReadDatabaseDir);
+        }
+        if let Err(e) = fs::create_dir_all(dir_path) {
+            warn!("create database directory err: {}", e);
+            return Err(Errors::FailedToCreateDatabaseDir);
+        }
+    }
+
+    Ok(())
+}
+
+// 获取数据库目录的文件锁，锁被其他进程持有时在 timeout 内不断重试，超时返回 DatabaseIsUsing
+fn lock_dir(dir_path: &Path, timeout: Option<Duration>) -> Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir_path.join(FILE_LOCK_NAME))?;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    while !try_lock_exclusive(&file)? {
+        match deadline {
+            Some(deadline) if Instant::now() < deadline => thread::sleep(LOCK_RETRY_INTERVAL),
+            _ => return Err(Errors::DatabaseIsUsing),
+        }
+    }
+    Ok(file)
+}
+
+// 持久化目录，保证目录中新创建的文件在崩溃后仍然存在
+fn sync_dir(dir_path: &Path) -> Result<()> {
+    if let Err(e) = fs::File::open(dir_path).and_then(|dir| dir.sync_all()) {
+        warn!("sync database directory err: {}", e);
+        return Err(Errors::FailedToSyncDatabaseDir);
     }
+    Ok(())
+}
 
-    let mut file_ids: Vec<u32> = Vec::new();
+// 从数据目录中加载数据文件，数据文件可能分布在多个目录中
+// 设置了 io_cache 时，除了最后一个活跃文件，其他的数据文件在访问时才打开
+fn load_data_files(
+    dir_paths: Vec<PathBuf>,
+    io_cache: &Option<Arc<IOManagerCache>>,
+    skip_unparsable_files: bool,
+    read_only: bool,
+) -> Result<Vec<DataFile>> {
     let mut data_files: Vec<DataFile> = Vec::new();
 
-    for file in dir.unwrap() {
-        if let Ok(entry) = file {
+    // 遍历所有文件 id，依次打开对应的数据文件
+    let file_ids = list_data_files(dir_paths, skip_unparsable_files)?;
+    let file_num = file_ids.len();
+    for (i, (file_id, dir_path)) in file_ids.into_iter().enumerate() {
+        let data_file = match io_cache {
+            Some(io_cache) if i + 1 < file_num => {
+                DataFile::new_cached(dir_path, file_id, io_cache.clone())
+            }
+            // 只读模式下以只读方式打开，只读的文件和只读的文件系统上也可以打开
+            _ if read_only => DataFile::open_read_only(dir_path, file_id)?,
+            _ => DataFile::new(dir_path, file_id)?,
+        };
+        data_files.push(data_file);
+    }
+
+    Ok(data_files)
+}
+
+// 列出所有目录中的数据文件 id 以及所在的目录，按照 id 从小到大排序
+// skip_unparsable_files 为 true 时跳过文件名不是数字的数据文件，否则返回错误
+fn list_data_files(
+    dir_paths: Vec<PathBuf>,
+    skip_unparsable_files: bool,
+) -> Result<Vec<(u64, PathBuf)>> {
+    let mut file_ids: Vec<(u64, PathBuf)> = Vec::new();
+
+    for dir_path in dir_paths {
+        // 读取数据目录
+        let dir = fs::read_dir(dir_path.clone());
+        if dir.is_err() {
+            return Err(Errors::FailedToReadDatabaseDir);
+        }
+
+        for entry in dir.unwrap().flatten() {
             // 拿到文件名
             let file_os_str = entry.file_name();
             let file_name = file_os_str.to_str().unwrap();
@@ -322,42 +1978,27 @@ fn load_data_files(dir_path: PathBuf) -> Result<Vec<DataFile>> {
             // 判断文件是否以.data 结尾
             if file_name.ends_with(DATA_FILE_NAME_SUFFIX) {
                 let split_name: Vec<&str> = file_name.split(".").collect();
-                let file_id = match split_name[0].parse::<u32>() {
+                let file_id = match split_name[0].parse::<u64>() {
                     Ok(fid) => fid,
+                    Err(_) if skip_unparsable_files => {
+                        warn!("skip unparsable data file: {}", entry.path().display());
+                        continue;
+                    }
                     Err(_) => {
                         return Err(Errors::DataDirectoryCorrupted);
                     }
                 };
-                file_ids.push(file_id);
+                file_ids.push((file_id, dir_path.clone()));
             }
         }
     }
 
-    // 如果没有数据文件则直接返回
-    if file_ids.is_empty() {
-        return Ok(data_files);
-    }
-
     // 对文件 id 进行排序，从小到大进行加载
     file_ids.sort();
-    // 遍历所有文件 id，依次打开对应的数据文件
-    for file_id in file_ids.iter() {
-        let data_file = DataFile::new(dir_path.clone(), *file_id)?;
-        data_files.push(data_file);
-    }
-
-    Ok(data_files)
-}
-
-fn check_options(opts: &Options) -> Option<Errors> {
-    let dir_path = opts.dir_path.to_str();
-    if dir_path.is_none() || dir_path.unwrap().len() == 0 {
-        return Some(Errors::DirPathIsEmpty);
-    }
-
-    if opts.data_file_size <= 0 {
-        return Some(Errors::DataFileSizeTooSmall);
+    // 不同目录中出现了相同 id 的数据文件
+    if file_ids.windows(2).any(|w| w[0].0 == w[1].0) {
+        return Err(Errors::DataDirectoryCorrupted);
     }
 
-    None
+    Ok(file_ids)
 }
@@ -1,18 +1,36 @@
 use bytes::Bytes;
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use crate::{
+    data::{
+        data_file::DataFile,
+        log_record::{LogRecordPos, LogRecordType},
+    },
     db::Engine,
     errors::Errors,
-    options::Options,
-    util::rand_kv::{get_test_key, get_test_value},
+    meta::{UnsealedFiles, FORMAT_VERSION, META_FILE_NAME, UNSEALED_FILE_NAME},
+    observer::EngineObserver,
+    options::{ChecksumKind, IndexType, IteratorOptions, Options},
+    util::{
+        key::decode_u64_be,
+        rand_kv::{get_test_key, get_test_value},
+    },
 };
 
 #[test]
 fn test_engine_put() {
-    let mut opts = Options::default();
-    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-put");
-    opts.data_file_size = 64 * 1024 * 1024;
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-put"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
     let engine = Engine::open(opts.clone()).expect("failed to open engine");
 
     // 1.正常 Put 一条数据
@@ -20,7 +38,7 @@ fn test_engine_put() {
     assert!(res1.is_ok());
     let res2 = engine.get(get_test_key(11));
     assert!(res2.is_ok());
-    assert!(res2.unwrap().len() > 0);
+    assert!(!res2.unwrap().is_empty());
 
     // 2.重复 Put key 相同的数据
     let res3 = engine.put(get_test_key(22), get_test_value(22));
@@ -64,9 +82,11 @@ fn test_engine_put() {
 
 #[test]
 fn test_engine_get() {
-    let mut opts = Options::default();
-    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-get");
-    opts.data_file_size = 64 * 1024 * 1024;
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-get"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
     let engine = Engine::open(opts.clone()).expect("failed to open engine");
 
     // 1.正常读取一条数据
@@ -74,7 +94,7 @@ fn test_engine_get() {
     assert!(res1.is_ok());
     let res2 = engine.get(get_test_key(111));
     assert!(res2.is_ok());
-    assert!(res2.unwrap().len() > 0);
+    assert!(!res2.unwrap().is_empty());
 
     // 2.读取一个不存在的 key
     let res3 = engine.get(Bytes::from("not existed key"));
@@ -121,9 +141,11 @@ fn test_engine_get() {
 
 #[test]
 fn test_engine_delete() {
-    let mut opts = Options::default();
-    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-delete");
-    opts.data_file_size = 64 * 1024 * 1024;
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-delete"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
     let engine = Engine::open(opts.clone()).expect("failed to open engine");
 
     // 1.正常删除一个存在的 key
@@ -167,9 +189,11 @@ fn test_engine_delete() {
 
 #[test]
 fn test_engine_close() {
-    let mut opts = Options::default();
-    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-close");
-    opts.data_file_size = 64 * 1024 * 1024;
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-close"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
     let engine = Engine::open(opts.clone()).expect("failed to open engine");
 
     let res1 = engine.put(get_test_key(222), get_test_value(222));
@@ -184,9 +208,11 @@ fn test_engine_close() {
 
 #[test]
 fn test_engine_sync() {
-    let mut opts = Options::default();
-    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-sync");
-    opts.data_file_size = 64 * 1024 * 1024;
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-sync"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
     let engine = Engine::open(opts.clone()).expect("failed to open engine");
 
     let res1 = engine.put(get_test_key(222), get_test_value(222));
@@ -198,3 +224,3691 @@ fn test_engine_sync() {
     // 删除测试的文件夹
     std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
 }
+
+#[test]
+fn test_engine_read_only() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-read-only"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let res1 = engine.put(get_test_key(11), get_test_value(11));
+    assert!(res1.is_ok());
+    let res2 = engine.put(get_test_key(22), get_test_value(22));
+    assert!(res2.is_ok());
+    let res3 = engine.delete(get_test_key(22));
+    assert!(res3.is_ok());
+    std::mem::drop(engine);
+
+    // 1.只读模式下可以正常读取已有的数据
+    let mut ro_opts = opts.clone();
+    ro_opts.read_only = true;
+    let ro_engine = Engine::open(ro_opts.clone()).expect("failed to open engine");
+    let res4 = ro_engine.get(get_test_key(11));
+    assert_eq!(get_test_value(11), res4.unwrap());
+    let res5 = ro_engine.get(get_test_key(22));
+    assert_eq!(Errors::KeyNotFound, res5.err().unwrap());
+
+    // 2.只读模式下写入和删除都会被拒绝
+    let res6 = ro_engine.put(get_test_key(33), get_test_value(33));
+    assert_eq!(Errors::ReadOnlyDatabase, res6.err().unwrap());
+    let res7 = ro_engine.delete(get_test_key(11));
+    assert_eq!(Errors::ReadOnlyDatabase, res7.err().unwrap());
+    let res8 = ro_engine.get(get_test_key(11));
+    assert_eq!(get_test_value(11), res8.unwrap());
+
+    // 3.只读模式下不会创建不存在的目录
+    let mut ro_opts2 = ro_opts.clone();
+    ro_opts2.dir_path = PathBuf::from("/tmp/bitcask-rs-read-only-not-exist");
+    let res9 = Engine::open(ro_opts2.clone());
+    assert_eq!(Errors::FailedToReadDatabaseDir, res9.err().unwrap());
+    assert!(!ro_opts2.dir_path.exists());
+
+    // 4.只读模式下打开没有数据文件的目录不会创建任何文件
+    let list_dir = |path: &PathBuf| {
+        let mut names = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+    std::fs::create_dir_all(&ro_opts2.dir_path).expect("failed to create dir");
+    let names = list_dir(&ro_opts2.dir_path);
+    let ro_engine2 = Engine::open(ro_opts2.clone()).expect("failed to open engine");
+    let res10 = ro_engine2.get(get_test_key(11));
+    assert_eq!(Errors::KeyNotFound, res10.err().unwrap());
+    let res11 = ro_engine2.put(get_test_key(11), get_test_value(11));
+    assert_eq!(Errors::ReadOnlyDatabase, res11.err().unwrap());
+    assert_eq!(0, ro_engine2.list_keys().unwrap().len());
+    std::mem::drop(ro_engine2);
+    assert_eq!(names, list_dir(&ro_opts2.dir_path));
+    assert!(names.is_empty());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(ro_opts2.clone().dir_path).expect("failed to remove path");
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_read_only_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-read-only-permissions"),
+        data_file_size: 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert!(engine.close().is_ok());
+    std::mem::drop(engine);
+
+    // 数据文件和目录都改为只读，之后只读模式下仍然可以打开
+    let set_mode = |mode: u32, dir_mode: u32| {
+        for entry in std::fs::read_dir(&opts.dir_path).unwrap() {
+            let path = entry.unwrap().path();
+            let res = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode));
+            assert!(res.is_ok());
+        }
+        let res =
+            std::fs::set_permissions(&opts.dir_path, std::fs::Permissions::from_mode(dir_mode));
+        assert!(res.is_ok());
+    };
+    set_mode(0o444, 0o555);
+    let file_num = std::fs::read_dir(&opts.dir_path).unwrap().count();
+    assert!(file_num > 2);
+
+    // 所有的数据文件都打开，以及限制打开的文件数量时按需打开
+    for max_open_files in [0, 2] {
+        let ro_opts = Options {
+            read_only: true,
+            max_open_files,
+            ..opts.clone()
+        };
+        let ro_engine = Engine::open(ro_opts).expect("failed to open engine");
+        for i in 0..100 {
+            let res = ro_engine.get(get_test_key(i));
+            assert_eq!(get_test_value(i), res.unwrap());
+        }
+        let res = ro_engine.put(get_test_key(100), get_test_value(100));
+        assert_eq!(Errors::ReadOnlyDatabase, res.err().unwrap());
+        std::mem::drop(ro_engine);
+        assert_eq!(file_num, std::fs::read_dir(&opts.dir_path).unwrap().count());
+    }
+
+    // 删除测试的文件夹
+    set_mode(0o644, 0o755);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_put_if_absent() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-put-if-absent"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.key 不存在时写入成功
+    let res1 = engine.put_if_absent(get_test_key(11), get_test_value(11));
+    assert!(res1.unwrap());
+    let res2 = engine.get(get_test_key(11));
+    assert_eq!(get_test_value(11), res2.unwrap());
+
+    // 2.key 已存在时不写入
+    let res3 = engine.put_if_absent(get_test_key(11), Bytes::from("a new value"));
+    assert!(!res3.unwrap());
+    let res4 = engine.get(get_test_key(11));
+    assert_eq!(get_test_value(11), res4.unwrap());
+
+    // 3.key 被删除之后可以重新写入
+    let res5 = engine.delete(get_test_key(11));
+    assert!(res5.is_ok());
+    let res6 = engine.put_if_absent(get_test_key(11), Bytes::from("a new value"));
+    assert!(res6.unwrap());
+    let res7 = engine.get(get_test_key(11));
+    assert_eq!(Bytes::from("a new value"), res7.unwrap());
+
+    // 4.key 为空
+    let res8 = engine.put_if_absent(Bytes::new(), get_test_value(22));
+    assert_eq!(Errors::KeyIsEmpty, res8.err().unwrap());
+
+    // 5.多个线程并发写入同一个 key，只有一个能够成功
+    let engine = Arc::new(engine);
+    let mut handles = Vec::new();
+    for i in 0..8 {
+        let engine = engine.clone();
+        handles.push(std::thread::spawn(move || {
+            engine
+                .put_if_absent(get_test_key(33), get_test_value(i))
+                .expect("failed to put if absent")
+        }));
+    }
+    let mut success = 0;
+    for handle in handles {
+        if handle.join().unwrap() {
+            success += 1;
+        }
+    }
+    assert_eq!(1, success);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_insert() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-insert"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.key 不存在时写入成功
+    let res1 = engine.insert(get_test_key(11), get_test_value(11));
+    assert!(res1.is_ok());
+
+    // 2.key 已存在时返回错误，不覆盖已有的 value
+    let res2 = engine.insert(get_test_key(11), Bytes::from("a new value"));
+    assert_eq!(Errors::KeyAlreadyExists, res2.err().unwrap());
+    assert_eq!(get_test_value(11), engine.get(get_test_key(11)).unwrap());
+
+    // 3.key 被删除之后可以重新写入
+    let res3 = engine.delete(get_test_key(11));
+    assert!(res3.is_ok());
+    let res4 = engine.insert(get_test_key(11), Bytes::from("a new value"));
+    assert!(res4.is_ok());
+    assert_eq!(
+        Bytes::from("a new value"),
+        engine.get(get_test_key(11)).unwrap()
+    );
+
+    // 4.key 为空
+    let res5 = engine.insert(Bytes::new(), get_test_value(22));
+    assert_eq!(Errors::KeyIsEmpty, res5.err().unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_get_or_insert_with() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-get-or-insert-with"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.key 不存在时写入 f 返回的 value
+    let res1 = engine.get_or_insert_with(get_test_key(11), || get_test_value(11));
+    assert_eq!(get_test_value(11), res1.unwrap());
+    assert_eq!(get_test_value(11), engine.get(get_test_key(11)).unwrap());
+
+    // 2.key 已存在时返回已有的 value，不调用 f
+    let res2 = engine.get_or_insert_with(get_test_key(11), || panic!("should not be called"));
+    assert_eq!(get_test_value(11), res2.unwrap());
+
+    // 3.key 为空
+    let res3 = engine.get_or_insert_with(Bytes::new(), || get_test_value(22));
+    assert_eq!(Errors::KeyIsEmpty, res3.err().unwrap());
+
+    // 4.多个线程并发获取同一个不存在的 key，只调用一次 f，所有线程得到相同的 value
+    let engine = Arc::new(engine);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut handles = Vec::new();
+    for i in 0..8 {
+        let engine = engine.clone();
+        let calls = calls.clone();
+        handles.push(std::thread::spawn(move || {
+            engine
+                .get_or_insert_with(get_test_key(33), || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    get_test_value(i)
+                })
+                .expect("failed to get or insert")
+        }));
+    }
+    let values: Vec<Bytes> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+    assert!(values.iter().all(|v| *v == values[0]));
+    assert_eq!(values[0], engine.get(get_test_key(33)).unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_open_dir_path_is_file() {
+    let mut opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-dir-path-is-file"),
+        ..Default::default()
+    };
+    std::fs::write(opts.dir_path.clone(), "not a directory").expect("failed to create file");
+
+    let res1 = Engine::open(opts.clone());
+    assert_eq!(Errors::DirPathIsNotDirectory, res1.err().unwrap());
+
+    // 只读模式下同样返回该错误
+    opts.read_only = true;
+    let res2 = Engine::open(opts.clone());
+    assert_eq!(Errors::DirPathIsNotDirectory, res2.err().unwrap());
+
+    // 删除测试的文件
+    std::fs::remove_file(opts.clone().dir_path).expect("failed to remove file");
+}
+
+#[test]
+fn test_engine_reopen_write_off() {
+    let mut opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-reopen-write-off"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let data_file_len = |file_id: u64| {
+        let file_name = opts.dir_path.join(std::format!("{:09}.data", file_id));
+        std::fs::metadata(file_name).unwrap().len()
+    };
+
+    // 1.活跃文件是新创建的空文件，重启后从 0 开始写入
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    std::mem::drop(engine);
+    assert_eq!(0, data_file_len(0));
+
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    let res1 = engine2.put(get_test_key(11), get_test_value(11));
+    assert!(res1.is_ok());
+    let pos1 = engine2.index.get(get_test_key(11).to_vec()).unwrap();
+    assert_eq!(0, pos1.file_id);
+    assert_eq!(0, pos1.offset);
+    let res2 = engine2.put(get_test_key(22), get_test_value(22));
+    assert!(res2.is_ok());
+    std::mem::drop(engine2);
+
+    // 2.活跃文件中已有数据，重启后追加写到最后一条数据之后
+    let file_len = data_file_len(0);
+    let engine3 = Engine::open(opts.clone()).expect("failed to open engine");
+    let res3 = engine3.put(get_test_key(33), get_test_value(33));
+    assert!(res3.is_ok());
+    let pos3 = engine3.index.get(get_test_key(33).to_vec()).unwrap();
+    assert_eq!(0, pos3.file_id);
+    assert_eq!(file_len, pos3.offset);
+    std::mem::drop(engine3);
+
+    let engine4 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(get_test_value(11), engine4.get(get_test_key(11)).unwrap());
+    assert_eq!(get_test_value(22), engine4.get(get_test_key(22)).unwrap());
+    assert_eq!(get_test_value(33), engine4.get(get_test_key(33)).unwrap());
+    std::mem::drop(engine4);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+
+    // 3.存在多个数据文件，重启后追加写到最新的数据文件中
+    opts.data_file_size = 4 * 1024;
+    let engine5 = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..200 {
+        let res = engine5.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let last_pos = engine5.index.get(get_test_key(199).to_vec()).unwrap();
+    assert!(last_pos.file_id > 0);
+    std::mem::drop(engine5);
+
+    let file_len = data_file_len(last_pos.file_id);
+    let engine6 = Engine::open(opts.clone()).expect("failed to open engine");
+    let res4 = engine6.put(get_test_key(1000), get_test_value(1000));
+    assert!(res4.is_ok());
+    let pos4 = engine6.index.get(get_test_key(1000).to_vec()).unwrap();
+    assert_eq!(last_pos.file_id, pos4.file_id);
+    assert_eq!(file_len, pos4.offset);
+    std::mem::drop(engine6);
+
+    let engine7 = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..200 {
+        assert_eq!(get_test_value(i), engine7.get(get_test_key(i)).unwrap());
+    }
+    assert_eq!(
+        get_test_value(1000),
+        engine7.get(get_test_key(1000)).unwrap()
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_multi_data_dirs() {
+    let data_dirs = vec![
+        PathBuf::from("/tmp/bitcask-rs-multi-dirs-1"),
+        PathBuf::from("/tmp/bitcask-rs-multi-dirs-2"),
+    ];
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-multi-dirs"),
+        data_dirs: data_dirs.clone(),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..500 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 0..500 {
+        assert_eq!(get_test_value(i), engine.get(get_test_key(i)).unwrap());
+    }
+    std::mem::drop(engine);
+
+    // 数据文件分布在两个目录中
+    for data_dir in data_dirs.iter() {
+        let files = std::fs::read_dir(data_dir)
+            .expect("failed to read dir")
+            .count();
+        assert!(files > 1);
+    }
+
+    // 重启后所有的数据都能读取到
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..500 {
+        assert_eq!(get_test_value(i), engine2.get(get_test_key(i)).unwrap());
+    }
+    let res1 = engine2.put(get_test_key(1000), get_test_value(1000));
+    assert!(res1.is_ok());
+    assert_eq!(
+        get_test_value(1000),
+        engine2.get(get_test_key(1000)).unwrap()
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    for data_dir in data_dirs {
+        std::fs::remove_dir_all(data_dir).expect("failed to remove path");
+    }
+}
+
+#[test]
+fn test_engine_stat() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-stat"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let stat1 = engine.stat().unwrap();
+    assert_eq!(1, stat1.data_file_num);
+    assert_eq!(0, stat1.disk_size);
+
+    let res1 = engine.put(get_test_key(11), get_test_value(11));
+    assert!(res1.is_ok());
+    let stat2 = engine.stat().unwrap();
+    assert_eq!(1, stat2.data_file_num);
+    assert!(stat2.disk_size > stat1.disk_size);
+
+    // 写入的数据转换到新的数据文件中
+    for i in 0..200 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let stat3 = engine.stat().unwrap();
+    assert!(stat3.data_file_num > 1);
+    assert!(stat3.disk_size > stat2.disk_size);
+
+    // 磁盘空间大小和数据目录中的数据文件大小一致
+    let mut dir_size = 0;
+    for entry in std::fs::read_dir(opts.dir_path.clone()).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_name().to_str().unwrap().ends_with(".data") {
+            dir_size += entry.metadata().unwrap().len();
+        }
+    }
+    assert_eq!(dir_size, stat3.disk_size);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_verify() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-verify"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.空的数据库
+    let report1 = engine.verify().unwrap();
+    assert_eq!(0, report1.valid_records);
+    assert_eq!(0, report1.invalid_records);
+    assert!(report1.first_invalid.is_none());
+
+    // 2.数据没有损坏的情况
+    for i in 0..200 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let res1 = engine.delete(get_test_key(0));
+    assert!(res1.is_ok());
+    let report2 = engine.verify().unwrap();
+    assert_eq!(201, report2.valid_records);
+    assert_eq!(0, report2.invalid_records);
+    assert!(report2.first_invalid.is_none());
+
+    // 3.篡改旧的数据文件中一条记录的 value
+    let pos = engine.index.get(get_test_key(10).to_vec()).unwrap();
+    assert!(
+        pos.file_id
+            < engine
+                .index
+                .get(get_test_key(199).to_vec())
+                .unwrap()
+                .file_id
+    );
+    let file_name = opts.dir_path.join(std::format!("{:09}.data", pos.file_id));
+    let mut content = std::fs::read(file_name.clone()).unwrap();
+    content[pos.offset as usize + 40] ^= 0xff;
+    std::fs::write(file_name, content).unwrap();
+
+    let report3 = engine.verify().unwrap();
+    assert_eq!(200, report3.valid_records);
+    assert_eq!(1, report3.invalid_records);
+    assert_eq!(Some((pos.file_id, pos.offset)), report3.first_invalid);
+
+    // 校验不会修改内存索引
+    assert_eq!(get_test_value(11), engine.get(get_test_key(11)).unwrap());
+    assert_eq!(
+        Errors::InvalidLogRecordCrc,
+        engine.get(get_test_key(10)).err().unwrap()
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_put_value_too_large() {
+    let mut opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-value-too-large"),
+        ..Default::default()
+    };
+    // key 为 1 个字节、版本为 1 时，编码后的记录大小为 header(4) + key(1) + value + crc(4)
+    opts.data_file_size = 64;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.记录大小恰好等于数据文件大小
+    let value = Bytes::from(vec![b'v'; 55]);
+    let res1 = engine.put(Bytes::from("a"), value.clone());
+    assert!(res1.is_ok());
+    let res2 = engine.put(Bytes::from("b"), value.clone());
+    assert!(res2.is_ok());
+    assert_eq!(2, engine.stat().unwrap().data_file_num);
+    assert_eq!(value, engine.get(Bytes::from("a")).unwrap());
+    assert_eq!(value, engine.get(Bytes::from("b")).unwrap());
+
+    // 2.记录大小超过数据文件大小
+    let res3 = engine.put(Bytes::from("c"), Bytes::from(vec![b'v'; 56]));
+    assert_eq!(Errors::ValueTooLarge, res3.err().unwrap());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get(Bytes::from("c")).err().unwrap()
+    );
+    assert_eq!(2, engine.stat().unwrap().data_file_num);
+
+    // 3.重启后数据不受影响
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(value, engine2.get(Bytes::from("a")).unwrap());
+    assert_eq!(value, engine2.get(Bytes::from("b")).unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_rotate() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-rotate"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let res1 = engine.put(get_test_key(11), get_test_value(11));
+    assert!(res1.is_ok());
+    assert_eq!(
+        0,
+        engine.index.get(get_test_key(11).to_vec()).unwrap().file_id
+    );
+
+    // 1.封存当前活跃文件，新的数据写入到新的数据文件中
+    let rotate_res1 = engine.rotate();
+    assert!(rotate_res1.is_ok());
+    assert_eq!(2, engine.stat().unwrap().data_file_num);
+    assert!(opts.dir_path.join("000000001.data").is_file());
+
+    let res2 = engine.put(get_test_key(22), get_test_value(22));
+    assert!(res2.is_ok());
+    assert_eq!(
+        1,
+        engine.index.get(get_test_key(22).to_vec()).unwrap().file_id
+    );
+
+    // 2.旧的数据仍然可以从封存的文件中读取
+    assert_eq!(get_test_value(11), engine.get(get_test_key(11)).unwrap());
+    assert_eq!(get_test_value(22), engine.get(get_test_key(22)).unwrap());
+
+    // 3.活跃文件为空时也可以封存
+    let rotate_res2 = engine.rotate();
+    assert!(rotate_res2.is_ok());
+    let rotate_res3 = engine.rotate();
+    assert!(rotate_res3.is_ok());
+    assert_eq!(4, engine.stat().unwrap().data_file_num);
+
+    // 4.重启后数据都能读取到
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(get_test_value(11), engine2.get(get_test_key(11)).unwrap());
+    assert_eq!(get_test_value(22), engine2.get(get_test_key(22)).unwrap());
+    let res3 = engine2.put(get_test_key(33), get_test_value(33));
+    assert!(res3.is_ok());
+    assert_eq!(
+        3,
+        engine2
+            .index
+            .get(get_test_key(33).to_vec())
+            .unwrap()
+            .file_id
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_write_buffer() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-write-buffer"),
+        write_buffer_size: 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.写入大量的小数据，数据都还在缓冲区中，没有写入文件
+    for i in 0..1000 {
+        let res = engine.put(get_test_key(i), Bytes::from(format!("v-{}", i)));
+        assert!(res.is_ok());
+    }
+    assert_eq!(0, engine.stat().unwrap().disk_size);
+
+    // 2.sync 之前也能读取到缓冲区中的数据
+    for i in 0..1000 {
+        let res = engine.get(get_test_key(i));
+        assert_eq!(Bytes::from(format!("v-{}", i)), res.unwrap());
+    }
+    let res1 = engine.delete(get_test_key(10));
+    assert!(res1.is_ok());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get(get_test_key(10)).err().unwrap()
+    );
+
+    // 3.sync 之后数据写入文件
+    let sync_res = engine.sync();
+    assert!(sync_res.is_ok());
+    assert!(engine.stat().unwrap().disk_size > 0);
+    assert_eq!(Bytes::from("v-11"), engine.get(get_test_key(11)).unwrap());
+
+    // 4.关闭数据库时缓冲区中剩余的数据也会写入文件
+    let res2 = engine.put(get_test_key(2000), get_test_value(2000));
+    assert!(res2.is_ok());
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(
+        get_test_value(2000),
+        engine2.get(get_test_key(2000)).unwrap()
+    );
+    assert_eq!(
+        Bytes::from("v-999"),
+        engine2.get(get_test_key(999)).unwrap()
+    );
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine2.get(get_test_key(10)).err().unwrap()
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_clear() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-clear"),
+        data_file_size: 64 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..1000 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert!(engine.stat().unwrap().data_file_num > 1);
+    let last_file_id = engine.active_file.read().get_file_id();
+
+    // 1.清空之后没有任何数据
+    let clear_res = engine.clear();
+    assert!(clear_res.is_ok());
+    assert!(engine.list_keys().unwrap().is_empty());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get(get_test_key(1)).err().unwrap()
+    );
+    let stat = engine.stat().unwrap();
+    assert_eq!(1, stat.data_file_num);
+    assert_eq!(0, stat.disk_size);
+
+    // 2.清空之后可以继续写入，新的数据文件不会复用之前的文件 id
+    let res1 = engine.put(get_test_key(2000), get_test_value(2000));
+    assert!(res1.is_ok());
+    assert_eq!(
+        last_file_id + 1,
+        engine
+            .index
+            .get(get_test_key(2000).to_vec())
+            .unwrap()
+            .file_id
+    );
+
+    // 3.重启之后只有清空之后写入的数据
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(1, engine2.list_keys().unwrap().len());
+    assert_eq!(
+        get_test_value(2000),
+        engine2.get(get_test_key(2000)).unwrap()
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_open_with_report() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-open-report"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+
+    // 1.空的数据库
+    let (engine, report1) = Engine::open_with_report(opts.clone()).expect("failed to open engine");
+    assert_eq!(0, report1.data_file_num);
+    assert_eq!(0, report1.record_num);
+
+    // 2.写入 100 条数据，删除 10 条，并分布在两个数据文件中
+    for i in 0..50 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let rotate_res = engine.rotate();
+    assert!(rotate_res.is_ok());
+    for i in 50..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 0..10 {
+        let res = engine.delete(get_test_key(i));
+        assert!(res.is_ok());
+    }
+    std::mem::drop(engine);
+
+    let (engine2, report2) = Engine::open_with_report(opts.clone()).expect("failed to open engine");
+    assert_eq!(2, report2.data_file_num);
+    assert_eq!(110, report2.record_num);
+    assert_eq!(90, engine2.list_keys().unwrap().len());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_checksum_kind() {
+    let mut opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-checksum"),
+        checksum: ChecksumKind::XxHash64,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    std::mem::drop(engine);
+
+    // 换一种校验算法重新打开，之前写入的数据仍然可以读取
+    opts.checksum = ChecksumKind::Crc32c;
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    let res2 = engine2.put(get_test_key(2), get_test_value(2));
+    assert!(res2.is_ok());
+    std::mem::drop(engine2);
+
+    let engine3 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(get_test_value(1), engine3.get(get_test_key(1)).unwrap());
+    assert_eq!(get_test_value(2), engine3.get(get_test_key(2)).unwrap());
+    assert_eq!(2, engine3.verify().unwrap().valid_records);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_update() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-update"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.key 不存在时 f 拿到的是 None
+    let res1 = engine.update(get_test_key(11), |v| {
+        assert!(v.is_none());
+        Some(get_test_value(11))
+    });
+    assert!(res1.is_ok());
+    assert_eq!(get_test_value(11), engine.get(get_test_key(11)).unwrap());
+
+    // 2.f 返回 None 时删除 key
+    let res2 = engine.update(get_test_key(11), |v| {
+        assert_eq!(Some(get_test_value(11)), v);
+        None
+    });
+    assert!(res2.is_ok());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get(get_test_key(11)).err().unwrap()
+    );
+
+    // 3.key 为空
+    let res3 = engine.update(Bytes::new(), |v| v);
+    assert_eq!(Errors::KeyIsEmpty, res3.err().unwrap());
+
+    // 4.多个线程并发累加同一个计数器，不会丢失更新
+    let engine = Arc::new(engine);
+    let mut handles = Vec::new();
+    for _ in 0..8 {
+        let engine = engine.clone();
+        handles.push(std::thread::spawn(move || {
+            for _ in 0..100 {
+                engine
+                    .update(Bytes::from("counter"), |v| {
+                        let n = v.map_or(0, |v| {
+                            String::from_utf8(v.to_vec()).unwrap().parse().unwrap()
+                        });
+                        Some(Bytes::from((n + 1).to_string()))
+                    })
+                    .expect("failed to update");
+            }
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(
+        Bytes::from("800"),
+        engine.get(Bytes::from("counter")).unwrap()
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_changes_since() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-changes-since"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+
+    // 1.记录当前的文件 id，之后的写入都在新的文件中
+    let rotate_res = engine.rotate();
+    assert!(rotate_res.is_ok());
+    let file_id = engine.active_file_id();
+    assert!(engine.changes_since(file_id).unwrap().is_empty());
+
+    for i in 100..110 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    // 修改旧的 key，以最新的 value 为准
+    let res1 = engine.put(get_test_key(1), Bytes::from("a new value"));
+    assert!(res1.is_ok());
+    let res2 = engine.put(get_test_key(1), Bytes::from("a newer value"));
+    assert!(res2.is_ok());
+    // 写入之后又删除的 key 不会出现在结果中
+    let res3 = engine.put(get_test_key(200), get_test_value(200));
+    assert!(res3.is_ok());
+    let res4 = engine.delete(get_test_key(200));
+    assert!(res4.is_ok());
+
+    // 2.只返回新写入的 key
+    let changes = engine.changes_since(file_id).unwrap();
+    assert_eq!(11, changes.len());
+    for (key, value) in changes.iter() {
+        if *key == get_test_key(1) {
+            assert_eq!(Bytes::from("a newer value"), value);
+        } else {
+            assert_eq!(engine.get(key.clone()).unwrap(), value);
+        }
+    }
+
+    // 3.从第一个文件开始返回所有的数据
+    assert_eq!(110, engine.changes_since(0).unwrap().len());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_sequence() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-sequence"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(0, engine.sequence());
+
+    // 1.每次写入和删除都加一
+    let res1 = engine.put(get_test_key(11), get_test_value(11));
+    assert!(res1.is_ok());
+    assert_eq!(1, engine.sequence());
+    let res2 = engine.put(get_test_key(11), get_test_value(22));
+    assert!(res2.is_ok());
+    assert_eq!(2, engine.sequence());
+    let res3 = engine.delete(get_test_key(11));
+    assert!(res3.is_ok());
+    assert_eq!(3, engine.sequence());
+
+    // 2.读取以及没有修改数据的操作不会改变序号
+    assert!(engine.get(get_test_key(11)).is_err());
+    let res4 = engine.delete(get_test_key(11));
+    assert!(res4.is_ok());
+    let res5 = engine.put(Bytes::new(), get_test_value(11));
+    assert!(res5.is_err());
+    assert_eq!(3, engine.sequence());
+
+    // 3.put_if_absent 以及 update 写入数据时同样会加一
+    let res6 = engine.put_if_absent(get_test_key(22), get_test_value(22));
+    assert!(res6.unwrap());
+    let res7 = engine.put_if_absent(get_test_key(22), get_test_value(22));
+    assert!(!res7.unwrap());
+    assert_eq!(4, engine.sequence());
+    let res8 = engine.update(get_test_key(22), |_| None);
+    assert!(res8.is_ok());
+    assert_eq!(5, engine.sequence());
+
+    // 4.清空数据库
+    let res9 = engine.clear();
+    assert!(res9.is_ok());
+    assert_eq!(6, engine.sequence());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_put_empty_value() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-put-empty-value"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.value 为空的数据可以正常读取，不会被当作删除的数据
+    let res1 = engine.put(get_test_key(11), Bytes::new());
+    assert!(res1.is_ok());
+    assert_eq!(Bytes::new(), engine.get(get_test_key(11)).unwrap());
+    let res2 = engine.put(get_test_key(22), Bytes::new());
+    assert!(res2.is_ok());
+
+    // 2.删除之后读取不到
+    let res3 = engine.delete(get_test_key(22));
+    assert!(res3.is_ok());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get(get_test_key(22)).err().unwrap()
+    );
+
+    // 3.重启之后仍然能够区分空的 value 和删除的数据
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(Bytes::new(), engine2.get(get_test_key(11)).unwrap());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine2.get(get_test_key(22)).err().unwrap()
+    );
+    assert_eq!(1, engine2.list_keys().unwrap().len());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_snapshot_btree_index() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-snapshot-btree"),
+        data_file_size: 64 * 1024 * 1024,
+        index_type: IndexType::SnapshotBTree,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+
+    // 迭代的过程中可以写入数据，迭代器只能看到创建时的数据
+    let iter = engine.iter(IteratorOptions::default());
+    let res1 = engine.put(get_test_key(100), get_test_value(100));
+    assert!(res1.is_ok());
+    let res2 = engine.delete(get_test_key(0));
+    assert!(res2.is_ok());
+    let mut count = 0;
+    while let Some((key, value)) = iter.next() {
+        assert!(key != get_test_key(100));
+        if key != get_test_key(0) {
+            assert_eq!(engine.get(key).unwrap(), value);
+        }
+        count += 1;
+    }
+    assert_eq!(100, count);
+
+    // 重启之后重新加载索引
+    std::mem::drop(iter);
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(100, engine2.list_keys().unwrap().len());
+    assert_eq!(get_test_value(100), engine2.get(get_test_key(100)).unwrap());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine2.get(get_test_key(0)).err().unwrap()
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_put_buffered() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-put-buffered"),
+        data_file_size: 64 * 1024 * 1024,
+        sync_writes: true,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.逐条写入，每条数据都需要持久化
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert_eq!(100, engine.sync_count.load(Ordering::SeqCst));
+
+    // 2.暂存的数据在提交之前读取不到
+    for i in 100..200 {
+        let res = engine.put_buffered(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get(get_test_key(100)).err().unwrap()
+    );
+
+    // 3.提交时只持久化一次
+    let commit_res1 = engine.commit_buffered();
+    assert_eq!(100, commit_res1.unwrap());
+    assert_eq!(101, engine.sync_count.load(Ordering::SeqCst));
+    for i in 100..200 {
+        assert_eq!(get_test_value(i), engine.get(get_test_key(i)).unwrap());
+    }
+
+    // 4.没有暂存数据时提交不会写入
+    let commit_res2 = engine.commit_buffered();
+    assert_eq!(0, commit_res2.unwrap());
+    assert_eq!(101, engine.sync_count.load(Ordering::SeqCst));
+
+    // 5.key 为空
+    let res1 = engine.put_buffered(Bytes::new(), get_test_value(1));
+    assert_eq!(Errors::KeyIsEmpty, res1.err().unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_preallocate() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-preallocate"),
+        data_file_size: 1024 * 1024,
+        preallocate: true,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let file_name = opts.dir_path.join("000000000.data");
+
+    // 1.活跃文件预分配了 data_file_size 大小的空间
+    assert_eq!(
+        opts.data_file_size,
+        std::fs::metadata(&file_name).unwrap().len()
+    );
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert_eq!(get_test_value(10), engine.get(get_test_key(10)).unwrap());
+
+    // 2.关闭之后截断到实际写入的大小
+    let close_res = engine.close();
+    assert!(close_res.is_ok());
+    let write_off = std::fs::metadata(&file_name).unwrap().len();
+    assert!(write_off > 0 && write_off < opts.data_file_size);
+    assert_eq!(write_off, engine.stat().unwrap().disk_size);
+    std::mem::drop(engine);
+
+    // 3.没有截断就退出时，重启之后末尾的 0 被当作文件的结尾，新写入的数据紧跟在之前的数据之后
+    let mut engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(
+        opts.data_file_size,
+        std::fs::metadata(&file_name).unwrap().len()
+    );
+    let res1 = engine2.put(get_test_key(100), get_test_value(100));
+    assert!(res1.is_ok());
+    // 进程退出时文件锁会被释放
+    std::mem::drop(engine2._lock_file.take());
+    std::mem::forget(engine2);
+
+    let engine3 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(101, engine3.list_keys().unwrap().len());
+    assert_eq!(get_test_value(100), engine3.get(get_test_key(100)).unwrap());
+    assert_eq!(
+        write_off,
+        engine3
+            .index
+            .get(get_test_key(100).to_vec())
+            .unwrap()
+            .offset
+    );
+
+    // 4.封存的数据文件同样会被截断
+    let rotate_res = engine3.rotate();
+    assert!(rotate_res.is_ok());
+    assert!(std::fs::metadata(&file_name).unwrap().len() < opts.data_file_size);
+    assert_eq!(get_test_value(100), engine3.get(get_test_key(100)).unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_comparator() {
+    for index_type in [IndexType::BTree, IndexType::SnapshotBTree] {
+        let mut opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-comparator"),
+            data_file_size: 64 * 1024 * 1024,
+            index_type,
+            ..Default::default()
+        };
+        // 按照数字大小排序
+        opts.comparator = Some(Arc::new(|a: &[u8], b: &[u8]| {
+            let a: u64 = std::str::from_utf8(a).unwrap().parse().unwrap();
+            let b: u64 = std::str::from_utf8(b).unwrap().parse().unwrap();
+            a.cmp(&b)
+        }));
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 1..=20u64 {
+            let res = engine.put(
+                Bytes::from(i.to_string()),
+                Bytes::from(format!("value-{}", i)),
+            );
+            assert!(res.is_ok());
+        }
+        assert_eq!(
+            Bytes::from("value-12"),
+            engine.get(Bytes::from("12")).unwrap()
+        );
+
+        // 正向迭代按照数字大小排序
+        let iter = engine.iter(IteratorOptions::default());
+        let mut expected = 1u64;
+        while let Some((key, _)) = iter.next() {
+            assert_eq!(Bytes::from(expected.to_string()), key);
+            expected += 1;
+        }
+        assert_eq!(21, expected);
+
+        // seek 按照比较函数定位
+        iter.seek("5".as_bytes().to_vec());
+        assert_eq!(Bytes::from("5"), iter.next().unwrap().0);
+        assert_eq!(Bytes::from("6"), iter.next().unwrap().0);
+
+        // 反向迭代
+        let iter_opts = IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        let iter2 = engine.iter(iter_opts);
+        let mut expected = 20u64;
+        while let Some((key, _)) = iter2.next() {
+            assert_eq!(Bytes::from(expected.to_string()), key);
+            expected -= 1;
+        }
+        assert_eq!(0, expected);
+        iter2.seek("15".as_bytes().to_vec());
+        assert_eq!(Bytes::from("15"), iter2.next().unwrap().0);
+
+        // 删除测试的文件夹
+        std::mem::drop(iter);
+        std::mem::drop(iter2);
+        std::mem::drop(engine);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
+
+#[test]
+fn test_engine_hashmap_index() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-hashmap"),
+        data_file_size: 64 * 1024 * 1024,
+        index_type: IndexType::HashMap,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let res1 = engine.delete(get_test_key(0));
+    assert!(res1.is_ok());
+    assert_eq!(get_test_value(10), engine.get(get_test_key(10)).unwrap());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get(get_test_key(0)).err().unwrap()
+    );
+
+    // 哈希索引不保证顺序，只比较数量
+    assert_eq!(99, engine.list_keys().unwrap().len());
+    let iter = engine.iter(IteratorOptions::default());
+    let mut count = 0;
+    while let Some((key, value)) = iter.next() {
+        assert_eq!(engine.get(key).unwrap(), value);
+        count += 1;
+    }
+    assert_eq!(99, count);
+
+    // 重启之后重新加载索引
+    std::mem::drop(iter);
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(99, engine2.list_keys().unwrap().len());
+    assert_eq!(get_test_value(99), engine2.get(get_test_key(99)).unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_options_mismatch() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-options-mismatch"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    std::mem::drop(engine);
+
+    // 设置了比较函数和创建时的配置冲突
+    let mut opts3 = opts.clone();
+    opts3.comparator = Some(Arc::new(|a: &[u8], b: &[u8]| a.cmp(b)));
+    let res3 = Engine::open(opts3);
+    assert_eq!(Errors::OptionsMismatch, res3.err().unwrap());
+
+    // 相同的配置可以正常打开
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(get_test_value(1), engine2.get(get_test_key(1)).unwrap());
+
+    // 删除测试的文件夹
+    std::mem::drop(engine2);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_unsupported_format_version() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-format-version"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    std::mem::drop(engine);
+
+    // 将元数据文件中的格式版本改为更新的版本
+    let meta_path = opts.dir_path.join(META_FILE_NAME);
+    let mut buf = std::fs::read(&meta_path).unwrap();
+    buf[4] = FORMAT_VERSION + 1;
+    std::fs::write(&meta_path, buf).unwrap();
+
+    let res2 = Engine::open(opts.clone());
+    assert_eq!(Errors::UnsupportedFormatVersion, res2.err().unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_repair_on_read() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-repair-on-read"),
+        data_file_size: 4 * 1024,
+        repair_on_read: true,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 旧版本的 value 在旧的数据文件中，新版本在活跃文件中
+    let res1 = engine.put(get_test_key(1), Bytes::from("old-value"));
+    assert!(res1.is_ok());
+    for i in 100..200 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let res2 = engine.put(get_test_key(1), Bytes::from("new-value"));
+    assert!(res2.is_ok());
+    let res3 = engine.put(get_test_key(2), Bytes::from("only-value"));
+    assert!(res3.is_ok());
+
+    // 篡改新版本的 value
+    let corrupt = |key: Bytes| {
+        let pos = engine.index.get(key.to_vec()).unwrap();
+        let file_name = opts.dir_path.join(std::format!("{:09}.data", pos.file_id));
+        let mut content = std::fs::read(file_name.clone()).unwrap();
+        content[pos.offset as usize + 3 + key.len()] ^= 0xff;
+        std::fs::write(file_name, content).unwrap();
+    };
+    corrupt(get_test_key(1));
+    corrupt(get_test_key(2));
+
+    // 返回更早的有效版本
+    assert_eq!(
+        Bytes::from("old-value"),
+        engine.get(get_test_key(1)).unwrap()
+    );
+    // 没有更早的版本时仍然返回错误
+    assert_eq!(
+        Errors::InvalidLogRecordCrc,
+        engine.get(get_test_key(2)).err().unwrap()
+    );
+    std::mem::drop(engine);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_sync_dir() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-sync-dir"),
+        data_file_size: 4 * 1024,
+        sync_dir: true,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(0, engine.dir_sync_count.load(Ordering::SeqCst));
+
+    // 每次切换活跃文件都会持久化一次目录
+    for i in 0..200 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let data_file_num = engine.stat().unwrap().data_file_num;
+    assert!(data_file_num > 1);
+    assert_eq!(
+        data_file_num - 1,
+        engine.dir_sync_count.load(Ordering::SeqCst)
+    );
+
+    let res1 = engine.rotate();
+    assert!(res1.is_ok());
+    assert_eq!(data_file_num, engine.dir_sync_count.load(Ordering::SeqCst));
+    std::mem::drop(engine);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_read_cache() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-read-cache"),
+        data_file_size: 64 * 1024 * 1024,
+        read_cache_size: 2,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..10 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+
+    // 缓存命中时不会再读取数据文件
+    assert_eq!(get_test_value(1), engine.get(get_test_key(1)).unwrap());
+    assert_eq!(1, engine.read_count.load(Ordering::SeqCst));
+    for _ in 0..10 {
+        assert_eq!(get_test_value(1), engine.get(get_test_key(1)).unwrap());
+    }
+    assert_eq!(1, engine.read_count.load(Ordering::SeqCst));
+
+    // 覆盖写入之后读取新的位置
+    let res1 = engine.put(get_test_key(1), Bytes::from("new-value"));
+    assert!(res1.is_ok());
+    assert_eq!(
+        Bytes::from("new-value"),
+        engine.get(get_test_key(1)).unwrap()
+    );
+    assert_eq!(2, engine.read_count.load(Ordering::SeqCst));
+
+    // 超过缓存大小时淘汰最久没有读取的数据
+    assert_eq!(get_test_value(2), engine.get(get_test_key(2)).unwrap());
+    assert_eq!(get_test_value(3), engine.get(get_test_key(3)).unwrap());
+    assert_eq!(4, engine.read_count.load(Ordering::SeqCst));
+    assert_eq!(
+        Bytes::from("new-value"),
+        engine.get(get_test_key(1)).unwrap()
+    );
+    assert_eq!(5, engine.read_count.load(Ordering::SeqCst));
+
+    // 清空之后缓存的数据不再有效
+    assert_eq!(get_test_value(0), engine.get(get_test_key(0)).unwrap());
+    let res2 = engine.clear();
+    assert!(res2.is_ok());
+    let res3 = engine.put(get_test_key(100), get_test_value(100));
+    assert!(res3.is_ok());
+    assert_eq!(get_test_value(100), engine.get(get_test_key(100)).unwrap());
+    std::mem::drop(engine);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_heal_dangling_index() {
+    let mut opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-heal-dangling-index"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..10 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    // 模拟索引指向了一个不存在的数据文件
+    let dangling_pos = LogRecordPos {
+        file_id: 100,
+        offset: 0,
+        inline: None,
+        version: 0,
+    };
+    engine.index.put(get_test_key(1).to_vec(), dangling_pos);
+
+    // 默认一直返回错误
+    for _ in 0..2 {
+        assert_eq!(
+            Errors::DataFileNotFound,
+            engine.get(get_test_key(1)).err().unwrap()
+        );
+    }
+    std::mem::drop(engine);
+
+    // 开启之后删除索引，之后返回 KeyNotFound
+    opts.heal_dangling_index = true;
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    engine2.index.put(get_test_key(1).to_vec(), dangling_pos);
+    engine2.index.put(get_test_key(2).to_vec(), dangling_pos);
+    for _ in 0..2 {
+        assert_eq!(
+            Errors::KeyNotFound,
+            engine2.get(get_test_key(1)).err().unwrap()
+        );
+    }
+    assert!(engine2.index.get(get_test_key(1).to_vec()).is_none());
+    assert!(engine2.index.get(get_test_key(2).to_vec()).is_some());
+    assert_eq!(9, engine2.list_keys().unwrap().len());
+    assert_eq!(get_test_value(3), engine2.get(get_test_key(3)).unwrap());
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_swap() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-swap"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 第一次写入时没有旧的 value
+    let res1 = engine.swap(get_test_key(1), get_test_value(1));
+    assert_eq!(None, res1.unwrap());
+
+    // 覆盖写入时返回旧的 value
+    let res2 = engine.swap(get_test_key(1), get_test_value(2));
+    assert_eq!(Some(get_test_value(1)), res2.unwrap());
+    assert_eq!(get_test_value(2), engine.get(get_test_key(1)).unwrap());
+
+    // 删除之后再写入也没有旧的 value
+    let res3 = engine.delete(get_test_key(1));
+    assert!(res3.is_ok());
+    let res4 = engine.swap(get_test_key(1), get_test_value(3));
+    assert_eq!(None, res4.unwrap());
+
+    let res5 = engine.swap(Bytes::new(), get_test_value(1));
+    assert_eq!(Errors::KeyIsEmpty, res5.err().unwrap());
+
+    // 删除测试的文件夹
+    std::mem::drop(engine);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_compact_key() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-compact-key"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    for i in 100..200 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let pos1 = engine.index.get(get_test_key(1).to_vec()).unwrap();
+    assert!(pos1.file_id < engine.active_file_id());
+
+    // 重新写入之后 key 位于活跃文件中，value 不变
+    let res2 = engine.compact_key(get_test_key(1));
+    assert!(res2.is_ok());
+    let pos2 = engine.index.get(get_test_key(1).to_vec()).unwrap();
+    assert_eq!(engine.active_file_id(), pos2.file_id);
+    assert_eq!(get_test_value(1), engine.get(get_test_key(1)).unwrap());
+
+    // 已经在活跃文件中时不会重复写入
+    let res3 = engine.compact_key(get_test_key(1));
+    assert!(res3.is_ok());
+    assert_eq!(pos2, engine.index.get(get_test_key(1).to_vec()).unwrap());
+
+    let res4 = engine.compact_key(get_test_key(2));
+    assert_eq!(Errors::KeyNotFound, res4.err().unwrap());
+
+    // 重启之后仍然是最新的位置
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(pos2, engine2.index.get(get_test_key(1).to_vec()).unwrap());
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_max_open_files() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-max-open-files"),
+        data_file_size: 4 * 1024,
+        max_open_files: 2,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..500 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert!(engine.stat().unwrap().data_file_num > 5);
+    let io_cache = engine.io_cache.clone().unwrap();
+
+    // 从所有的数据文件中读取，打开的旧的数据文件数量不超过限制
+    for i in 0..500 {
+        assert_eq!(get_test_value(i), engine.get(get_test_key(i)).unwrap());
+        assert!(io_cache.len() <= 2);
+    }
+
+    // 重启之后加载索引时也不会超过限制
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    let io_cache2 = engine2.io_cache.clone().unwrap();
+    assert!(io_cache2.len() <= 2);
+    for i in (0..500).rev() {
+        assert_eq!(get_test_value(i), engine2.get(get_test_key(i)).unwrap());
+        assert!(io_cache2.len() <= 2);
+    }
+    assert_eq!(500, engine2.verify().unwrap().valid_records);
+
+    // 清空之后重新写入的数据不会读取到已经删除的文件
+    let res1 = engine2.clear();
+    assert!(res1.is_ok());
+    for i in 1000..1500 {
+        let res = engine2.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 1000..1500 {
+        assert_eq!(get_test_value(i), engine2.get(get_test_key(i)).unwrap());
+    }
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_put_synced() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-put-synced"),
+        data_file_size: 64 * 1024 * 1024,
+        sync_writes: false,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 关闭 sync_writes 时 put 不会持久化
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    assert_eq!(0, engine.sync_count.load(Ordering::SeqCst));
+
+    // put_synced 每次写入都持久化一次
+    let res2 = engine.put_synced(get_test_key(2), get_test_value(2));
+    assert!(res2.is_ok());
+    assert_eq!(1, engine.sync_count.load(Ordering::SeqCst));
+    assert_eq!(get_test_value(2), engine.get(get_test_key(2)).unwrap());
+
+    let res3 = engine.put_synced(Bytes::new(), get_test_value(3));
+    assert_eq!(Errors::KeyIsEmpty, res3.err().unwrap());
+    assert_eq!(1, engine.sync_count.load(Ordering::SeqCst));
+
+    // 删除测试的文件夹
+    std::mem::drop(engine);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_large_file_id() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-large-file-id"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    std::mem::drop(engine);
+
+    // 将数据文件的 id 改为 u32 的最大值
+    let large_id = u32::MAX as u64;
+    std::fs::rename(
+        opts.dir_path.join("000000000.data"),
+        opts.dir_path.join(std::format!("{}.data", large_id)),
+    )
+    .unwrap();
+
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(large_id, engine2.active_file_id());
+    assert_eq!(get_test_value(1), engine2.get(get_test_key(1)).unwrap());
+
+    // 切换活跃文件之后文件 id 超过 u32 的范围
+    for i in 100..200 {
+        let res = engine2.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert!(engine2.active_file_id() > large_id);
+    std::mem::drop(engine2);
+
+    let engine3 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert!(engine3.active_file_id() > large_id);
+    assert_eq!(101, engine3.list_keys().unwrap().len());
+    assert_eq!(get_test_value(1), engine3.get(get_test_key(1)).unwrap());
+    assert_eq!(get_test_value(199), engine3.get(get_test_key(199)).unwrap());
+    std::mem::drop(engine3);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_group_commit() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-group-commit"),
+        sync_writes: true,
+        group_commit: true,
+        ..Default::default()
+    };
+    let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+
+    // 1.多个线程并发写入，持久化的次数远少于写入的次数
+    let mut handles = Vec::new();
+    for t in 0..8 {
+        let engine = engine.clone();
+        handles.push(std::thread::spawn(move || {
+            for i in 0..100 {
+                let res = engine.put(get_test_key(t * 100 + i), get_test_value(t * 100 + i));
+                assert!(res.is_ok());
+            }
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let sync_count = engine.group_commit.as_ref().unwrap().sync_count();
+    assert!(sync_count > 0);
+    assert!(sync_count < 800);
+    // 写入时不再直接持久化
+    assert_eq!(0, engine.sync_count.load(Ordering::SeqCst));
+
+    // 2.删除数据同样由后台线程持久化
+    let res1 = engine.delete(get_test_key(0));
+    assert!(res1.is_ok());
+    std::mem::drop(engine);
+
+    // 3.重新打开之后数据都在
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(799, engine2.list_keys().unwrap().len());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine2.get(get_test_key(0)).err().unwrap()
+    );
+    for i in 1..800 {
+        assert_eq!(get_test_value(i), engine2.get(get_test_key(i)).unwrap());
+    }
+    std::mem::drop(engine2);
+
+    // 4.关闭 sync_writes 时不启动后台线程
+    let mut opts2 = opts.clone();
+    opts2.sync_writes = false;
+    let engine3 = Engine::open(opts2).expect("failed to open engine");
+    assert!(engine3.group_commit.is_none());
+    std::mem::drop(engine3);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_locate_key() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-locate-key"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.key 不存在
+    assert!(engine.locate_key(get_test_key(1)).unwrap().is_empty());
+
+    // 2.key 的多条记录分布在两个文件中
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    let first_file_id = engine.active_file_id();
+    for i in 100..200 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let res2 = engine.put(get_test_key(1), get_test_value(2));
+    assert!(res2.is_ok());
+    let res3 = engine.delete(get_test_key(1));
+    assert!(res3.is_ok());
+    assert!(engine.active_file_id() > first_file_id);
+
+    let positions = engine.locate_key(get_test_key(1)).unwrap();
+    assert_eq!(3, positions.len());
+    assert_eq!(first_file_id, positions[0].file_id());
+    assert_eq!(0, positions[0].offset());
+    assert_eq!(engine.active_file_id(), positions[1].file_id());
+    assert_eq!(positions[1].file_id(), positions[2].file_id());
+    assert!(positions[1].offset() < positions[2].offset());
+
+    // 3.key 为空
+    let res4 = engine.locate_key(Bytes::new());
+    assert_eq!(Errors::KeyIsEmpty, res4.err().unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_load_threads() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-load-threads"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 覆盖写入和删除的记录分布在多个数据文件中
+    for i in 0..300 {
+        let res = engine.put(get_test_key(i % 100), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 0..100 {
+        if i % 3 == 0 {
+            let res = engine.delete(get_test_key(i));
+            assert!(res.is_ok());
+        }
+    }
+    std::mem::drop(engine);
+
+    let collect = |engine: &Engine| {
+        let mut items = Vec::new();
+        let iter = engine.iter(IteratorOptions::default());
+        while let Some(item) = iter.next() {
+            items.push(item);
+        }
+        items
+    };
+
+    // 单线程和多线程加载得到的索引相同
+    let mut opts1 = opts.clone();
+    opts1.load_threads = Some(1);
+    let engine1 = Engine::open(opts1).expect("failed to open engine");
+    assert!(engine1.stat().unwrap().data_file_num > 4);
+    let items1 = collect(&engine1);
+    let file_id1 = engine1.active_file_id();
+    std::mem::drop(engine1);
+
+    let mut opts2 = opts.clone();
+    opts2.load_threads = Some(4);
+    let engine2 = Engine::open(opts2).expect("failed to open engine");
+    let items2 = collect(&engine2);
+    assert_eq!(66, items2.len());
+    assert_eq!(items1, items2);
+    assert_eq!(file_id1, engine2.active_file_id());
+
+    // 多线程加载之后可以继续写入
+    let res = engine2.put(get_test_key(1000), get_test_value(1000));
+    assert!(res.is_ok());
+    assert_eq!(
+        get_test_value(1000),
+        engine2.get(get_test_key(1000)).unwrap()
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+// 崩溃一致性测试工具，把数据库目录中最后一个数据文件依次截断到每一个偏移，包括记录的边界和记录中间的位置，
+// 每次都在目录的副本上打开数据库，校验恢复了截断位置之前所有完整的记录，并且之后可以继续正常写入
+// 开启了预分配时截断之后再用 0 填充到数据文件的大小
+fn check_truncated_recovery(opts: &Options) {
+    // 记录原始目录中所有的记录，以及每条记录的结束位置
+    let mut read_opts = opts.clone();
+    read_opts.read_only = true;
+    let engine = Engine::open(read_opts).expect("failed to open engine");
+    let last_file_id = engine.active_file_id();
+    let last_file_name = std::format!("{:09}.data", last_file_id);
+    let file_size = std::fs::metadata(opts.dir_path.join(&last_file_name))
+        .unwrap()
+        .len();
+
+    let mut records: Vec<(LogRecordPos, Vec<u8>, Option<Vec<u8>>)> = Vec::new();
+    engine
+        .for_each_record(|pos, record| {
+            let value = match record.rec_type {
+                LogRecordType::NORMAL => Some(record.value),
+                LogRecordType::DELETED => None,
+                LogRecordType::FOOTER => return,
+            };
+            records.push((pos, record.key, value));
+        })
+        .unwrap();
+    std::mem::drop(engine);
+
+    let mut boundaries = Vec::new();
+    for (i, (pos, _, _)) in records.iter().enumerate() {
+        let end = match records.get(i + 1) {
+            Some((next, _, _)) if next.file_id == pos.file_id => next.offset,
+            _ if pos.file_id == last_file_id => file_size,
+            _ => 0,
+        };
+        boundaries.push(end);
+    }
+
+    let copy_dir = PathBuf::from(std::format!("{}-copy", opts.dir_path.display()));
+    let mut copy_opts = opts.clone();
+    copy_opts.dir_path = copy_dir.clone();
+    for cut in 0..=file_size {
+        // 复制整个目录，再截断最后一个数据文件
+        let _ = std::fs::remove_dir_all(&copy_dir);
+        std::fs::create_dir_all(&copy_dir).unwrap();
+        for entry in std::fs::read_dir(&opts.dir_path).unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), copy_dir.join(entry.file_name())).unwrap();
+        }
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(copy_dir.join(&last_file_name))
+            .unwrap();
+        file.set_len(cut).unwrap();
+        // 预分配的文件崩溃时长度不变，没有写入的部分都是 0
+        if opts.preallocate {
+            file.set_len(opts.data_file_size.max(file_size)).unwrap();
+        }
+        std::mem::drop(file);
+
+        // 截断位置之前完整的记录按照写入的顺序生效
+        let mut expected = std::collections::BTreeMap::new();
+        for ((pos, key, value), end) in records.iter().zip(boundaries.iter()) {
+            if pos.file_id == last_file_id && *end > cut {
+                break;
+            }
+            match value {
+                Some(value) => expected.insert(key.clone(), value.clone()),
+                None => expected.remove(key),
+            };
+        }
+
+        let engine = Engine::open(copy_opts.clone())
+            .unwrap_or_else(|e| panic!("failed to open engine truncated at {}: {}", cut, e));
+        let mut actual = std::collections::BTreeMap::new();
+        let iter = engine.iter(IteratorOptions::default());
+        while let Some((key, value)) = iter.next() {
+            actual.insert(key.to_vec(), value.to_vec());
+        }
+        assert_eq!(expected, actual, "truncated at {}", cut);
+
+        // 恢复之后继续写入，重新打开时可以读取到新写入的数据
+        let res = engine.put(Bytes::from("crash-check"), Bytes::from("value"));
+        assert!(res.is_ok());
+        std::mem::drop(iter);
+        std::mem::drop(engine);
+        let engine2 = Engine::open(copy_opts.clone()).expect("failed to open engine");
+        assert_eq!(expected.len() + 1, engine2.list_keys().unwrap().len());
+        assert_eq!(
+            Bytes::from("value"),
+            engine2.get(Bytes::from("crash-check")).unwrap()
+        );
+        std::mem::drop(engine2);
+    }
+
+    std::fs::remove_dir_all(copy_dir).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_truncated_recovery() {
+    for preallocate in [false, true] {
+        let opts = Options {
+            dir_path: PathBuf::from(std::format!(
+                "/tmp/bitcask-rs-truncated-recovery-{}",
+                preallocate
+            )),
+            data_file_size: 1024,
+            preallocate,
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 写入、覆盖写入和删除的记录分布在多个数据文件中
+        for i in 0..12 {
+            let res = engine.put(get_test_key(i % 5), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        let res1 = engine.delete(get_test_key(1));
+        assert!(res1.is_ok());
+        let res2 = engine.put(get_test_key(9), Bytes::new());
+        assert!(res2.is_ok());
+        let res3 = engine.delete(get_test_key(3));
+        assert!(res3.is_ok());
+        assert!(engine.active_file_id() > 0);
+        std::mem::drop(engine);
+
+        check_truncated_recovery(&opts);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
+
+#[test]
+fn test_engine_inline_value() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-inline-value"),
+        inline_value_threshold: 8,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let res1 = engine.put(Bytes::from("flag"), Bytes::from("1"));
+    assert!(res1.is_ok());
+    let res2 = engine.put(Bytes::from("counter"), Bytes::from("12345678"));
+    assert!(res2.is_ok());
+    let res3 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res3.is_ok());
+
+    // 1.较小的 value 读取时不访问数据文件
+    assert_eq!(Bytes::from("1"), engine.get(Bytes::from("flag")).unwrap());
+    assert_eq!(
+        Bytes::from("12345678"),
+        engine.get(Bytes::from("counter")).unwrap()
+    );
+    assert_eq!(0, engine.read_count.load(Ordering::SeqCst));
+    assert_eq!(get_test_value(1), engine.get(get_test_key(1)).unwrap());
+    assert_eq!(1, engine.read_count.load(Ordering::SeqCst));
+
+    // 2.覆盖写入较大的 value 之后从数据文件中读取
+    let res4 = engine.put(Bytes::from("flag"), get_test_value(2));
+    assert!(res4.is_ok());
+    assert_eq!(get_test_value(2), engine.get(Bytes::from("flag")).unwrap());
+    assert_eq!(2, engine.read_count.load(Ordering::SeqCst));
+    std::mem::drop(engine);
+
+    // 3.重新打开之后加载索引时同样内联
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(
+        Bytes::from("12345678"),
+        engine2.get(Bytes::from("counter")).unwrap()
+    );
+    assert_eq!(0, engine2.read_count.load(Ordering::SeqCst));
+    assert_eq!(get_test_value(2), engine2.get(Bytes::from("flag")).unwrap());
+    assert_eq!(1, engine2.read_count.load(Ordering::SeqCst));
+    std::mem::drop(engine2);
+
+    // 4.关闭内联之后所有的 value 都从数据文件中读取
+    let mut opts2 = opts.clone();
+    opts2.inline_value_threshold = 0;
+    let engine3 = Engine::open(opts2).expect("failed to open engine");
+    assert_eq!(
+        Bytes::from("12345678"),
+        engine3.get(Bytes::from("counter")).unwrap()
+    );
+    assert_eq!(1, engine3.read_count.load(Ordering::SeqCst));
+    std::mem::drop(engine3);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_skip_unparsable_files() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-skip-unparsable-files"),
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    std::mem::drop(engine);
+
+    // 数据目录中有文件名不是数字的数据文件
+    std::fs::write(opts.dir_path.join("backup.data"), b"backup").unwrap();
+
+    // 1.默认直接返回错误
+    let res2 = Engine::open(opts.clone());
+    assert_eq!(Errors::DataDirectoryCorrupted, res2.err().unwrap());
+
+    // 2.跳过这个文件之后正常打开
+    let mut opts2 = opts.clone();
+    opts2.skip_unparsable_files = true;
+    let engine2 = Engine::open(opts2).expect("failed to open engine");
+    assert_eq!(get_test_value(1), engine2.get(get_test_key(1)).unwrap());
+    assert_eq!(1, engine2.stat().unwrap().data_file_num);
+    std::mem::drop(engine2);
+    assert!(opts.dir_path.join("backup.data").exists());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[derive(Default)]
+struct CountingObserver {
+    puts: AtomicUsize,
+    put_bytes: AtomicUsize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    deletes: AtomicUsize,
+    rollovers: AtomicUsize,
+    last_file_id: AtomicU64,
+}
+
+impl EngineObserver for CountingObserver {
+    fn on_put(&self, key_len: usize, value_len: usize) {
+        self.puts.fetch_add(1, Ordering::SeqCst);
+        self.put_bytes
+            .fetch_add(key_len + value_len, Ordering::SeqCst);
+    }
+
+    fn on_get(&self, hit: bool) {
+        match hit {
+            true => self.hits.fetch_add(1, Ordering::SeqCst),
+            false => self.misses.fetch_add(1, Ordering::SeqCst),
+        };
+    }
+
+    fn on_delete(&self) {
+        self.deletes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_rollover(&self, new_file_id: u64) {
+        self.rollovers.fetch_add(1, Ordering::SeqCst);
+        self.last_file_id.store(new_file_id, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_engine_observer() {
+    let observer = Arc::new(CountingObserver::default());
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-observer"),
+        data_file_size: 4 * 1024,
+        observer: Some(observer.clone()),
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let mut put_bytes = 0;
+    for i in 0..100 {
+        put_bytes += get_test_key(i).len() + get_test_value(i).len();
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 0..10 {
+        assert!(engine.get(get_test_key(i)).is_ok());
+    }
+    assert!(engine.get(get_test_key(1000)).is_err());
+    let res1 = engine.delete(get_test_key(0));
+    assert!(res1.is_ok());
+    // 删除不存在的 key 不会写入数据
+    let res2 = engine.delete(get_test_key(1000));
+    assert!(res2.is_ok());
+
+    assert_eq!(100, observer.puts.load(Ordering::SeqCst));
+    assert_eq!(put_bytes, observer.put_bytes.load(Ordering::SeqCst));
+    assert_eq!(10, observer.hits.load(Ordering::SeqCst));
+    assert_eq!(1, observer.misses.load(Ordering::SeqCst));
+    assert_eq!(1, observer.deletes.load(Ordering::SeqCst));
+    let rollovers = observer.rollovers.load(Ordering::SeqCst);
+    assert_eq!(engine.stat().unwrap().data_file_num - 1, rollovers);
+    assert!(rollovers > 0);
+    assert_eq!(
+        engine.active_file_id(),
+        observer.last_file_id.load(Ordering::SeqCst)
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_key_mismatch() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-key-mismatch"),
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    let res2 = engine.put(get_test_key(2), get_test_value(2));
+    assert!(res2.is_ok());
+
+    // 索引中 key 1 的位置指向了 key 2 的记录，记录本身的 crc 是正确的
+    let pos = engine.index.get(get_test_key(2).to_vec()).unwrap();
+    engine.index.put(get_test_key(1).to_vec(), pos);
+    assert_eq!(
+        Errors::KeyMismatch,
+        engine.get(get_test_key(1)).err().unwrap()
+    );
+    assert_eq!(get_test_value(2), engine.get(get_test_key(2)).unwrap());
+    std::mem::drop(engine);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+
+    // 自定义比较函数认为相等的 key 不会返回错误
+    let opts2 = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-key-mismatch-comparator"),
+        comparator: Some(Arc::new(|a: &[u8], b: &[u8]| {
+            a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())
+        })),
+        ..Default::default()
+    };
+    let engine2 = Engine::open(opts2.clone()).expect("failed to open engine");
+    let res3 = engine2.put(Bytes::from("KEY"), Bytes::from("v1"));
+    assert!(res3.is_ok());
+    let res4 = engine2.put(Bytes::from("key"), Bytes::from("v2"));
+    assert!(res4.is_ok());
+    assert_eq!(Bytes::from("v2"), engine2.get(Bytes::from("KEY")).unwrap());
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts2.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_data_subdir() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-data-subdir"),
+        data_subdir: Some("data".to_string()),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let data_file_num = engine.stat().unwrap().data_file_num;
+    assert!(data_file_num > 1);
+    std::mem::drop(engine);
+
+    // 数据文件都在子目录中，元数据文件在数据库目录中
+    let count_data_files = |dir: PathBuf| {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.file_name().to_str().unwrap().ends_with(".data"))
+            .count()
+    };
+    assert_eq!(data_file_num, count_data_files(opts.dir_path.join("data")));
+    assert_eq!(0, count_data_files(opts.dir_path.clone()));
+    assert!(opts.dir_path.join(META_FILE_NAME).exists());
+
+    // 重新打开之后数据都在
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(100, engine2.list_keys().unwrap().len());
+    assert_eq!(get_test_value(99), engine2.get(get_test_key(99)).unwrap());
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_get_ref() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-get-ref"),
+        read_cache_size: 10,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+
+    // 1.读取到正确的数据，从缓存中命中时和缓存共享同一块内存
+    let value1 = engine.get_ref(get_test_key(1)).unwrap();
+    assert_eq!(&get_test_value(1)[..], &value1[..]);
+    let value2 = engine.get_ref(get_test_key(1)).unwrap();
+    assert_eq!(value1.as_ptr(), value2.as_ptr());
+
+    // 2.key 不存在
+    let res2 = engine.get_ref(get_test_key(2));
+    assert_eq!(Errors::KeyNotFound, res2.err().unwrap());
+
+    // 3.覆盖写入和关闭数据库之后，之前返回的 value 仍然有效
+    let res3 = engine.put(get_test_key(1), get_test_value(3));
+    assert!(res3.is_ok());
+    std::mem::drop(engine);
+    assert_eq!(&get_test_value(1)[..], &value1[..]);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_sync_file() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-sync-file"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let active_file_id = engine.active_file_id();
+    assert!(active_file_id > 0);
+
+    let sync_counts = |engine: &Engine| {
+        let older_files = engine.older_files.read();
+        (
+            older_files[&0].sync_count.load(Ordering::SeqCst),
+            engine.active_file.read().sync_count.load(Ordering::SeqCst),
+        )
+    };
+    let (older_before, active_before) = sync_counts(&engine);
+
+    // 1.只持久化旧的数据文件
+    let res1 = engine.sync_file(0);
+    assert!(res1.is_ok());
+    assert_eq!((older_before + 1, active_before), sync_counts(&engine));
+
+    // 2.只持久化活跃文件
+    let res2 = engine.sync_file(active_file_id);
+    assert!(res2.is_ok());
+    assert_eq!((older_before + 1, active_before + 1), sync_counts(&engine));
+
+    // 3.数据文件不存在
+    let res3 = engine.sync_file(active_file_id + 1);
+    assert_eq!(Errors::DataFileNotFound, res3.err().unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_min_free_bytes() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-min-free-bytes"),
+        min_free_bytes: 1,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.可用空间足够时正常写入
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    std::mem::drop(engine);
+
+    // 2.可用空间不足时拒绝写入，之前的数据不受影响
+    let mut opts2 = opts.clone();
+    opts2.min_free_bytes = u64::MAX;
+    let engine2 = Engine::open(opts2).expect("failed to open engine");
+    let res2 = engine2.put(get_test_key(2), get_test_value(2));
+    assert_eq!(Errors::InsufficientDiskSpace, res2.err().unwrap());
+    let res3 = engine2.delete(get_test_key(1));
+    assert_eq!(Errors::InsufficientDiskSpace, res3.err().unwrap());
+    assert_eq!(get_test_value(1), engine2.get(get_test_key(1)).unwrap());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine2.get(get_test_key(2)).err().unwrap()
+    );
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_merge_partial() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-merge-partial"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..300 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    // 覆盖写入和删除一部分数据，前面的文件中产生无效的数据
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i + 1000));
+        assert!(res.is_ok());
+    }
+    for i in 100..150 {
+        let res = engine.delete(get_test_key(i));
+        assert!(res.is_ok());
+    }
+    let res1 = engine.rotate();
+    assert!(res1.is_ok());
+    let file_ids_before: Vec<u64> = engine.older_files.read().keys().copied().collect();
+    assert!(file_ids_before.len() > 3);
+
+    // 1.只处理指定数量的文件
+    let res2 = engine.merge_partial(2);
+    assert_eq!(2, res2.unwrap());
+    let older_files = engine.older_files.read();
+    let removed: Vec<&u64> = file_ids_before
+        .iter()
+        .filter(|fid| !older_files.contains_key(fid))
+        .collect();
+    assert_eq!(2, removed.len());
+    std::mem::drop(older_files);
+
+    // 2.所有有效的数据都可以读取到，删除的数据仍然不存在
+    let check = |engine: &Engine| {
+        for i in 0..300 {
+            let res = engine.get(get_test_key(i));
+            match i {
+                0..=99 => assert_eq!(get_test_value(i + 1000), res.unwrap()),
+                100..=149 => assert_eq!(Errors::KeyNotFound, res.err().unwrap()),
+                _ => assert_eq!(get_test_value(i), res.unwrap()),
+            }
+        }
+    };
+    check(&engine);
+
+    // 3.没有可以回收的空间时不处理任何文件
+    let mut count = 0;
+    loop {
+        let n = engine.merge_partial(1).unwrap();
+        if n == 0 {
+            break;
+        }
+        count += n;
+        assert!(count < 100);
+    }
+    check(&engine);
+
+    // 4.重启之后数据不变
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    check(&engine2);
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_rewrite() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-rewrite"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), Bytes::from(i.to_string()));
+        assert!(res.is_ok());
+    }
+    let res1 = engine.rotate();
+    assert!(res1.is_ok());
+    let file_ids_before: Vec<u64> = engine.older_files.read().keys().copied().collect();
+
+    // 1.value 翻倍，是 3 的倍数的 key 被删除
+    let res2 = engine.rewrite(|_, value| {
+        let n: usize = std::str::from_utf8(value).unwrap().parse().unwrap();
+        (!n.is_multiple_of(3)).then(|| (n * 2).to_string().into_bytes())
+    });
+    assert_eq!(100, res2.unwrap());
+    let check = |engine: &Engine| {
+        for i in 0..100 {
+            let res = engine.get(get_test_key(i));
+            if i.is_multiple_of(3) {
+                assert_eq!(Errors::KeyNotFound, res.err().unwrap());
+            } else {
+                assert_eq!(Bytes::from((i * 2).to_string()), res.unwrap());
+            }
+        }
+    };
+    check(&engine);
+
+    // 2.旧的记录都不再有效，之前的数据文件可以全部回收
+    let res3 = engine.merge_partial(usize::MAX);
+    assert_eq!(file_ids_before.len(), res3.unwrap());
+    let older_files = engine.older_files.read();
+    assert!(file_ids_before
+        .iter()
+        .all(|fid| !older_files.contains_key(fid)));
+    std::mem::drop(older_files);
+    check(&engine);
+
+    // 3.重启之后数据不变
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    check(&engine2);
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_u64_key() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-u64-key"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 乱序写入，按照字节顺序比较时小端序或者字符串编码的顺序都和数值顺序不一致
+    let keys = [65536u64, 1, 256, 0];
+    for key in keys {
+        let res = engine.put_u64_key(key, Bytes::from(key.to_string()));
+        assert!(res.is_ok());
+    }
+    assert_eq!(Bytes::from("256"), engine.get_u64_key(256).unwrap());
+    assert_eq!(Errors::KeyNotFound, engine.get_u64_key(2).err().unwrap());
+
+    // 迭代器按照数值从小到大返回
+    let iter = engine.iter(IteratorOptions::default());
+    let mut iter_keys = Vec::new();
+    while let Some((key, _)) = iter.next() {
+        iter_keys.push(decode_u64_be(&key).unwrap());
+    }
+    assert_eq!(vec![0, 1, 256, 65536], iter_keys);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_merge_partial_tombstone_retention() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-tombstone-retention"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let write_and_delete = |engine: &Engine, start: usize| {
+        for i in start..start + 100 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        for i in start..start + 50 {
+            let res = engine.delete(get_test_key(i));
+            assert!(res.is_ok());
+        }
+        let res = engine.rotate();
+        assert!(res.is_ok());
+    };
+    let check = |engine: &Engine, start: usize| {
+        for i in start..start + 100 {
+            let res = engine.get(get_test_key(i));
+            if i < start + 50 {
+                assert_eq!(Errors::KeyNotFound, res.err().unwrap());
+            } else {
+                assert_eq!(get_test_value(i), res.unwrap());
+            }
+        }
+    };
+
+    // 1.不保留删除记录，合并所有的文件之后删除的 key 没有任何记录
+    write_and_delete(&engine, 0);
+    let res1 = engine.merge_partial(usize::MAX);
+    assert!(res1.unwrap() > 0);
+    for i in 0..50 {
+        assert!(engine.locate_key(get_test_key(i)).unwrap().is_empty());
+    }
+    check(&engine, 0);
+    std::mem::drop(engine);
+
+    // 2.保留所有文件中的删除记录，旧的 value 被回收，只剩下删除记录
+    let mut opts2 = opts.clone();
+    opts2.tombstone_retention = u64::MAX;
+    let engine2 = Engine::open(opts2.clone()).expect("failed to open engine");
+    write_and_delete(&engine2, 100);
+    let res2 = engine2.merge_partial(usize::MAX);
+    assert!(res2.unwrap() > 0);
+    for i in 100..150 {
+        assert_eq!(1, engine2.locate_key(get_test_key(i)).unwrap().len());
+    }
+    check(&engine2, 100);
+    std::mem::drop(engine2);
+
+    // 3.重启之后删除的 key 仍然不存在
+    let engine3 = Engine::open(opts.clone()).expect("failed to open engine");
+    check(&engine3, 0);
+    check(&engine3, 100);
+    std::mem::drop(engine3);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_data_file_footer() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-data-file-footer"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..200 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let res1 = engine.close();
+    assert!(res1.is_ok());
+
+    // 1.切换活跃文件和关闭时写入 footer，所有的文件都可以整体校验
+    let older_records: u64 = engine
+        .older_files
+        .read()
+        .values()
+        .map(|f| f.verify_footer().unwrap().unwrap())
+        .sum();
+    let active_records = engine.active_file.read().verify_footer().unwrap().unwrap();
+    assert_eq!(200, older_records + active_records);
+    assert_eq!(200, engine.verify().unwrap().valid_records);
+    let active_file_name = opts.dir_path.join(std::format!(
+        "{:09}.data",
+        engine.active_file.read().get_file_id()
+    ));
+    std::mem::drop(engine);
+
+    // 没有写入新的数据时多次关闭和重启不会重复写入 footer
+    let file_size = std::fs::metadata(&active_file_name).unwrap().len();
+    for _ in 0..3 {
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine.close().is_ok());
+        std::mem::drop(engine);
+    }
+    assert_eq!(
+        file_size,
+        std::fs::metadata(&active_file_name).unwrap().len()
+    );
+
+    // 2.重启之后继续写入，关闭时活跃文件的 footer 仍然正确，读取数据时忽略 footer
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    let res2 = engine2.put(get_test_key(200), get_test_value(200));
+    assert!(res2.is_ok());
+    let res3 = engine2.close();
+    assert!(res3.is_ok());
+    assert!(engine2
+        .active_file
+        .read()
+        .verify_footer()
+        .unwrap()
+        .is_some());
+    assert_eq!(201, engine2.list_keys().unwrap().len());
+    assert_eq!(201, engine2.verify().unwrap().valid_records);
+
+    // 3.篡改一个字节之后整体校验失败，逐条校验找到损坏的记录
+    let pos = engine2.index.get(get_test_key(10).to_vec()).unwrap();
+    let file_name = opts.dir_path.join(std::format!("{:09}.data", pos.file_id));
+    let mut content = std::fs::read(file_name.clone()).unwrap();
+    content[pos.offset as usize + 40] ^= 0x01;
+    std::fs::write(file_name, content).unwrap();
+    let older_files = engine2.older_files.read();
+    assert!(older_files[&pos.file_id].verify_footer().unwrap().is_none());
+    std::mem::drop(older_files);
+    let report = engine2.verify().unwrap();
+    assert_eq!(200, report.valid_records);
+    assert_eq!(Some((pos.file_id, pos.offset)), report.first_invalid);
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_reopen_with_each_index_type() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-reopen-index-type"),
+        data_file_size: 4 * 1024,
+        index_type: IndexType::BTree,
+        ..Default::default()
+    };
+
+    // 使用 BTree 索引写入数据，包括覆盖写入和删除，数据分布在多个数据文件中
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in (0..200).rev() {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 0..50 {
+        let res = engine.put(get_test_key(i), get_test_value(i + 1000));
+        assert!(res.is_ok());
+    }
+    for i in 50..100 {
+        let res = engine.delete(get_test_key(i));
+        assert!(res.is_ok());
+    }
+    assert!(engine.active_file_id() > 0);
+    std::mem::drop(engine);
+
+    // 依次使用每种索引类型打开，读取到的数据和顺序都相同
+    let read_all = |engine: &Engine| {
+        let mut forward = Vec::new();
+        let iter = engine.iter(IteratorOptions::default());
+        while let Some(item) = iter.next() {
+            forward.push(item);
+        }
+        let iter_opts = IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        let mut backward = Vec::new();
+        let iter = engine.iter(iter_opts);
+        while let Some(item) = iter.next() {
+            backward.push(item);
+        }
+        let values: Vec<Option<Bytes>> =
+            (0..200).map(|i| engine.get(get_test_key(i)).ok()).collect();
+        (engine.list_keys().unwrap(), forward, backward, values)
+    };
+
+    let mut expected = None;
+    for index_type in [
+        IndexType::BTree,
+        IndexType::SnapshotBTree,
+        IndexType::HashMap,
+    ] {
+        let mut opts2 = opts.clone();
+        opts2.index_type = index_type;
+        let engine2 = Engine::open(opts2).expect("failed to open engine");
+        let res = read_all(&engine2);
+        assert_eq!(150, res.0.len());
+        assert_eq!(get_test_key(0), res.1[0].0);
+        assert_eq!(get_test_value(1000), res.1[0].1);
+        assert!(res.3[50..100].iter().all(|v| v.is_none()));
+        match &expected {
+            Some(expected) => assert_eq!(expected, &res),
+            None => expected = Some(res),
+        }
+        std::mem::drop(engine2);
+    }
+
+    // 还没有实现的跳表索引打开时返回错误
+    let mut opts3 = opts.clone();
+    opts3.index_type = IndexType::SkipList;
+    let res3 = Engine::open(opts3);
+    assert_eq!(Errors::UnsupportedIndexType, res3.err().unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_async_rollover_sync() {
+    for async_rollover_sync in [false, true] {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-async-rollover-sync"),
+            data_file_size: 4 * 1024,
+            async_rollover_sync,
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..100 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        assert!(engine.active_file_id() > 0);
+
+        // 开启之后切换活跃文件时写入线程不再持久化旧的数据文件
+        let older_syncs = engine.older_files.read()[&0]
+            .sync_count
+            .load(Ordering::SeqCst);
+        assert_eq!(!async_rollover_sync as usize, older_syncs);
+
+        // 关闭时会等待后台的持久化完成，重启之后数据不变
+        let res1 = engine.close();
+        assert!(res1.is_ok());
+        std::mem::drop(engine);
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..100 {
+            assert_eq!(get_test_value(i), engine2.get(get_test_key(i)).unwrap());
+        }
+        std::mem::drop(engine2);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
+
+#[test]
+fn test_engine_record_alignment() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-record-alignment"),
+        data_file_size: 4 * 1024,
+        record_alignment: 8,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i).slice(..i % 50));
+        assert!(res.is_ok());
+    }
+    let res1 = engine.delete(get_test_key(0));
+    assert!(res1.is_ok());
+    assert!(engine.active_file_id() > 0);
+
+    // 每条记录都从对齐的位置开始
+    for i in 1..100 {
+        let pos = engine.index.get(get_test_key(i).to_vec()).unwrap();
+        assert_eq!(0, pos.offset % 8);
+    }
+    assert_eq!(101, engine.verify().unwrap().valid_records);
+    std::mem::drop(engine);
+
+    // 不对齐重新打开之后仍然可以读取对齐的记录，之后的记录不再填充
+    let mut opts2 = opts.clone();
+    opts2.record_alignment = 1;
+    let engine2 = Engine::open(opts2).expect("failed to open engine");
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine2.get(get_test_key(0)).err().unwrap()
+    );
+    for i in 1..100 {
+        assert_eq!(
+            get_test_value(i).slice(..i % 50),
+            engine2.get(get_test_key(i)).unwrap()
+        );
+    }
+    let res2 = engine2.put(get_test_key(100), get_test_value(100));
+    assert!(res2.is_ok());
+    assert_eq!(get_test_value(100), engine2.get(get_test_key(100)).unwrap());
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_truncate_on_recovery() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-truncate-on-recovery"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..10 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let write_off = engine.active_file.read().get_write_off();
+    std::mem::drop(engine);
+
+    // 在最后一条记录之后追加无法解析的数据
+    let file_name = opts.dir_path.join(std::format!("{:09}.data", 0));
+    let mut content = std::fs::read(&file_name).unwrap();
+    content.extend_from_slice(&[0xff; 100]);
+    std::fs::write(&file_name, &content).unwrap();
+
+    // 1.不截断时拒绝打开，数据文件不变
+    let mut opts2 = opts.clone();
+    opts2.truncate_on_recovery = false;
+    let res1 = Engine::open(opts2.clone());
+    assert_eq!(Errors::TrailingDataInActiveFile, res1.err().unwrap());
+    assert_eq!(
+        write_off + 100,
+        std::fs::metadata(&file_name).unwrap().len()
+    );
+
+    // 2.默认截断末尾的数据，之后的写入在重启之后可以正常读取
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(write_off, std::fs::metadata(&file_name).unwrap().len());
+    let res2 = engine2.put(get_test_key(10), get_test_value(10));
+    assert!(res2.is_ok());
+    std::mem::drop(engine2);
+
+    let engine3 = Engine::open(opts2).expect("failed to open engine");
+    for i in 0..=10 {
+        assert_eq!(get_test_value(i), engine3.get(get_test_key(i)).unwrap());
+    }
+    std::mem::drop(engine3);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_get_versioned() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-get-versioned"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 每次 put 版本加 1
+    for i in 0..3 {
+        let res = engine.put(get_test_key(1), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let (value, version) = engine.get_versioned(get_test_key(1)).unwrap();
+    assert_eq!(get_test_value(2), value);
+    assert_eq!(3, version);
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get_versioned(get_test_key(2)).err().unwrap()
+    );
+
+    // 删除之后版本重新从 1 开始
+    let res = engine.put(get_test_key(2), get_test_value(2));
+    assert!(res.is_ok());
+    let res = engine.delete(get_test_key(2));
+    assert!(res.is_ok());
+    let res = engine.put(get_test_key(2), get_test_value(2));
+    assert!(res.is_ok());
+    assert_eq!(1, engine.get_versioned(get_test_key(2)).unwrap().1);
+
+    // 重启之后从数据文件中恢复最新的版本
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(3, engine2.get_versioned(get_test_key(1)).unwrap().1);
+    assert_eq!(1, engine2.get_versioned(get_test_key(2)).unwrap().1);
+
+    // 移动到活跃文件中不改变版本，之后的写入继续递增
+    let res = engine2.compact_key(get_test_key(1));
+    assert!(res.is_ok());
+    assert_eq!(3, engine2.get_versioned(get_test_key(1)).unwrap().1);
+    let res = engine2.put(get_test_key(1), get_test_value(3));
+    assert!(res.is_ok());
+    assert_eq!(4, engine2.get_versioned(get_test_key(1)).unwrap().1);
+
+    // 删除测试的文件夹
+    std::mem::drop(engine2);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_max_data_files() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-max-data-files"),
+        data_file_size: 4 * 1024,
+        max_data_files: 4,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 反复覆盖写入少量的 key，数据文件的数量始终不超过上限
+    for i in 0..5000 {
+        let res = engine.put(get_test_key(i % 20), get_test_value(i));
+        assert!(res.is_ok());
+        assert!(engine.stat().unwrap().data_file_num <= 4);
+    }
+    for i in 4980..5000 {
+        assert_eq!(get_test_value(i), engine.get(get_test_key(i % 20)).unwrap());
+    }
+
+    // 有效的数据超过上限时，合并无法减少文件数量，写入返回错误
+    let mut res = Ok(());
+    for i in 0..5000 {
+        res = engine.put(get_test_key(i + 20), get_test_value(i));
+        if res.is_err() {
+            break;
+        }
+    }
+    assert_eq!(Errors::TooManyDataFiles, res.err().unwrap());
+    assert!(engine.stat().unwrap().data_file_num <= 4);
+    assert_eq!(Errors::TooManyDataFiles, engine.rotate().err().unwrap());
+
+    // 重启之后数据不受影响
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 4980..5000 {
+        assert_eq!(
+            get_test_value(i),
+            engine2.get(get_test_key(i % 20)).unwrap()
+        );
+    }
+
+    // 删除测试的文件夹
+    std::mem::drop(engine2);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_max_data_files_record_alignment() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-max-data-files-alignment"),
+        data_file_size: 4 * 1024,
+        max_data_files: 4,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 第一个数据文件中有之后不会覆盖的 key，合并时需要重新写入
+    for i in 100..105 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    // 不对齐写入，直到数据文件的数量达到上限并且活跃文件快要写满
+    let mut i = 0;
+    loop {
+        let res = engine.put(get_test_key(i % 20), get_test_value(i).slice(..i % 50));
+        assert!(res.is_ok());
+        i += 1;
+        if engine.stat().unwrap().data_file_num == 4
+            && engine.active_file.read().get_write_off() + 100 > opts.data_file_size
+        {
+            break;
+        }
+    }
+    std::mem::drop(engine);
+
+    // 对齐重新打开，活跃文件的写入位置没有对齐
+    let mut opts2 = opts.clone();
+    opts2.record_alignment = 64;
+    let engine2 = Engine::open(opts2.clone()).expect("failed to open engine");
+    let write_off = engine2.active_file.read().get_write_off();
+    assert_ne!(0, write_off % 64);
+    let min_fid = engine2.older_files.read().keys().min().copied();
+
+    // 写入时先合并，合并时重新写入的数据切换了活跃文件，记录需要按照新的写入位置重新计算填充
+    let value = get_test_value(i);
+    let res = engine2.put(get_test_key(0), value.clone());
+    assert!(res.is_ok());
+    assert_ne!(min_fid, engine2.older_files.read().keys().min().copied());
+    let pos = engine2.index.get(get_test_key(0).to_vec()).unwrap();
+    assert_eq!(engine2.active_file_id(), pos.file_id);
+    assert_eq!(0, pos.offset % 64);
+    assert_eq!(0, engine2.active_file.read().get_write_off() % 64);
+    assert_eq!(value, engine2.get(get_test_key(0)).unwrap());
+    assert_eq!(None, engine2.verify().unwrap().first_invalid);
+    std::mem::drop(engine2);
+
+    // 重启之后可以读取写入的数据
+    let engine3 = Engine::open(opts2.clone()).expect("failed to open engine");
+    assert_eq!(value, engine3.get(get_test_key(0)).unwrap());
+    for i in 100..105 {
+        assert_eq!(get_test_value(i), engine3.get(get_test_key(i)).unwrap());
+    }
+
+    // 删除测试的文件夹
+    std::mem::drop(engine3);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+// 记录创建的 span 的名字和创建 span 的线程，用于测试 tracing 的输出
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+struct SpanRecorder {
+    spans: parking_lot::Mutex<Vec<&'static str>>,
+    threads: parking_lot::Mutex<Vec<std::thread::ThreadId>>,
+    next_id: AtomicU64,
+}
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for SpanRecorder {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        self.spans.lock().push(span.metadata().name());
+        self.threads.lock().push(std::thread::current().id());
+        tracing::span::Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, _: &tracing::Event<'_>) {}
+
+    fn enter(&self, _: &tracing::span::Id) {}
+
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_engine_tracing_spans() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-tracing-spans"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let recorder = Arc::new(SpanRecorder::default());
+
+    // with_default 只在当前线程的这个作用域中生效，不会设置全局的 subscriber，
+    // 同时运行的其他测试创建的 span 不会被记录
+    tracing::subscriber::with_default(recorder.clone(), || {
+        // 写入数据切换活跃文件
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..200 {
+            let res = engine.put(get_test_key(i % 20), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        std::mem::drop(engine);
+        assert!(recorder.spans.lock().contains(&"rollover"));
+
+        // 重新打开时加载索引并恢复活跃文件
+        recorder.spans.lock().clear();
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(
+            vec!["open", "load_index_from_data_files", "recover_active_file"],
+            *recorder.spans.lock()
+        );
+
+        // 合并旧的数据文件
+        recorder.spans.lock().clear();
+        let res = engine2.merge_partial(1);
+        assert_eq!(1, res.unwrap());
+        assert_eq!(Some(&"merge"), recorder.spans.lock().first());
+    });
+
+    // 记录的 span 都是在当前线程中创建的，作用域结束之后创建的 span 不会再被记录
+    let current = std::thread::current().id();
+    assert!(recorder.threads.lock().iter().all(|id| *id == current));
+    let count = recorder.spans.lock().len();
+    let engine3 = Engine::open(opts.clone()).expect("failed to open engine");
+    std::mem::drop(engine3);
+    assert_eq!(count, recorder.spans.lock().len());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_io_buffer_flush() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-io-buffer-flush"),
+        data_file_size: 64 * 1024 * 1024,
+        io_buffer_size: 64 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let file_name = opts.dir_path.join("000000000.data");
+
+    // 写入的数据先放在缓冲区中，可以直接读取到
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert_eq!(0, std::fs::metadata(&file_name).unwrap().len());
+    assert_eq!(get_test_value(10), engine.get(get_test_key(10)).unwrap());
+
+    // flush 之后数据写入文件，但不进行持久化
+    let res = engine.put(get_test_key(100), get_test_value(100));
+    assert!(res.is_ok());
+    let sync_count = engine.active_file.read().sync_count.load(Ordering::SeqCst);
+    let res = engine.flush();
+    assert!(res.is_ok());
+    let write_off = engine.active_file.read().get_write_off();
+    assert_eq!(write_off, std::fs::metadata(&file_name).unwrap().len());
+    assert_eq!(
+        sync_count,
+        engine.active_file.read().sync_count.load(Ordering::SeqCst)
+    );
+
+    // 重启之后数据不受影响
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..=100 {
+        assert_eq!(get_test_value(i), engine2.get(get_test_key(i)).unwrap());
+    }
+
+    // 删除测试的文件夹
+    std::mem::drop(engine2);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_rename() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-rename"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.移动到不存在的 key
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+    let res2 = engine.rename(get_test_key(1), get_test_key(2));
+    assert!(res2.is_ok());
+    assert_eq!(get_test_value(1), engine.get(get_test_key(2)).unwrap());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get(get_test_key(1)).err().unwrap()
+    );
+
+    // 2.覆盖已经存在的 key
+    let res3 = engine.put(get_test_key(3), get_test_value(3));
+    assert!(res3.is_ok());
+    let res4 = engine.rename(get_test_key(3), get_test_key(2));
+    assert!(res4.is_ok());
+    assert_eq!(get_test_value(3), engine.get(get_test_key(2)).unwrap());
+
+    // 3.from 不存在
+    let res5 = engine.rename(get_test_key(1), get_test_key(4));
+    assert_eq!(Errors::KeyNotFound, res5.err().unwrap());
+    let res6 = engine.rename(Bytes::new(), get_test_key(4));
+    assert_eq!(Errors::KeyIsEmpty, res6.err().unwrap());
+
+    // 4.from 和 to 相同时不修改
+    let res7 = engine.rename(get_test_key(2), get_test_key(2));
+    assert!(res7.is_ok());
+    assert_eq!(get_test_value(3), engine.get(get_test_key(2)).unwrap());
+
+    // 重启之后数据不受影响
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(get_test_value(3), engine2.get(get_test_key(2)).unwrap());
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine2.get(get_test_key(1)).err().unwrap()
+    );
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine2.get(get_test_key(3)).err().unwrap()
+    );
+    assert_eq!(1, engine2.list_keys().unwrap().len());
+
+    // 删除测试的文件夹
+    std::mem::drop(engine2);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_index_memory_usage() {
+    for index_type in [
+        IndexType::BTree,
+        IndexType::SnapshotBTree,
+        IndexType::HashMap,
+    ] {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-index-memory-usage"),
+            data_file_size: 64 * 1024 * 1024,
+            index_type,
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(0, engine.index_memory_usage());
+
+        // 写入新的 key 时估算的内存大小单调递增
+        let mut last = 0;
+        for i in 0..100 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+            let usage = engine.index_memory_usage();
+            assert!(usage > last);
+            last = usage;
+        }
+
+        // 删除 key 之后减小
+        let res = engine.delete(get_test_key(0));
+        assert!(res.is_ok());
+        assert!(engine.index_memory_usage() < last);
+
+        // 删除测试的文件夹
+        std::mem::drop(engine);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
+
+#[test]
+fn test_engine_lock_timeout() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-lock-timeout"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.文件锁被持有时打开失败，设置了等待时间时等待之后失败
+    let res1 = Engine::open(opts.clone());
+    assert_eq!(Errors::DatabaseIsUsing, res1.err().unwrap());
+    let mut opts2 = opts.clone();
+    opts2.lock_timeout = Some(Duration::from_millis(50));
+    let start = Instant::now();
+    let res2 = Engine::open(opts2.clone());
+    assert_eq!(Errors::DatabaseIsUsing, res2.err().unwrap());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    // 2.其他进程写入的同时可以以只读模式打开并读取数据，只读模式下不获取文件锁
+    let res3 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res3.is_ok());
+    engine.sync().unwrap();
+    let mut opts3 = opts.clone();
+    opts3.read_only = true;
+    let ro_engine1 = Engine::open(opts3.clone()).expect("failed to open engine");
+    let ro_engine2 = Engine::open(opts3.clone()).expect("failed to open engine");
+    assert_eq!(get_test_value(1), ro_engine1.get(get_test_key(1)).unwrap());
+    assert_eq!(get_test_value(1), ro_engine2.get(get_test_key(1)).unwrap());
+    let res4 = engine.put(get_test_key(2), get_test_value(2));
+    assert!(res4.is_ok());
+    assert_eq!(get_test_value(1), ro_engine1.get(get_test_key(1)).unwrap());
+
+    // 3.等待的过程中文件锁被释放之后打开成功，只读的数据库不加锁，不会阻止以读写模式打开
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        std::mem::drop(engine);
+    });
+    opts2.lock_timeout = Some(Duration::from_secs(10));
+    let res5 = Engine::open(opts2.clone());
+    assert!(res5.is_ok());
+    handle.join().unwrap();
+    let engine2 = res5.unwrap();
+    assert_eq!(get_test_value(2), engine2.get(get_test_key(2)).unwrap());
+    assert_eq!(get_test_value(1), ro_engine2.get(get_test_key(1)).unwrap());
+    std::mem::drop(ro_engine1);
+    std::mem::drop(ro_engine2);
+
+    // 删除测试的文件夹
+    std::mem::drop(engine2);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_files_info() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-files-info"),
+        data_file_size: 4 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.只有一个空的活跃文件
+    let infos1 = engine.files_info().unwrap();
+    assert_eq!(1, infos1.len());
+    assert_eq!(engine.active_file_id(), infos1[0].file_id);
+    assert_eq!(0, infos1[0].size);
+    assert_eq!(0, infos1[0].reclaimable);
+
+    // 2.覆盖写入之后，前面的文件中有可以回收的空间
+    for i in 0..300 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i + 1000));
+        assert!(res.is_ok());
+    }
+    let stat = engine.stat().unwrap();
+    let infos2 = engine.files_info().unwrap();
+    assert_eq!(stat.data_file_num, infos2.len());
+    assert_eq!(
+        stat.disk_size,
+        infos2.iter().map(|info| info.size).sum::<u64>()
+    );
+    assert!(infos2.windows(2).all(|w| w[0].file_id < w[1].file_id));
+    assert_eq!(engine.active_file_id(), infos2.last().unwrap().file_id);
+    for info in &infos2 {
+        assert!(info.size > 0);
+        assert!(info.reclaimable <= info.size);
+    }
+    assert!(infos2[0].reclaimable > 0);
+    assert_eq!(0, infos2.last().unwrap().reclaimable);
+
+    // 3.合并之后旧的数据文件中没有可以回收的空间
+    let res1 = engine.merge_partial(usize::MAX);
+    assert!(res1.is_ok());
+    let infos3 = engine.files_info().unwrap();
+    for info in &infos3 {
+        assert_eq!(0, info.reclaimable);
+    }
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_verify_crc_on_read() {
+    for verify_crc_on_read in [true, false] {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-verify-crc-on-read"),
+            verify_crc_on_read,
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let res1 = engine.put(get_test_key(1), Bytes::from("value-1"));
+        assert!(res1.is_ok());
+        let res2 = engine.put(get_test_key(2), Bytes::from("value-2"));
+        assert!(res2.is_ok());
+
+        // 篡改 key-1 的 value 的第一个字节，header 包括类型、key 和 value 的长度以及版本
+        let key = get_test_key(1);
+        let pos = engine.index.get(key.to_vec()).unwrap();
+        let file_name = opts.dir_path.join(std::format!("{:09}.data", pos.file_id));
+        let mut content = std::fs::read(file_name.clone()).unwrap();
+        content[pos.offset as usize + 4 + key.len()] ^= 0xff;
+        std::fs::write(file_name, content).unwrap();
+
+        // 1.开启校验时返回错误，关闭校验时不计算 crc，直接返回篡改之后的 value
+        let res3 = engine.get(key);
+        match verify_crc_on_read {
+            true => assert_eq!(Errors::InvalidLogRecordCrc, res3.err().unwrap()),
+            false => {
+                let value = res3.unwrap();
+                assert_ne!(Bytes::from("value-1"), value);
+                assert_eq!(&b"alue-1"[..], &value[1..]);
+            }
+        }
+        assert_eq!(Bytes::from("value-2"), engine.get(get_test_key(2)).unwrap());
+
+        // 2.verify 仍然会校验 crc
+        let report = engine.verify().unwrap();
+        assert_eq!(1, report.valid_records);
+        assert_eq!(1, report.invalid_records);
+        std::mem::drop(engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
+
+#[test]
+fn test_engine_write_shards() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-write-shards"),
+        data_file_size: 16 * 1024,
+        write_shards: 4,
+        ..Default::default()
+    };
+    let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+
+    // 1.每个写入分片都有一个活跃文件
+    assert_eq!(4, engine.stat().unwrap().data_file_num);
+
+    // 2.多个线程并发写入、覆盖和删除，之后读取到每个 key 最新的数据
+    let handles: Vec<_> = (0..8)
+        .map(|t| {
+            let engine = engine.clone();
+            std::thread::spawn(move || {
+                for i in t * 500..(t + 1) * 500 {
+                    let res = engine.put(get_test_key(i), get_test_value(i));
+                    assert!(res.is_ok());
+                }
+                for i in t * 500..t * 500 + 100 {
+                    let res = engine.put(get_test_key(i), get_test_value(i + 10000));
+                    assert!(res.is_ok());
+                }
+                for i in t * 500 + 100..t * 500 + 150 {
+                    let res = engine.delete(get_test_key(i));
+                    assert!(res.is_ok());
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let check = |engine: &Engine| {
+        for i in 0..4000 {
+            let res = engine.get(get_test_key(i));
+            match i % 500 {
+                0..=99 => assert_eq!(get_test_value(i + 10000), res.unwrap()),
+                100..=149 => assert_eq!(Errors::KeyNotFound, res.err().unwrap()),
+                _ => assert_eq!(get_test_value(i), res.unwrap()),
+            }
+        }
+    };
+    check(&engine);
+    assert_eq!(3600, engine.list_keys().unwrap().len());
+    assert!(engine.stat().unwrap().data_file_num > 4);
+
+    // 3.合并之后以及重新打开之后数据不变，重新打开时可以使用不同的分片数量
+    let res1 = engine.merge_partial(usize::MAX);
+    assert!(res1.is_ok());
+    check(&engine);
+    std::mem::drop(engine);
+
+    // 模拟崩溃时没有写完的记录，每个分片的活跃文件末尾都可能有不完整的记录
+    // 数据库目录中记录了每个分片的活跃文件，选择其中 id 最小的文件
+    let (_, unsealed) = UnsealedFiles::load(&opts.dir_path).unwrap();
+    let unsealed = unsealed.unwrap();
+    assert_eq!(4, unsealed.len());
+    let file_name = opts
+        .dir_path
+        .join(std::format!("{:09}.data", unsealed.first().unwrap()));
+    let mut content = std::fs::read(&file_name).unwrap();
+    content.extend_from_slice(&[0x01, 0x20, 0x20]);
+    std::fs::write(&file_name, content).unwrap();
+
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    check(&engine2);
+    let res2 = engine2.close();
+    assert!(res2.is_ok());
+    std::mem::drop(engine2);
+
+    let mut opts2 = opts.clone();
+    opts2.write_shards = 1;
+    let engine3 = Engine::open(opts2).expect("failed to open engine");
+    check(&engine3);
+    std::mem::drop(engine3);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_write_shards_quiet_shard_torn_tail() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-write-shards-torn-tail"),
+        data_file_size: 4 * 1024,
+        write_shards: 2,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 第二个分片只写入少量数据，第一个分片写入大量数据，滚动多次之后文件 id 远大于第二个分片的活跃文件
+    let (busy, quiet): (Vec<usize>, Vec<usize>) =
+        (0..2000).partition(|i| engine.shard_of(&get_test_key(*i)) == 0);
+    for &i in quiet.iter().take(5) {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for &i in busy.iter() {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    // 新建的数据库中第二个分片的活跃文件 id 为 1，写入的数据很少，不会滚动
+    let quiet_fid = 1;
+    assert!(engine.active_file.read().get_file_id() > quiet_fid + 2);
+
+    // 模拟崩溃，没有写入 footer，写入量少的分片的活跃文件末尾有没有写完的记录
+    std::mem::drop(engine);
+    let file_name = opts.dir_path.join(std::format!("{:09}.data", quiet_fid));
+    let mut content = std::fs::read(&file_name).unwrap();
+    let size = content.len() as u64;
+    content.extend_from_slice(&[0x01, 0x20, 0x20]);
+    std::fs::write(&file_name, content).unwrap();
+
+    // 重新打开时忽略并截断不完整的记录
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(size, std::fs::metadata(&file_name).unwrap().len());
+    for &i in quiet.iter().take(5).chain(busy.iter()) {
+        assert_eq!(get_test_value(i), engine2.get(get_test_key(i)).unwrap());
+    }
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_corrupted_sealed_file_without_footer() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-sealed-without-footer"),
+        data_file_size: 400,
+        ..Default::default()
+    };
+    let key = |i: usize| Bytes::from(std::format!("key-{}", i));
+    let value = |i: usize| Bytes::from(std::format!("value-{}", i));
+
+    // 1.写入数据后模拟崩溃，0 号文件没有写入 footer
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..3 {
+        assert!(engine.put(key(i), value(i)).is_ok());
+    }
+    std::mem::drop(engine);
+
+    // 2.重新打开后继续写入直到 0 号文件滚动，滚动后的 0 号文件仍然没有 footer
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    let mut count = 3;
+    while engine2.active_file.read().get_file_id() == 0 {
+        assert!(engine2.put(key(count), value(count)).is_ok());
+        count += 1;
+    }
+    assert!(engine2.close().is_ok());
+    std::mem::drop(engine2);
+
+    // 3.修改 0 号文件中间一条记录的 value 长度，记录超出了文件的末尾
+    let data_file = DataFile::open_read_only(opts.dir_path.clone(), 0).unwrap();
+    let offsets = data_file
+        .records()
+        .map(|res| res.unwrap().0.offset)
+        .collect::<Vec<_>>();
+    std::mem::drop(data_file);
+    let file_name = opts.dir_path.join(std::format!("{:09}.data", 0));
+    let mut content = std::fs::read(&file_name).unwrap();
+    let size = content.len() as u64;
+    let offset = offsets[offsets.len() / 2] as usize;
+    content[offset + 2..offset + 4].copy_from_slice(&[0xff, 0x7f]);
+    std::fs::write(&file_name, content).unwrap();
+
+    // 上次打开时 0 号文件已经写满，即使没有 footer 也不能当作不完整的记录截断
+    let res = Engine::open(opts.clone());
+    assert_eq!(Errors::InvalidLogRecordCrc, res.err().unwrap());
+    assert_eq!(size, std::fs::metadata(&file_name).unwrap().len());
+
+    // 4.没有记录正在写入的数据文件时（旧版本的数据库），只有最后一个数据文件会被截断
+    std::fs::remove_file(opts.dir_path.join(UNSEALED_FILE_NAME)).unwrap();
+    let res = Engine::open(opts.clone());
+    assert_eq!(Errors::InvalidLogRecordCrc, res.err().unwrap());
+    assert_eq!(size, std::fs::metadata(&file_name).unwrap().len());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_write_shards_concurrent_writers() {
+    for write_shards in [1, 4] {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-write-shards-concurrent"),
+            write_shards,
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 找到两个在不同分片中的 key，只有一个分片时所有的 key 都在同一个分片中
+        let key1 = get_test_key(0);
+        let key2 = (1..)
+            .map(get_test_key)
+            .find(|k| write_shards == 1 || engine.shard_of(k) != engine.shard_of(&key1))
+            .unwrap();
+
+        // 1.持有一个分片的写锁时，其他分片的写入不会被阻塞
+        let write_guard = engine.lock_shard(&key1);
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let res = engine.put(key2.clone(), get_test_value(2));
+                tx.send(res.is_ok()).unwrap();
+            });
+            let res = rx.recv_timeout(Duration::from_millis(200));
+            match write_shards {
+                1 => assert!(res.is_err()),
+                _ => assert!(res.unwrap()),
+            }
+            std::mem::drop(write_guard);
+        });
+        if write_shards == 1 {
+            assert!(rx.recv().unwrap());
+        }
+        assert_eq!(get_test_value(2), engine.get(key2).unwrap());
+
+        // 2.多个线程并发写入，写入的数据全部可以读取，重启之后同样可以读取
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let engine = &engine;
+                s.spawn(move || {
+                    for i in t * 250..(t + 1) * 250 {
+                        let res = engine.put_synced(get_test_key(i), get_test_value(i));
+                        assert!(res.is_ok());
+                    }
+                });
+            }
+        });
+        for i in 0..1000 {
+            assert_eq!(get_test_value(i), engine.get(get_test_key(i)).unwrap());
+        }
+        std::mem::drop(engine);
+
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(1000, engine2.list_keys().unwrap().len());
+        for i in 0..1000 {
+            assert_eq!(get_test_value(i), engine2.get(get_test_key(i)).unwrap());
+        }
+        std::mem::drop(engine2);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
+
+#[test]
+fn test_engine_delete_range() {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-delete-range"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for key in ["a", "b", "c", "d", "e", "f", "g", "h"] {
+        let res = engine.put(Bytes::from(key), get_test_value(1));
+        assert!(res.is_ok());
+    }
+
+    // 1.删除 [c, f) 范围内的 key，不包括 f
+    let res1 = engine.delete_range(Bytes::from("c"), Bytes::from("f"));
+    assert_eq!(3, res1.unwrap());
+    let keys = engine.list_keys().unwrap();
+    assert_eq!(
+        vec!["a", "b", "f", "g", "h"],
+        keys.iter()
+            .map(|k| std::str::from_utf8(k).unwrap())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine.get(Bytes::from("d")).err().unwrap()
+    );
+
+    // 2.起点不小于终点时不删除任何 key
+    let res2 = engine.delete_range(Bytes::from("g"), Bytes::from("b"));
+    assert_eq!(0, res2.unwrap());
+    let res3 = engine.delete_range(Bytes::from("g"), Bytes::from("g"));
+    assert_eq!(0, res3.unwrap());
+    assert_eq!(5, engine.list_keys().unwrap().len());
+
+    // 3.范围内没有 key，以及范围超出所有的 key
+    let res4 = engine.delete_range(Bytes::from("c"), Bytes::from("f"));
+    assert_eq!(0, res4.unwrap());
+    let res5 = engine.delete_range(Bytes::from("g"), Bytes::from("z"));
+    assert_eq!(2, res5.unwrap());
+    std::mem::drop(engine);
+
+    // 重启之后删除的 key 仍然不存在
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(3, engine2.list_keys().unwrap().len());
+    std::mem::drop(engine2);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
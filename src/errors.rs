@@ -1,4 +1,4 @@
-use std::result;
+use std::{io, result};
 
 use thiserror::Error;
 
@@ -31,6 +31,9 @@ pub enum Errors {
     #[error("database dir path can not be empty")]
     DirPathIsEmpty,
 
+    #[error("database dir path exists but is not a directory")]
+    DirPathIsNotDirectory,
+
     #[error("database data file size must be greater than 0")]
     DataFileSizeTooSmall,
 
@@ -48,6 +51,212 @@ pub enum Errors {
 
     #[error("invalid crc value, log record maybe corrupted")]
     InvalidLogRecordCrc,
+
+    #[error("the database is opened in read-only mode")]
+    ReadOnlyDatabase,
+
+    #[error("failed to write export data")]
+    FailedToWriteExportData,
+
+    #[error("failed to read import data")]
+    FailedToReadImportData,
+
+    #[error("invalid import data, maybe not an exported file or truncated")]
+    InvalidImportData,
+
+    #[error("unsupported export format version")]
+    UnsupportedExportVersion,
+
+    #[error("failed to run the blocking task")]
+    BlockingTaskFailed,
+
+    #[error("the encoded log record is larger than the data file size")]
+    ValueTooLarge,
+
+    #[error("failed to remove data file")]
+    FailedToRemoveDataFile,
+
+    #[error("the io manager does not support positional writes")]
+    WriteAtNotSupported,
+
+    #[error("the options conflict with the ones the database was created with")]
+    OptionsMismatch,
+
+    #[error("failed to write the database meta file")]
+    FailedToWriteMetaFile,
+
+    #[error("the data format version is newer than this version supports")]
+    UnsupportedFormatVersion,
+
+    #[error("failed to sync the database directory")]
+    FailedToSyncDatabaseDir,
+
+    #[error("failed to serialize or deserialize the key or value")]
+    SerializationFailed,
+
+    #[error("database load threads must be greater than 0")]
+    LoadThreadsIsZero,
+
+    #[error("inline value threshold is larger than the max inline value size")]
+    InlineValueThresholdTooLarge,
+
+    #[error("the key of the record read from data file does not match, index maybe corrupted")]
+    KeyMismatch,
+
+    #[error("insufficient free disk space for the write")]
+    InsufficientDiskSpace,
+
+    #[error("record alignment must be a power of two no larger than the max record alignment")]
+    InvalidRecordAlignment,
+
+    #[error("active data file has unexpected data after the last valid record")]
+    TrailingDataInActiveFile,
+
+    #[error("max data files must be zero or at least 2")]
+    MaxDataFilesTooSmall,
+
+    #[error("the number of data files reached the max data files and merge can not reduce it")]
+    TooManyDataFiles,
+
+    #[error("the key already exists")]
+    KeyAlreadyExists,
+
+    #[error("the database directory is used by another process")]
+    DatabaseIsUsing,
+
+    #[error("write shards must be greater than 0")]
+    WriteShardsIsZero,
+
+    #[error("max data files can not be used with multiple write shards")]
+    MaxDataFilesWithWriteShards,
+
+    #[error("custom comparator can not be used with the hashmap index")]
+    ComparatorWithHashMapIndex,
+
+    #[error("the index type is not supported yet")]
+    UnsupportedIndexType,
+
+    #[error("io: {0}")]
+    Io(#[source] IoError),
+}
+
+impl Errors {
+    /// 是否是 key 不存在，不是真正的失败，调用方通常需要单独处理
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Errors::KeyNotFound)
+    }
+
+    /// 是否是数据损坏，包括数据文件、索引和导入的数据，重试不能解决，需要修复或者恢复数据
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            Errors::DataDirectoryCorrupted
+                | Errors::InvalidLogRecordCrc
+                | Errors::DataFileNotFound
+                | Errors::KeyMismatch
+                | Errors::TrailingDataInActiveFile
+                | Errors::InvalidImportData
+        )
+    }
+
+    /// 是否是读写文件或目录失败，包括底层的 IO 错误和磁盘空间不足，之后重试可能成功
+    pub fn is_io(&self) -> bool {
+        matches!(
+            self,
+            Errors::FailedToReadFromDataFile
+                | Errors::FailedWriteToDataFile
+                | Errors::FailedSyncDataFile
+                | Errors::FailedToOpenDataFile
+                | Errors::FailedToCreateDatabaseDir
+                | Errors::FailedToReadDatabaseDir
+                | Errors::FailedToWriteExportData
+                | Errors::FailedToReadImportData
+                | Errors::FailedToRemoveDataFile
+                | Errors::FailedToWriteMetaFile
+                | Errors::FailedToSyncDatabaseDir
+                | Errors::InsufficientDiskSpace
+                | Errors::Io(_)
+        )
+    }
+}
+
+/// 底层的 IO 错误，保留操作系统返回的错误信息，按照错误类型判断是否相等
+#[derive(Error, Debug)]
+#[error(transparent)]
+pub struct IoError(#[from] pub io::Error);
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.kind() == other.0.kind()
+    }
+}
+
+impl From<io::Error> for Errors {
+    fn from(e: io::Error) -> Self {
+        Errors::Io(IoError(e))
+    }
 }
 
 pub type Result<T> = result::Result<T, Errors>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_category() {
+        // 每个错误对应的分类：不存在、数据损坏、IO 错误，三者都不是的为 0
+        let cases = vec![
+            (Errors::FailedToReadFromDataFile, 3),
+            (Errors::FailedWriteToDataFile, 3),
+            (Errors::FailedSyncDataFile, 3),
+            (Errors::FailedToOpenDataFile, 3),
+            (Errors::KeyIsEmpty, 0),
+            (Errors::IndexUpdateFailed, 0),
+            (Errors::KeyNotFound, 1),
+            (Errors::DataFileNotFound, 2),
+            (Errors::DirPathIsEmpty, 0),
+            (Errors::DirPathIsNotDirectory, 0),
+            (Errors::DataFileSizeTooSmall, 0),
+            (Errors::FailedToCreateDatabaseDir, 3),
+            (Errors::FailedToReadDatabaseDir, 3),
+            (Errors::DataDirectoryCorrupted, 2),
+            (Errors::ReadDataFileEOF, 0),
+            (Errors::InvalidLogRecordCrc, 2),
+            (Errors::ReadOnlyDatabase, 0),
+            (Errors::FailedToWriteExportData, 3),
+            (Errors::FailedToReadImportData, 3),
+            (Errors::InvalidImportData, 2),
+            (Errors::UnsupportedExportVersion, 0),
+            (Errors::BlockingTaskFailed, 0),
+            (Errors::ValueTooLarge, 0),
+            (Errors::FailedToRemoveDataFile, 3),
+            (Errors::WriteAtNotSupported, 0),
+            (Errors::OptionsMismatch, 0),
+            (Errors::FailedToWriteMetaFile, 3),
+            (Errors::UnsupportedFormatVersion, 0),
+            (Errors::FailedToSyncDatabaseDir, 3),
+            (Errors::SerializationFailed, 0),
+            (Errors::LoadThreadsIsZero, 0),
+            (Errors::InlineValueThresholdTooLarge, 0),
+            (Errors::KeyMismatch, 2),
+            (Errors::InsufficientDiskSpace, 3),
+            (Errors::InvalidRecordAlignment, 0),
+            (Errors::TrailingDataInActiveFile, 2),
+            (Errors::MaxDataFilesTooSmall, 0),
+            (Errors::TooManyDataFiles, 0),
+            (Errors::KeyAlreadyExists, 0),
+            (Errors::DatabaseIsUsing, 0),
+            (Errors::WriteShardsIsZero, 0),
+            (Errors::MaxDataFilesWithWriteShards, 0),
+            (Errors::ComparatorWithHashMapIndex, 0),
+            (Errors::UnsupportedIndexType, 0),
+            (io::Error::from(io::ErrorKind::PermissionDenied).into(), 3),
+        ];
+        for (err, category) in cases {
+            assert_eq!(category == 1, err.is_not_found(), "{:?}", err);
+            assert_eq!(category == 2, err.is_corruption(), "{:?}", err);
+            assert_eq!(category == 3, err.is_io(), "{:?}", err);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::io::{ErrorKind, Read, Write};
+
+use bytes::Bytes;
+use log::error;
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+    options::IteratorOptions,
+};
+
+// 导出文件的格式，头部是魔数和格式版本，后面依次是每条 key/value 数据
+//
+// +--------------+---------+-------------+---------+---------------+---------+-----
+// | magic "BCKX" | version |  key size   |   key   |  value size   |  value  | ...
+// +--------------+---------+-------------+---------+---------------+---------+-----
+//      4字节        1字节     4字节(大端)     变长      4字节(大端)       变长
+const EXPORT_MAGIC: &[u8; 4] = b"BCKX";
+const EXPORT_VERSION: u8 = 1;
+
+impl Engine {
+    /// 将数据库中所有有效的 key/value 导出，用于跨机器或跨版本迁移数据
+    pub fn export(&self, mut w: impl Write) -> Result<()> {
+        write_all(&mut w, EXPORT_MAGIC)?;
+        write_all(&mut w, &[EXPORT_VERSION])?;
+
+        let iter = self.iter(IteratorOptions::default());
+        while let Some((key, value)) = iter.next() {
+            write_all(&mut w, &(key.len() as u32).to_be_bytes())?;
+            write_all(&mut w, &key)?;
+            write_all(&mut w, &(value.len() as u32).to_be_bytes())?;
+            write_all(&mut w, &value)?;
+        }
+
+        if let Err(e) = w.flush() {
+            error!("failed to flush export data: {}", e);
+            return Err(Errors::FailedToWriteExportData);
+        }
+        Ok(())
+    }
+
+    /// 导入 export 导出的数据，依次调用 put 写入，返回导入的数据条数
+    pub fn import(&self, mut r: impl Read) -> Result<usize> {
+        // 校验魔数和版本
+        let mut header = [0u8; 5];
+        if read_full(&mut r, &mut header)? != header.len() {
+            return Err(Errors::InvalidImportData);
+        }
+        if &header[..4] != EXPORT_MAGIC {
+            return Err(Errors::InvalidImportData);
+        }
+        if header[4] != EXPORT_VERSION {
+            return Err(Errors::UnsupportedExportVersion);
+        }
+
+        let mut count = 0;
+        // 读取到 key size 之前恰好结束，说明数据已经全部导入
+        while let Some(key_size) = read_size(&mut r)? {
+            let key = read_bytes(&mut r, key_size)?;
+            let value_size = match read_size(&mut r)? {
+                Some(size) => size,
+                None => return Err(Errors::InvalidImportData),
+            };
+            let value = read_bytes(&mut r, value_size)?;
+
+            self.put(key, value)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+fn write_all(w: &mut impl Write, buf: &[u8]) -> Result<()> {
+    if let Err(e) = w.write_all(buf) {
+        error!("failed to write export data: {}", e);
+        return Err(Errors::FailedToWriteExportData);
+    }
+    Ok(())
+}
+
+// 尽可能读满 buf，返回实际读取的字节数，读取到末尾时可能小于 buf 的长度
+fn read_full(r: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                error!("failed to read import data: {}", e);
+                return Err(Errors::FailedToReadImportData);
+            }
+        }
+    }
+    Ok(n)
+}
+
+// 读取 4 字节的长度字段，如果已经读到了末尾则返回 None
+fn read_size(r: &mut impl Read) -> Result<Option<usize>> {
+    let mut buf = [0u8; 4];
+    match read_full(r, &mut buf)? {
+        0 => Ok(None),
+        4 => Ok(Some(u32::from_be_bytes(buf) as usize)),
+        _ => Err(Errors::InvalidImportData),
+    }
+}
+
+fn read_bytes(r: &mut impl Read, size: usize) -> Result<Bytes> {
+    let mut buf = vec![0u8; size];
+    if read_full(r, &mut buf)? != size {
+        return Err(Errors::InvalidImportData);
+    }
+    Ok(buf.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{options::Options, util};
+
+    use super::*;
+
+    #[test]
+    fn test_export_and_import() {
+        let opts1 = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-export"),
+            ..Default::default()
+        };
+        let engine1 = Engine::open(opts1.clone()).expect("failed to open engine");
+
+        for i in 0..100 {
+            let put_res = engine1.put(
+                util::rand_kv::get_test_key(i),
+                util::rand_kv::get_test_value(i),
+            );
+            assert!(put_res.is_ok());
+        }
+        let put_res1 = engine1.put(Bytes::from("empty-value"), Bytes::new());
+        assert!(put_res1.is_ok());
+        let del_res1 = engine1.delete(util::rand_kv::get_test_key(10));
+        assert!(del_res1.is_ok());
+
+        let mut buf = Vec::new();
+        let export_res = engine1.export(&mut buf);
+        assert!(export_res.is_ok());
+        assert_eq!(EXPORT_MAGIC, &buf[..4]);
+        assert_eq!(EXPORT_VERSION, buf[4]);
+
+        // 导入到一个新的数据库中
+        let opts2 = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-import"),
+            ..Default::default()
+        };
+        let engine2 = Engine::open(opts2.clone()).expect("failed to open engine");
+
+        let import_res = engine2.import(&buf[..]);
+        assert_eq!(100, import_res.unwrap());
+        for i in 0..100 {
+            let get_res = engine2.get(util::rand_kv::get_test_key(i));
+            if i == 10 {
+                assert_eq!(Errors::KeyNotFound, get_res.err().unwrap());
+            } else {
+                assert_eq!(util::rand_kv::get_test_value(i), get_res.unwrap());
+            }
+        }
+        let get_res1 = engine2.get(Bytes::from("empty-value"));
+        assert!(get_res1.unwrap().is_empty());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts1.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(opts2.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_import_invalid_data() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-import-invalid"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 魔数不匹配
+        let import_res1 = engine.import("not exported data".as_bytes());
+        assert_eq!(Errors::InvalidImportData, import_res1.err().unwrap());
+
+        // 版本不支持
+        let mut buf2 = EXPORT_MAGIC.to_vec();
+        buf2.push(EXPORT_VERSION + 1);
+        let import_res2 = engine.import(&buf2[..]);
+        assert_eq!(Errors::UnsupportedExportVersion, import_res2.err().unwrap());
+
+        // 数据被截断
+        let mut buf3 = EXPORT_MAGIC.to_vec();
+        buf3.push(EXPORT_VERSION);
+        buf3.extend_from_slice(&4u32.to_be_bytes());
+        buf3.extend_from_slice("ke".as_bytes());
+        let import_res3 = engine.import(&buf3[..]);
+        assert_eq!(Errors::InvalidImportData, import_res3.err().unwrap());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
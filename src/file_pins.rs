@@ -0,0 +1,152 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use log::warn;
+use parking_lot::{Mutex, RwLock, RwLockReadGuard};
+
+use crate::{
+    data::data_file::DataFile,
+    errors::{Errors, Result},
+    fio::io_cache::IOManagerCache,
+};
+
+/// 延迟删除合并之后的数据文件，迭代器和快照创建时记录当前的版本，
+/// 合并删除的数据文件在之前创建的迭代器和快照全部释放之后才真正从磁盘上删除
+pub(crate) struct FilePins {
+    state: Mutex<PinState>,
+    retired: RwLock<HashMap<u64, DataFile>>, // 已经被合并但仍然可能被读取的数据文件
+    io_cache: Option<Arc<IOManagerCache>>,   // 删除文件之后需要关闭缓存的文件句柄
+}
+
+#[derive(Default)]
+struct PinState {
+    version: u64,                             // 每次有数据文件等待删除时加一
+    readers: BTreeMap<u64, usize>,            // 每个版本中还没有释放的读取者数量
+    pending: Vec<(u64, Vec<(u64, PathBuf)>)>, // 等待删除的数据文件，以及它们被合并时的版本
+}
+
+/// 读取者持有的版本，版本之后被合并的数据文件在释放之前不会被删除
+pub(crate) struct FilePin<'a> {
+    pins: &'a FilePins,
+    version: u64,
+}
+
+impl FilePins {
+    pub(crate) fn new(io_cache: Option<Arc<IOManagerCache>>) -> Self {
+        Self {
+            state: Mutex::new(PinState::default()),
+            retired: RwLock::new(HashMap::new()),
+            io_cache,
+        }
+    }
+
+    /// 记录一个读取者，之后被合并的数据文件在返回的 FilePin 释放之前仍然可以读取
+    /// 需要在读取索引之前调用，保证读取到的位置所在的文件都会被保留
+    pub(crate) fn pin(&self) -> FilePin<'_> {
+        let mut state = self.state.lock();
+        let version = state.version;
+        *state.readers.entry(version).or_default() += 1;
+        FilePin {
+            pins: self,
+            version,
+        }
+    }
+
+    /// 删除合并之后不再需要的数据文件，还有读取者时先保留，最后一个读取者释放时再删除
+    /// 需要在从 older_files 中移除文件之前持有 older_files 的写锁，读取时才能在这里找到文件
+    pub(crate) fn retire(&self, files: Vec<(DataFile, PathBuf)>) -> Result<()> {
+        let mut state = self.state.lock();
+        if state.readers.is_empty() {
+            let paths: Vec<PathBuf> = files.into_iter().map(|(_, path)| path).collect();
+            return self.remove_files(paths.iter());
+        }
+
+        let version = state.version;
+        state.version += 1;
+        let mut retired = self.retired.write();
+        let mut paths = Vec::with_capacity(files.len());
+        for (data_file, path) in files {
+            paths.push((data_file.get_file_id(), path));
+            retired.insert(data_file.get_file_id(), data_file);
+        }
+        state.pending.push((version, paths));
+        Ok(())
+    }
+
+    /// 清空数据库时调用，等待删除的数据文件已经和其他数据文件一起被删除，读取者释放时不需要再删除
+    pub(crate) fn clear(&self) {
+        let mut state = self.state.lock();
+        state.pending.clear();
+        self.retired.write().clear();
+    }
+
+    /// 被合并但还没有删除的数据文件，读取旧的数据文件中找不到时在这里查找
+    pub(crate) fn retired(&self) -> RwLockReadGuard<'_, HashMap<u64, DataFile>> {
+        self.retired.read()
+    }
+
+    // 删除磁盘上的数据文件，并关闭缓存的文件句柄
+    fn remove_files<'p>(&self, paths: impl Iterator<Item = &'p PathBuf>) -> Result<()> {
+        for path in paths {
+            if fs::remove_file(path).is_err() {
+                return Err(Errors::FailedToRemoveDataFile);
+            }
+            if let Some(io_cache) = &self.io_cache {
+                io_cache.remove(path);
+            }
+        }
+        Ok(())
+    }
+
+    // 读取者释放之后，删除没有更早的读取者需要的数据文件
+    fn unpin(&self, version: u64) {
+        let mut state = self.state.lock();
+        if let Some(count) = state.readers.get_mut(&version) {
+            *count -= 1;
+            if *count == 0 {
+                state.readers.remove(&version);
+            }
+        }
+
+        // 版本不大于 v 的读取者可能读取 v 时被合并的文件
+        let oldest = state.readers.keys().next().copied();
+        let (removable, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut state.pending)
+            .into_iter()
+            .partition(|(v, _)| oldest.is_none_or(|oldest| oldest > *v));
+        state.pending = pending;
+        if removable.is_empty() {
+            return;
+        }
+
+        let mut retired = self.retired.write();
+        for (_, files) in removable {
+            for (file_id, _) in files.iter() {
+                retired.remove(file_id);
+            }
+            if let Err(e) = self.remove_files(files.iter().map(|(_, path)| path)) {
+                warn!("failed to remove merged data file: {}", e);
+            }
+        }
+    }
+}
+
+impl Clone for FilePin<'_> {
+    fn clone(&self) -> Self {
+        let mut state = self.pins.state.lock();
+        *state.readers.entry(self.version).or_default() += 1;
+        FilePin {
+            pins: self.pins,
+            version: self.version,
+        }
+    }
+}
+
+impl Drop for FilePin<'_> {
+    fn drop(&mut self) {
+        self.pins.unpin(self.version);
+    }
+}
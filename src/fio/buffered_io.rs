@@ -0,0 +1,181 @@
+use std::{io, sync::Arc};
+
+use parking_lot::Mutex;
+
+use super::IOManager;
+use crate::errors::Result;
+
+/// 带写缓冲区的 IO，写入的数据先放在用户态的缓冲区中，缓冲区满了之后再写入 inner
+/// flush 只把缓冲区中的数据写入 inner，sync 在写入之后还会持久化 inner
+pub struct BufferedIO {
+    inner: Arc<dyn IOManager>,
+    buf: Mutex<Vec<u8>>, // 还没有写入 inner 的数据
+    capacity: usize,     // 缓冲区大小，超过大小的写入直接写入 inner
+}
+
+impl BufferedIO {
+    pub fn new(inner: Arc<dyn IOManager>, capacity: usize) -> Self {
+        Self {
+            inner,
+            buf: Mutex::new(Vec::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    // 将缓冲区中的数据全部写入 inner，写入失败时保留还没有写入的数据
+    fn flush_buf(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let mut written = 0;
+        let res = loop {
+            if written == buf.len() {
+                break Ok(());
+            }
+            match self.inner.write(&buf[written..]) {
+                Ok(0) => break Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                Ok(n) => written += n,
+                Err(e) => break Err(e),
+            }
+        };
+        buf.drain(..written);
+        res
+    }
+}
+
+impl IOManager for BufferedIO {
+    // 读取之前先写入缓冲区中的数据，保证可以读取到所有已经写入的数据
+    fn read(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.flush_buf(&mut self.buf.lock())?;
+        self.inner.read(buf, offset)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let mut write_buf = self.buf.lock();
+        if write_buf.len() + buf.len() > self.capacity {
+            self.flush_buf(&mut write_buf)?;
+        }
+        if buf.len() >= self.capacity {
+            return self.inner.write(buf);
+        }
+        write_buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        self.flush_buf(&mut self.buf.lock())?;
+        self.inner.write_at(buf, offset)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.flush_buf(&mut self.buf.lock())?;
+        self.inner.flush()
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.flush_buf(&mut self.buf.lock())?;
+        self.inner.sync()
+    }
+
+    fn size(&self) -> Result<u64> {
+        let write_buf = self.buf.lock();
+        Ok(self.inner.size()? + write_buf.len() as u64)
+    }
+
+    fn set_len(&self, size: u64) -> Result<()> {
+        self.flush_buf(&mut self.buf.lock())?;
+        self.inner.set_len(size)
+    }
+}
+
+impl Drop for BufferedIO {
+    // 关闭时将缓冲区中剩余的数据写入 inner
+    fn drop(&mut self) {
+        let _ = self.flush_buf(&mut self.buf.lock());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 记录调用过的方法的 IO，数据保存在内存中
+    #[derive(Default)]
+    struct MockIO {
+        data: Mutex<Vec<u8>>,
+        calls: Mutex<Vec<&'static str>>,
+    }
+
+    impl IOManager for MockIO {
+        fn read(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+            self.calls.lock().push("read");
+            let data = self.data.lock();
+            let start = (offset as usize).min(data.len());
+            let n = buf.len().min(data.len() - start);
+            buf[..n].copy_from_slice(&data[start..start + n]);
+            Ok(n)
+        }
+
+        fn write(&self, buf: &[u8]) -> Result<usize> {
+            self.calls.lock().push("write");
+            self.data.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&self) -> Result<()> {
+            self.calls.lock().push("flush");
+            Ok(())
+        }
+
+        fn sync(&self) -> Result<()> {
+            self.calls.lock().push("sync");
+            Ok(())
+        }
+
+        fn size(&self) -> Result<u64> {
+            Ok(self.data.lock().len() as u64)
+        }
+
+        fn set_len(&self, size: u64) -> Result<()> {
+            self.calls.lock().push("set_len");
+            self.data.lock().resize(size as usize, 0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_buffered_io_flush_and_sync() {
+        let mock = Arc::new(MockIO::default());
+        let bio = BufferedIO::new(mock.clone(), 16);
+
+        // 写入的数据先放在缓冲区中，大小包括缓冲区中的数据
+        let res1 = bio.write(b"key-a");
+        assert_eq!(5, res1.unwrap());
+        assert!(mock.calls.lock().is_empty());
+        assert_eq!(5, bio.size().unwrap());
+
+        // flush 只写入 inner，不持久化
+        let res2 = bio.flush();
+        assert!(res2.is_ok());
+        assert_eq!(vec!["write", "flush"], *mock.calls.lock());
+        assert_eq!(b"key-a".to_vec(), *mock.data.lock());
+
+        // sync 写入之后持久化
+        mock.calls.lock().clear();
+        let res3 = bio.write(b"key-b");
+        assert!(res3.is_ok());
+        let res4 = bio.sync();
+        assert!(res4.is_ok());
+        assert_eq!(vec!["write", "sync"], *mock.calls.lock());
+
+        // 缓冲区满了之后自动写入，读取时可以读到缓冲区中的数据
+        mock.calls.lock().clear();
+        let res5 = bio.write(&[b'v'; 12]);
+        assert!(res5.is_ok());
+        let res6 = bio.write(&[b'w'; 8]);
+        assert!(res6.is_ok());
+        assert_eq!(vec!["write"], *mock.calls.lock());
+        let mut buf = [0u8; 8];
+        let res7 = bio.read(&mut buf, 22);
+        assert_eq!(8, res7.unwrap());
+        assert_eq!([b'w'; 8], buf);
+        assert_eq!(30, bio.size().unwrap());
+    }
+}
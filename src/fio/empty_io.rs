@@ -0,0 +1,46 @@
+use super::IOManager;
+use crate::errors::{Errors, Result};
+
+/// 没有任何数据的 IO，不对应磁盘上的文件，只读模式下数据库中没有数据文件时作为活跃文件使用
+pub struct EmptyIO;
+
+impl IOManager for EmptyIO {
+    fn read(&self, _buf: &mut [u8], _offset: u64) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn write(&self, _buf: &[u8]) -> Result<usize> {
+        Err(Errors::ReadOnlyDatabase)
+    }
+
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(0)
+    }
+
+    fn set_len(&self, _size: u64) -> Result<()> {
+        Err(Errors::ReadOnlyDatabase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_io() {
+        let empty_io = EmptyIO;
+        assert_eq!(0, empty_io.size().unwrap());
+        let mut buf = [0u8; 8];
+        assert_eq!(0, empty_io.read(&mut buf, 0).unwrap());
+        assert_eq!(
+            Errors::ReadOnlyDatabase,
+            empty_io.write(b"a").err().unwrap()
+        );
+        assert_eq!(Errors::ReadOnlyDatabase, empty_io.set_len(8).err().unwrap());
+        assert!(empty_io.sync().is_ok());
+    }
+}
@@ -7,32 +7,40 @@ use std::{
 };
 
 use super::IOManager;
-use crate::errors::{Errors, Result};
+use crate::errors::Result;
 use log::error;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
 /// FileIO 标准系统文件 IO
 pub struct FileIO {
-    fd: Arc<RwLock<File>>, // 系统文件描述符
+    fd: Arc<RwLock<File>>,              // 系统文件描述符
+    file_name: PathBuf,                 // 文件路径，指定位置写入时使用
+    positional_fd: Mutex<Option<File>>, // 非追加模式的文件描述符，第一次指定位置写入时打开
 }
 
 impl FileIO {
     pub fn new(file_name: PathBuf) -> Result<Self> {
-        match OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .append(true)
-            .open(file_name)
-        {
-            Ok(file) => {
-                return Ok(FileIO {
-                    fd: Arc::new(RwLock::new(file)),
-                })
-            }
+        Self::open(
+            OpenOptions::new().create(true).read(true).append(true),
+            file_name,
+        )
+    }
+
+    /// 以只读方式打开已有的文件，文件不存在时返回错误而不是创建文件，之后的写入都会失败
+    pub fn open_read_only(file_name: PathBuf) -> Result<Self> {
+        Self::open(OpenOptions::new().read(true), file_name)
+    }
+
+    fn open(options: &OpenOptions, file_name: PathBuf) -> Result<Self> {
+        match options.open(&file_name) {
+            Ok(file) => Ok(FileIO {
+                fd: Arc::new(RwLock::new(file)),
+                file_name,
+                positional_fd: Mutex::new(None),
+            }),
             Err(e) => {
                 error!("failed to open data file: {}", e);
-                return Err(Errors::FailedToOpenDataFile);
+                Err(e.into())
             }
         }
     }
@@ -42,21 +50,42 @@ impl IOManager for FileIO {
     fn read(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
         let read_guard = self.fd.read();
         match read_guard.read_at(buf, offset) {
-            Ok(n) => return Ok(n),
+            Ok(n) => Ok(n),
             Err(e) => {
                 error!("read from data file err: {}", e);
-                return Err(Errors::FailedToReadFromDataFile);
+                Err(e.into())
             }
-        };
+        }
     }
 
     fn write(&self, buf: &[u8]) -> Result<usize> {
         let mut write_guard = self.fd.write();
         match write_guard.write(buf) {
-            Ok(n) => return Ok(n),
+            Ok(n) => Ok(n),
             Err(e) => {
                 error!("write to data file err: {}", e);
-                return Err(Errors::FailedWriteToDataFile);
+                Err(e.into())
+            }
+        }
+    }
+
+    // 追加模式打开的文件会忽略写入位置，所以需要以非追加模式再打开一次文件进行写入
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        let mut positional_fd = self.positional_fd.lock();
+        if positional_fd.is_none() {
+            match OpenOptions::new().write(true).open(&self.file_name) {
+                Ok(file) => *positional_fd = Some(file),
+                Err(e) => {
+                    error!("failed to open data file: {}", e);
+                    return Err(e.into());
+                }
+            }
+        }
+        match positional_fd.as_ref().unwrap().write_at(buf, offset) {
+            Ok(n) => Ok(n),
+            Err(e) => {
+                error!("write to data file err: {}", e);
+                Err(e.into())
             }
         }
     }
@@ -64,8 +93,28 @@ impl IOManager for FileIO {
     fn sync(&self) -> Result<()> {
         let read_guard = self.fd.read();
         if let Err(e) = read_guard.sync_all() {
-            error!("failed t o sync data file: {}", e);
-            return Err(Errors::FailedSyncDataFile);
+            error!("failed to sync data file: {}", e);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Result<u64> {
+        let read_guard = self.fd.read();
+        match read_guard.metadata() {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) => {
+                error!("failed to get data file metadata: {}", e);
+                Err(e.into())
+            }
+        }
+    }
+
+    fn set_len(&self, size: u64) -> Result<()> {
+        let write_guard = self.fd.write();
+        if let Err(e) = write_guard.set_len(size) {
+            error!("failed to set data file length: {}", e);
+            return Err(e.into());
         }
         Ok(())
     }
@@ -75,6 +124,8 @@ impl IOManager for FileIO {
 mod tests {
     use std::{fs, path::PathBuf};
 
+    use crate::errors::Errors;
+
     use super::*;
 
     #[test]
@@ -147,4 +198,140 @@ mod tests {
         let res3 = fs::remove_file(path.clone());
         assert!(res3.is_ok());
     }
+
+    #[test]
+    fn test_file_io_write_at() {
+        let path = PathBuf::from("/tmp/e.data");
+        let fio_res = FileIO::new(path.clone());
+        assert!(fio_res.is_ok());
+        let fio = fio_res.ok().unwrap();
+
+        let res1 = fio.write("key-a".as_bytes());
+        assert!(res1.is_ok());
+
+        // 覆盖已经写入的数据
+        let res2 = fio.write_at("KEY".as_bytes(), 0);
+        assert!(res2.is_ok());
+        assert_eq!(3, res2.ok().unwrap());
+
+        let mut buf = [0u8; 5];
+        let read_res1 = fio.read(&mut buf, 0);
+        assert!(read_res1.is_ok());
+        assert_eq!("KEY-a".as_bytes(), buf);
+
+        // 之后追加写入的数据仍然写到文件末尾
+        let res3 = fio.write("key-b".as_bytes());
+        assert!(res3.is_ok());
+        assert_eq!(10, fio.size().unwrap());
+        let read_res2 = fio.read(&mut buf, 5);
+        assert!(read_res2.is_ok());
+        assert_eq!("key-b".as_bytes(), buf);
+
+        let res4 = fs::remove_file(path.clone());
+        assert!(res4.is_ok());
+    }
+
+    #[test]
+    fn test_file_io_size() {
+        let path = PathBuf::from("/tmp/d.data");
+        let fio_res = FileIO::new(path.clone());
+        assert!(fio_res.is_ok());
+        let fio = fio_res.ok().unwrap();
+
+        assert_eq!(0, fio.size().unwrap());
+
+        let res1 = fio.write("key-a".as_bytes());
+        assert!(res1.is_ok());
+        assert_eq!(5, fio.size().unwrap());
+
+        let res2 = fs::remove_file(path.clone());
+        assert!(res2.is_ok());
+    }
+
+    #[test]
+    fn test_file_io_set_len() {
+        let path = PathBuf::from("/tmp/f.data");
+        let fio_res = FileIO::new(path.clone());
+        assert!(fio_res.is_ok());
+        let fio = fio_res.ok().unwrap();
+
+        let res1 = fio.set_len(100);
+        assert!(res1.is_ok());
+        assert_eq!(100, fio.size().unwrap());
+
+        let res2 = fio.write_at("key-a".as_bytes(), 0);
+        assert!(res2.is_ok());
+        let res3 = fio.set_len(5);
+        assert!(res3.is_ok());
+        assert_eq!(5, fio.size().unwrap());
+
+        let res4 = fs::remove_file(path.clone());
+        assert!(res4.is_ok());
+    }
+
+    #[test]
+    fn test_file_io_error() {
+        let path = PathBuf::from("/tmp/g.data");
+        let fio_res = FileIO::new(path.clone());
+        assert!(fio_res.is_ok());
+
+        // 以只写的方式打开文件，读取时操作系统返回错误
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        let fio = FileIO {
+            fd: Arc::new(RwLock::new(file)),
+            file_name: path.clone(),
+            positional_fd: Mutex::new(None),
+        };
+        let mut buf = [0u8; 5];
+        let read_res = fio.read(&mut buf, 0);
+        match read_res.err().unwrap() {
+            Errors::Io(e) => assert_eq!(Some(9), e.0.raw_os_error()), // EBADF
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // 打开不存在的目录中的文件
+        let open_res = FileIO::new(PathBuf::from("/tmp/bitcask-rs-not-exist/g.data"));
+        let err = open_res.err().unwrap();
+        assert_eq!(
+            Errors::from(std::io::Error::from(std::io::ErrorKind::NotFound)),
+            err
+        );
+        assert!(err.to_string().starts_with("io: "));
+
+        let res = fs::remove_file(path.clone());
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_file_io_open_read_only() {
+        let path = PathBuf::from("/tmp/h.data");
+
+        // 文件不存在时返回错误，不会创建文件
+        let open_res = FileIO::open_read_only(path.clone());
+        assert_eq!(
+            Errors::from(std::io::Error::from(std::io::ErrorKind::NotFound)),
+            open_res.err().unwrap()
+        );
+        assert!(!path.exists());
+
+        let fio = FileIO::new(path.clone()).unwrap();
+        let write_res = fio.write("key-a".as_bytes());
+        assert!(write_res.is_ok());
+
+        // 以只读方式打开可以读取数据，写入时操作系统返回错误
+        let ro_fio = FileIO::open_read_only(path.clone()).unwrap();
+        let mut buf = [0u8; 5];
+        let read_res = ro_fio.read(&mut buf, 0);
+        assert_eq!(5, read_res.unwrap());
+        assert_eq!("key-a".as_bytes(), buf);
+        let write_res = ro_fio.write("key-b".as_bytes());
+        match write_res.err().unwrap() {
+            Errors::Io(e) => assert_eq!(Some(9), e.0.raw_os_error()), // EBADF
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert_eq!(5, ro_fio.size().unwrap());
+
+        let res = fs::remove_file(path.clone());
+        assert!(res.is_ok());
+    }
 }
@@ -0,0 +1,93 @@
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use lru::LruCache;
+use parking_lot::Mutex;
+
+use super::{new_io_manager, new_read_only_io_manager, IOManager};
+use crate::errors::Result;
+
+/// 打开的文件句柄缓存，最多同时打开 capacity 个文件，超出时关闭最久没有访问的文件
+/// 被淘汰的文件如果正在被读取，会在读取结束之后再关闭
+pub struct IOManagerCache {
+    cache: Mutex<LruCache<PathBuf, Arc<dyn IOManager>>>,
+    read_only: bool, // 以只读方式打开文件，只读模式打开数据库时使用
+}
+
+impl IOManagerCache {
+    pub fn new(capacity: NonZeroUsize, read_only: bool) -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(capacity)),
+            read_only,
+        }
+    }
+
+    /// 获取文件对应的 IOManager，文件没有打开时重新打开
+    pub fn get(&self, file_name: &Path) -> Result<Arc<dyn IOManager>> {
+        let mut cache = self.cache.lock();
+        if let Some(io_manager) = cache.get(file_name) {
+            return Ok(io_manager.clone());
+        }
+
+        let io_manager: Arc<dyn IOManager> = match self.read_only {
+            true => Arc::new(new_read_only_io_manager(file_name.to_path_buf())?),
+            false => Arc::new(new_io_manager(file_name.to_path_buf())?),
+        };
+        cache.put(file_name.to_path_buf(), io_manager.clone());
+        Ok(io_manager)
+    }
+
+    /// 关闭文件，文件被删除之后需要调用，避免之后创建的同名文件读取到旧的内容
+    pub fn remove(&self, file_name: &Path) {
+        self.cache.lock().pop(file_name);
+    }
+
+    /// 当前打开的文件数量
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.cache.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_io_manager_cache() {
+        let cache = IOManagerCache::new(NonZeroUsize::new(2).unwrap(), false);
+        let paths: Vec<PathBuf> = (0..4)
+            .map(|i| PathBuf::from(format!("/tmp/io-cache-{}.data", i)))
+            .collect();
+
+        for (i, path) in paths.iter().enumerate() {
+            let io_manager = cache.get(path).unwrap();
+            let res = io_manager.write(format!("value-{}", i).as_bytes());
+            assert!(res.is_ok());
+            assert!(cache.len() <= 2);
+        }
+        assert_eq!(2, cache.len());
+
+        // 被淘汰的文件重新打开之后仍然可以读取到数据
+        for (i, path) in paths.iter().enumerate() {
+            let mut buf = [0u8; 7];
+            let res = cache.get(path).unwrap().read(&mut buf, 0);
+            assert!(res.is_ok());
+            assert_eq!(format!("value-{}", i).as_bytes(), buf);
+            assert!(cache.len() <= 2);
+        }
+
+        cache.remove(&paths[3]);
+        assert_eq!(1, cache.len());
+
+        for path in paths {
+            let res = fs::remove_file(path);
+            assert!(res.is_ok());
+        }
+    }
+}
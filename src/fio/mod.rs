@@ -1,11 +1,17 @@
+pub mod buffered_io;
+pub mod empty_io;
 pub mod file_io;
-use std::path::PathBuf;
+pub mod io_cache;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
 
-use crate::errors::Result;
+use crate::errors::{Errors, Result};
 
 use self::file_io::FileIO;
 
-/// 抽象IO管理接口，可以接入不同的 IO 类型，目前支持标准文件
+/// 抽象IO管理接口，可以接入不同的 IO 类型，目前支持标准文件和带写缓冲区的 IO
 pub trait IOManager: Sync + Send {
     /// 从文件的给定位置读取对应的数据
     fn read(&self, buf: &mut [u8], offset: u64) -> Result<usize>;
@@ -13,11 +19,80 @@ pub trait IOManager: Sync + Send {
     /// 写入字节数组到文件中
     fn write(&self, buf: &[u8]) -> Result<usize>;
 
-    /// 持久化数据
+    /// 将字节数组写入到文件的给定位置，不改变追加写入的位置
+    /// 默认不支持，只能追加写入的 IO 类型返回错误
+    fn write_at(&self, _buf: &[u8], _offset: u64) -> Result<usize> {
+        Err(Errors::WriteAtNotSupported)
+    }
+
+    /// 将用户态缓冲区中的数据写入操作系统，不保证持久化
+    /// 默认不做任何事情，没有缓冲区的 IO 类型写入时已经交给了操作系统
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// 持久化数据，需要先写入缓冲区中的数据
     fn sync(&self) -> Result<()>;
+
+    /// 获取文件大小
+    fn size(&self) -> Result<u64>;
+
+    /// 设置文件大小，超出的部分被截断，不足的部分用 0 填充
+    fn set_len(&self, size: u64) -> Result<()>;
+}
+
+/// 获取路径所在的文件系统中可以使用的空间大小，不支持的平台返回 None
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Result<Option<u64>> {
+    use std::{ffi::CString, io, os::unix::ffi::OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: c_path 是以 0 结尾的合法路径，stat 由 statvfs 填充
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    // 不同平台上这两个字段的类型不同，统一转换为 u64
+    #[allow(clippy::useless_conversion)]
+    let available = u64::from(stat.f_bavail) * u64::from(stat.f_frsize);
+    Ok(Some(available))
+}
+
+/// 获取路径所在的文件系统中可以使用的空间大小，不支持的平台返回 None
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Result<Option<u64>> {
+    Ok(None)
+}
+
+/// 尝试获取文件的排他锁，锁已经被其他的文件描述符持有时返回 false，文件关闭时自动释放
+#[cfg(unix)]
+pub fn try_lock_exclusive(file: &File) -> Result<bool> {
+    use std::{io, os::unix::io::AsRawFd};
+
+    // SAFETY: fd 在 file 的生命周期内一直有效
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    match e.kind() {
+        io::ErrorKind::WouldBlock => Ok(false),
+        _ => Err(e.into()),
+    }
+}
+
+/// 尝试获取文件的排他锁，不支持的平台直接返回成功
+#[cfg(not(unix))]
+pub fn try_lock_exclusive(_file: &File) -> Result<bool> {
+    Ok(true)
 }
 
 /// 根据文件名称初始化 IOManager
 pub fn new_io_manager(file_name: PathBuf) -> Result<impl IOManager> {
     FileIO::new(file_name)
 }
+
+/// 根据文件名称以只读方式初始化 IOManager，文件需要已经存在
+pub fn new_read_only_io_manager(file_name: PathBuf) -> Result<impl IOManager> {
+    FileIO::open_read_only(file_name)
+}
@@ -0,0 +1,117 @@
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::error;
+use parking_lot::{Condvar, Mutex, RwLock};
+
+use crate::{
+    data::data_file::DataFile,
+    errors::{Errors, Result},
+};
+
+/// 批量持久化，写入数据之后不直接持久化，而是等待后台线程持久化
+/// 后台线程一次持久化之前所有已经写入的数据，多个并发的写入只需要一次持久化
+pub(crate) struct GroupCommit {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+    #[cfg(test)]
+    sync_count: AtomicUsize, // 后台线程持久化的次数，只在测试中使用
+}
+
+#[derive(Default)]
+struct State {
+    written: u64,   // 已经写入的数据序号
+    synced: u64,    // 已经持久化的数据序号
+    failed: bool,   // 持久化是否失败过，失败之后不能保证之前写入的数据已经持久化
+    shutdown: bool, // 是否关闭后台线程
+}
+
+impl GroupCommit {
+    /// 启动后台持久化线程，每次持久化所有写入分片当前的活跃文件
+    /// 切换活跃文件时旧的文件已经持久化，所以只需要持久化当前的活跃文件
+    pub(crate) fn start(active_files: Vec<Arc<RwLock<DataFile>>>) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            cond: Condvar::new(),
+            #[cfg(test)]
+            sync_count: AtomicUsize::new(0),
+        });
+
+        let thread_shared = shared.clone();
+        let handle = thread::spawn(move || {
+            let shared = thread_shared;
+            let mut state = shared.state.lock();
+            loop {
+                while state.synced == state.written && !state.shutdown {
+                    shared.cond.wait(&mut state);
+                }
+                if state.synced == state.written && state.shutdown {
+                    return;
+                }
+
+                // 持久化的过程中释放锁，新的写入可以继续加入下一批
+                let target = state.written;
+                let res = parking_lot::MutexGuard::unlocked(&mut state, || {
+                    #[cfg(test)]
+                    shared.sync_count.fetch_add(1, Ordering::SeqCst);
+                    active_files
+                        .iter()
+                        .try_for_each(|active_file| active_file.read().sync())
+                });
+                if let Err(e) = res {
+                    error!("group commit sync err: {}", e);
+                    state.failed = true;
+                }
+                state.synced = target;
+                shared.cond.notify_all();
+            }
+        });
+
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// 等待之前写入的数据被持久化，需要在写入数据并释放写锁之后调用
+    pub(crate) fn commit(&self) -> Result<()> {
+        let mut state = self.shared.state.lock();
+        state.written += 1;
+        let ticket = state.written;
+        self.shared.cond.notify_all();
+
+        while state.synced < ticket {
+            self.shared.cond.wait(&mut state);
+        }
+        if state.failed {
+            return Err(Errors::FailedSyncDataFile);
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn sync_count(&self) -> usize {
+        self.shared.sync_count.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for GroupCommit {
+    // 持久化剩余的数据之后关闭后台线程
+    fn drop(&mut self) {
+        self.shared.state.lock().shutdown = true;
+        self.shared.cond.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -1,109 +1,385 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, VecDeque},
+    mem,
+    ops::Bound,
+    sync::Arc,
+};
 
 use parking_lot::RwLock;
 
-use crate::{data::log_record::LogRecordPos, errors::Result, options::IteratorOptions};
+use crate::{
+    data::log_record::LogRecordPos,
+    errors::Result,
+    options::{Comparator, IteratorOptions},
+};
 use bytes::Bytes;
 
 use super::{IndexIterator, Indexer};
 
+/// key 的排序方式，设置了比较函数时按照比较函数排序，为 None 时按照字节序排序
+///
+/// 索引中的每个 key 都持有比较函数的引用，外面再包一层 Arc，key 中只需要保存一个指针
+#[derive(Clone, Default)]
+pub struct KeyOrder(Option<Arc<Comparator>>);
+
+impl KeyOrder {
+    pub(crate) fn new(comparator: Option<Comparator>) -> Self {
+        Self(comparator.map(Arc::new))
+    }
+
+    /// 是否设置了比较函数
+    pub(crate) fn is_custom(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// 创建按照这个排序方式比较的 key
+    pub(crate) fn key(&self, key: Vec<u8>) -> IndexKey {
+        IndexKey {
+            key,
+            order: self.clone(),
+        }
+    }
+}
+
+/// 索引中存储的 key，设置了比较函数时按照比较函数排序，否则按照字节序排序
+#[derive(Clone)]
+pub struct IndexKey {
+    pub(crate) key: Vec<u8>,
+    order: KeyOrder,
+}
+
+impl IndexKey {
+    /// 按照字节序比较的 key，只能和同样按照字节序比较的 key 放在一起
+    pub(crate) fn bytewise(key: Vec<u8>) -> Self {
+        Self {
+            key,
+            order: KeyOrder::default(),
+        }
+    }
+}
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match &self.order.0 {
+            Some(comparator) => comparator(&self.key, &other.key),
+            None => self.key.cmp(&other.key),
+        }
+    }
+}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for IndexKey {}
+
+impl std::fmt::Debug for IndexKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.key.fmt(f)
+    }
+}
+
+// 估算 BTreeMap 占用的内存大小，每个条目包括 key 的内容、IndexKey 和 LogRecordPos 本身
+pub(crate) fn tree_memory_usage(tree: &BTreeMap<IndexKey, LogRecordPos>) -> usize {
+    let entry_size = mem::size_of::<IndexKey>() + mem::size_of::<LogRecordPos>();
+    tree.keys().map(|k| k.key.capacity() + entry_size).sum()
+}
+
 // Btree 索引，主要封装了标准库中的 BtreeMap 结构
 pub struct BTree {
-    tree: Arc<RwLock<BTreeMap<Vec<u8>, LogRecordPos>>>,
+    tree: Arc<RwLock<BTreeMap<IndexKey, LogRecordPos>>>,
+    order: KeyOrder, // key 的排序方式
 }
 
 impl BTree {
+    // 按照字节序排序的索引
+    #[cfg(test)]
     pub fn new() -> Self {
+        Self::with_comparator(None)
+    }
+
+    pub fn with_comparator(comparator: Option<Comparator>) -> Self {
         Self {
             tree: Arc::new(RwLock::new(BTreeMap::new())),
+            order: KeyOrder::new(comparator),
         }
     }
+
+    fn index_key(&self, key: Vec<u8>) -> IndexKey {
+        self.order.key(key)
+    }
 }
 
 impl Indexer for BTree {
     fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool {
+        let key = self.index_key(key);
         let mut write_guard = self.tree.write();
         write_guard.insert(key, pos);
         true
     }
 
+    fn bulk_put(&self, entries: Vec<(Vec<u8>, LogRecordPos)>) -> bool {
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(key, pos)| (self.index_key(key), pos))
+            .collect();
+        let mut write_guard = self.tree.write();
+        write_guard.extend(entries);
+        true
+    }
+
     fn get(&self, key: Vec<u8>) -> Option<LogRecordPos> {
+        let key = self.index_key(key);
         let read_guard = self.tree.read();
         read_guard.get(&key).copied()
     }
 
     fn delete(&self, key: Vec<u8>) -> bool {
+        let key = self.index_key(key);
         let mut write_guard = self.tree.write();
         let remove_res = write_guard.remove(&key);
         remove_res.is_some()
     }
 
+    fn clear(&self) {
+        let mut write_guard = self.tree.write();
+        write_guard.clear();
+    }
+
     fn list_keys(&self) -> Result<Vec<Bytes>> {
         let read_guard = self.tree.read();
         let mut keys = Vec::with_capacity(read_guard.len());
         for (k, _) in read_guard.iter() {
-            keys.push(Bytes::copy_from_slice(&k));
+            keys.push(Bytes::copy_from_slice(&k.key));
         }
         Ok(keys)
     }
 
-    /// 索引信息全存到了一个数组里，这可能就导致内存的急剧膨胀，主要是因为BTree自带的iter()无法
-    /// 满足我们的需要，除非找到一个合适的数据结构有合适的iter()能狗满足我们的需求
+    /// 迭代器只保存当前遍历的位置，每次取数据时短暂地获取读锁，通过 range 批量读取之后的若干条数据，
+    /// 不需要将索引中的数据拷贝到数组中，遍历过程中不会阻塞写入
     fn iterator(&self, options: IteratorOptions) -> Box<dyn IndexIterator> {
-        let read_guard = self.tree.read();
-        let mut items = Vec::with_capacity(read_guard.len());
-        // 将 BTree 中的数据存储到数组中
-        for (key, value) in read_guard.iter() {
-            items.push((key.clone(), value.clone()));
+        Box::new(BTreeIterator::new(
+            self.tree.clone(),
+            options,
+            self.order.clone(),
+        ))
+    }
+
+    /// 拷贝一份当前的索引，代价和索引的大小成正比
+    fn snapshot(&self) -> (Arc<BTreeMap<IndexKey, LogRecordPos>>, KeyOrder) {
+        (Arc::new(self.tree.read().clone()), self.order.clone())
+    }
+
+    fn memory_usage(&self) -> usize {
+        tree_memory_usage(&self.tree.read())
+    }
+}
+
+// 迭代器每次从索引中读取的最大条数
+const ITERATOR_BATCH_SIZE: usize = 64;
+
+/// 迭代器遍历的 BTreeMap，可以是加锁的索引或者不会再修改的快照
+pub trait TreeSource: Sync + Send {
+    /// 在读取 BTreeMap 期间执行函数，加锁的索引只在函数执行期间持有读锁
+    fn with_tree<R>(&self, f: impl FnOnce(&BTreeMap<IndexKey, LogRecordPos>) -> R) -> R;
+}
+
+impl TreeSource for Arc<RwLock<BTreeMap<IndexKey, LogRecordPos>>> {
+    fn with_tree<R>(&self, f: impl FnOnce(&BTreeMap<IndexKey, LogRecordPos>) -> R) -> R {
+        f(&self.read())
+    }
+}
+
+impl TreeSource for Arc<BTreeMap<IndexKey, LogRecordPos>> {
+    fn with_tree<R>(&self, f: impl FnOnce(&BTreeMap<IndexKey, LogRecordPos>) -> R) -> R {
+        f(self)
+    }
+}
+
+/// BTree 索引迭代器，T 为遍历的 BTreeMap，可以是加锁的索引或者快照
+pub struct BTreeIterator<T = Arc<RwLock<BTreeMap<IndexKey, LogRecordPos>>>> {
+    tree: T,                                   // 加锁的索引或快照
+    bound: Bound<IndexKey>, // 下一次读取的边界，正向遍历时是下界，反向遍历时是上界
+    buffer: VecDeque<(Vec<u8>, LogRecordPos)>, // 已经读取但还没有返回的数据
+    current: Option<(Vec<u8>, LogRecordPos)>, // 最近一次 next 返回的数据
+    finished: bool,         // 后面已经没有满足条件的数据
+    options: IteratorOptions, // 配置项
+    order: KeyOrder,        // key 的排序方式
+}
+
+impl<T: TreeSource> BTreeIterator<T> {
+    pub(crate) fn new(tree: T, options: IteratorOptions, order: KeyOrder) -> Self {
+        let mut iter = Self {
+            tree,
+            bound: Bound::Unbounded,
+            buffer: VecDeque::new(),
+            current: None,
+            finished: false,
+            options,
+            order,
+        };
+        iter.rewind();
+        iter
+    }
+
+    // 重新设置遍历的边界，丢弃已经读取的数据
+    fn reset(&mut self, bound: Bound<IndexKey>) {
+        self.bound = bound;
+        self.buffer.clear();
+        self.finished = false;
+    }
+
+    // 没有 seek 时遍历的起点
+    // 使用字节序时满足前缀的 key 是连续的，正向遍历可以直接从前缀开始，
+    // 反向遍历从第一个大于所有满足前缀的 key 的位置开始
+    fn start_bound(&self) -> Bound<IndexKey> {
+        let prefix = &self.options.prefix;
+        if prefix.is_empty() || self.order.is_custom() {
+            return Bound::Unbounded;
         }
-        if options.reverse {
-            items.reverse();
+        if !self.options.reverse {
+            return Bound::Included(IndexKey::bytewise(prefix.clone()));
         }
-        Box::new(BTreeIterator {
-            items,
-            curr_index: 0,
-            options,
+
+        // 去掉末尾的 0xff 之后将最后一个字节加一，前缀全部是 0xff 时没有上界
+        let mut upper = prefix.clone();
+        while let Some(last) = upper.pop() {
+            if last < u8::MAX {
+                upper.push(last + 1);
+                return Bound::Excluded(IndexKey::bytewise(upper));
+            }
+        }
+        Bound::Unbounded
+    }
+
+    // 满足前缀的第一个或最后一个 key
+    fn prefix_edge(&self, last: bool) -> Option<IndexKey> {
+        let prefix = &self.options.prefix;
+        let custom_order = self.order.is_custom();
+        self.tree.with_tree(|tree| {
+            let matches = |k: &&IndexKey| k.key.starts_with(prefix);
+            // 没有前缀时直接取最小或最大的 key，自定义比较函数时满足前缀的 key 不一定是连续的，需要遍历所有的 key
+            if prefix.is_empty() || custom_order {
+                let mut keys = tree.keys();
+                return match last {
+                    true => keys.rfind(matches),
+                    false => keys.find(matches),
+                }
+                .cloned();
+            }
+
+            let start = IndexKey::bytewise(prefix.clone());
+            let mut keys = tree
+                .range((Bound::Included(start), Bound::Unbounded))
+                .map(|(k, _)| k)
+                .take_while(matches);
+            match last {
+                true => keys.last(),
+                false => keys.next(),
+            }
+            .cloned()
         })
     }
-}
 
-/// BTree 索引迭代器
-pub struct BTreeIterator {
-    items: Vec<(Vec<u8>, LogRecordPos)>, // 存储 key + 索引
-    curr_index: usize,                   // 当前遍历位置下标
-    options: IteratorOptions,            //配置项
+    // 在读锁中从当前的边界开始读取一批数据，只保留满足前缀的 key，每次最多访问 ITERATOR_BATCH_SIZE 条
+    fn fill_buffer(&mut self) {
+        let Self {
+            tree,
+            bound,
+            buffer,
+            finished,
+            options,
+            order,
+            ..
+        } = self;
+        let prefix = &options.prefix;
+        let reverse = options.reverse;
+
+        let last = tree.with_tree(|tree| {
+            let mut range = match reverse {
+                true => tree.range((Bound::Unbounded, bound.as_ref())),
+                false => tree.range((bound.as_ref(), Bound::Unbounded)),
+            };
+            let mut last = None;
+            for _ in 0..ITERATOR_BATCH_SIZE {
+                let item = match reverse {
+                    true => range.next_back(),
+                    false => range.next(),
+                };
+                let (k, pos) = match item {
+                    Some(item) => item,
+                    None => {
+                        *finished = true;
+                        break;
+                    }
+                };
+                last = Some(k);
+
+                if prefix.is_empty() || k.key.starts_with(prefix) {
+                    buffer.push_back((k.key.clone(), *pos));
+                    continue;
+                }
+
+                // 已经越过了前缀所在的范围，后面不会再有满足条件的 key
+                // 自定义比较函数时满足前缀的 key 不一定是连续的，需要继续遍历
+                if !order.is_custom()
+                    && ((!reverse && k.key.as_slice() > prefix.as_slice())
+                        || (reverse && k.key.as_slice() < prefix.as_slice()))
+                {
+                    *finished = true;
+                    break;
+                }
+            }
+            last.cloned()
+        });
+
+        if let Some(k) = last {
+            *bound = Bound::Excluded(k);
+        }
+    }
 }
 
-impl IndexIterator for BTreeIterator {
+impl<T: TreeSource> IndexIterator for BTreeIterator<T> {
     fn rewind(&mut self) {
-        self.curr_index = 0;
+        let bound = self.start_bound();
+        self.reset(bound);
     }
 
     fn seek(&mut self, key: Vec<u8>) {
-        self.curr_index = match self.items.binary_search_by(|(x, _)| {
-            if self.options.reverse {
-                x.cmp(&key).reverse()
-            } else {
-                x.cmp(&key)
-            }
-        }) {
-            Ok(equal_val) => equal_val,
-            Err(insert_val) => insert_val,
-        }
+        self.reset(Bound::Included(self.order.key(key)));
+    }
+
+    fn seek_to_first(&mut self) {
+        let bound = match self.prefix_edge(false) {
+            Some(k) => Bound::Included(k),
+            None => Bound::Unbounded,
+        };
+        self.reset(bound);
+    }
+
+    fn seek_to_last(&mut self) {
+        let bound = match self.prefix_edge(true) {
+            Some(k) => Bound::Included(k),
+            None => Bound::Unbounded,
+        };
+        self.reset(bound);
     }
 
     fn next(&mut self) -> Option<(&Vec<u8>, &LogRecordPos)> {
-        if self.curr_index >= self.items.len() {
-            return None;
+        while self.buffer.is_empty() && !self.finished {
+            self.fill_buffer();
         }
-        while let Some(item) = self.items.get(self.curr_index) {
-            self.curr_index += 1;
-            let prefix = &self.options.prefix;
-            if prefix.is_empty() || item.0.starts_with(&prefix) {
-                return Some((&item.0, &item.1));
-            }
-        }
-        None
+        self.current = self.buffer.pop_front();
+        self.current.as_ref().map(|(key, pos)| (key, pos))
     }
 }
 
@@ -120,18 +396,22 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
-        assert_eq!(res1, true);
+        assert!(res1);
 
         let res2 = bt.put(
             "aa".as_bytes().to_vec(),
             LogRecordPos {
                 file_id: 11,
                 offset: 22,
+                inline: None,
+                version: 0,
             },
         );
-        assert_eq!(res2, true);
+        assert!(res2);
     }
 
     #[test]
@@ -142,17 +422,21 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
-        assert_eq!(res1, true);
+        assert!(res1);
         let res2 = bt.put(
             "aa".as_bytes().to_vec(),
             LogRecordPos {
                 file_id: 11,
                 offset: 22,
+                inline: None,
+                version: 0,
             },
         );
-        assert_eq!(res2, true);
+        assert!(res2);
 
         let pos1 = bt.get("".as_bytes().to_vec());
         // println!("pos = {:?}", pos1);
@@ -174,17 +458,21 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
-        assert_eq!(res1, true);
+        assert!(res1);
         let res2 = bt.put(
             "aa".as_bytes().to_vec(),
             LogRecordPos {
                 file_id: 11,
                 offset: 22,
+                inline: None,
+                version: 0,
             },
         );
-        assert_eq!(res2, true);
+        assert!(res2);
 
         let del1 = bt.delete("".as_bytes().to_vec());
         assert!(del1);
@@ -212,6 +500,8 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
         let mut iter2 = bt.iterator(IteratorOptions::default());
@@ -230,6 +520,8 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
         bt.put(
@@ -237,6 +529,8 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
         bt.put(
@@ -244,19 +538,21 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
 
         let mut iter4 = bt.iterator(IteratorOptions::default());
         iter4.seek("b".as_bytes().to_vec());
         while let Some(item) = iter4.next() {
-            assert!(item.0.len() > 0);
+            assert!(!item.0.is_empty());
         }
 
         let mut iter5 = bt.iterator(IteratorOptions::default());
         iter5.seek("cadd".as_bytes().to_vec());
         while let Some(item) = iter5.next() {
-            assert!(item.0.len() > 0);
+            assert!(!item.0.is_empty());
             // println!("{:?}", String::from_utf8(item.0.to_vec()));
         }
 
@@ -266,12 +562,14 @@ mod tests {
         assert!(res6.is_none());
 
         // 反向迭代
-        let mut iter_opts = IteratorOptions::default();
-        iter_opts.reverse = true;
+        let iter_opts = IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        };
         let mut iter7 = bt.iterator(iter_opts);
         iter7.seek("bb".as_bytes().to_vec());
         while let Some(item) = iter7.next() {
-            assert!(item.0.len() > 0);
+            assert!(!item.0.is_empty());
         }
     }
 
@@ -287,10 +585,14 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
-        let mut iter_opt1 = IteratorOptions::default();
-        iter_opt1.reverse = true;
+        let iter_opt1 = IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        };
         let mut iter2 = bt.iterator(iter_opt1);
         assert!(iter2.next().is_some());
 
@@ -300,6 +602,8 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
         bt.put(
@@ -307,6 +611,8 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
         bt.put(
@@ -314,22 +620,243 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                inline: None,
+                version: 0,
             },
         );
 
-        let mut iter_opt2 = IteratorOptions::default();
-        iter_opt2.reverse = true;
+        let iter_opt2 = IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        };
         let mut iter3 = bt.iterator(iter_opt2);
         while let Some(item) = iter3.next() {
-            assert!(item.0.len() > 0);
+            assert!(!item.0.is_empty());
         }
 
         // 有前缀的情况
-        let mut iter_opt3 = IteratorOptions::default();
-        iter_opt3.prefix = "bbed".as_bytes().to_vec();
+        let iter_opt3 = IteratorOptions {
+            prefix: "bbed".as_bytes().to_vec(),
+            ..Default::default()
+        };
         let mut iter4 = bt.iterator(iter_opt3);
         while let Some(item) = iter4.next() {
-            assert!(item.0.len() > 0);
+            assert!(!item.0.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_btree_iterator_many_keys() {
+        let bt = BTree::new();
+        let mut keys = Vec::new();
+        for i in 0..1000 {
+            // 乱序写入，key 的前缀分为 a/b/c 三组
+            let key = std::format!("{}-{:04}", ["a", "b", "c"][i % 3], (i * 7) % 1000);
+            bt.put(
+                key.as_bytes().to_vec(),
+                LogRecordPos {
+                    file_id: 1,
+                    offset: i as u64,
+                    inline: None,
+                    version: 0,
+                },
+            );
+            keys.push(key.as_bytes().to_vec());
+        }
+        keys.sort();
+        keys.dedup();
+
+        // 迭代器只保存当前遍历的位置，不会拷贝整个索引，遍历结果需要和排序后的数组一致
+        let collect = |options: IteratorOptions, seek: Option<&str>| {
+            let mut iter = bt.iterator(options);
+            if let Some(key) = seek {
+                iter.seek(key.as_bytes().to_vec());
+            }
+            let mut res = Vec::new();
+            while let Some((key, _)) = iter.next() {
+                res.push(key.clone());
+            }
+            res
+        };
+
+        // 正向遍历
+        assert_eq!(keys, collect(IteratorOptions::default(), None));
+
+        // 反向遍历
+        let iter_opts1 = IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        let mut reversed = keys.clone();
+        reversed.reverse();
+        assert_eq!(reversed, collect(iter_opts1, None));
+
+        // 正向 seek，从第一个大于等于目标的 key 开始
+        let expected: Vec<Vec<u8>> = keys
+            .iter()
+            .filter(|k| k.as_slice() >= "b-0500".as_bytes())
+            .cloned()
+            .collect();
+        assert_eq!(
+            expected,
+            collect(IteratorOptions::default(), Some("b-0500"))
+        );
+
+        // 反向 seek，从第一个小于等于目标的 key 开始
+        let iter_opts2 = IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        let expected: Vec<Vec<u8>> = reversed
+            .iter()
+            .filter(|k| k.as_slice() <= "b-0500".as_bytes())
+            .cloned()
+            .collect();
+        assert_eq!(expected, collect(iter_opts2, Some("b-0500")));
+
+        // 前缀遍历
+        for reverse in [false, true] {
+            let iter_opts3 = IteratorOptions {
+                prefix: "b-".as_bytes().to_vec(),
+                reverse,
+                ..Default::default()
+            };
+            let mut expected: Vec<Vec<u8>> = keys
+                .iter()
+                .filter(|k| k.starts_with("b-".as_bytes()))
+                .cloned()
+                .collect();
+            if reverse {
+                expected.reverse();
+            }
+            assert_eq!(333, expected.len());
+            assert_eq!(expected, collect(iter_opts3, None));
+        }
+
+        // rewind 之后重新从头开始遍历
+        let mut iter = bt.iterator(IteratorOptions::default());
+        iter.seek("c".as_bytes().to_vec());
+        let first_c = keys.iter().find(|k| k.starts_with("c".as_bytes()));
+        assert_eq!(first_c, iter.next().map(|i| i.0));
+        iter.rewind();
+        assert_eq!(Some(&keys[0]), iter.next().map(|i| i.0));
+    }
+
+    #[test]
+    fn test_btree_bulk_put() {
+        // 包含重复的 key，后出现的位置应当覆盖先出现的
+        let entries: Vec<(Vec<u8>, LogRecordPos)> = (0..1000)
+            .map(|i| {
+                (
+                    format!("key-{:04}", i % 700).into_bytes(),
+                    LogRecordPos {
+                        file_id: 1,
+                        offset: i as u64,
+                        inline: None,
+                        version: 0,
+                    },
+                )
+            })
+            .collect();
+
+        let bt1 = BTree::new();
+        for (key, pos) in entries.clone() {
+            assert!(bt1.put(key, pos));
+        }
+
+        let bt2 = BTree::new();
+        assert!(bt2.bulk_put(entries));
+
+        assert_eq!(*bt1.tree.read(), *bt2.tree.read());
+        assert_eq!(700, bt2.list_keys().unwrap().len());
+        let pos = bt2.get("key-0000".as_bytes().to_vec()).unwrap();
+        assert_eq!(700, pos.offset);
+    }
+
+    #[test]
+    fn test_btree_iterator_write_while_iterating() {
+        let bt = BTree::new();
+        let pos = LogRecordPos {
+            file_id: 1,
+            offset: 10,
+            inline: None,
+            version: 0,
+        };
+        for i in 0..200 {
+            bt.put(format!("key-{:03}", i).into_bytes(), pos);
+        }
+
+        // 迭代器只在读取数据时短暂地持有读锁，遍历过程中可以修改索引
+        let mut iter = bt.iterator(IteratorOptions::default());
+        let mut keys = Vec::new();
+        while let Some((key, _)) = iter.next() {
+            keys.push(key.clone());
+            assert!(bt.delete(key.clone()));
+            // 删除当前位置之后的 key，之后不会再遍历到
+            bt.delete("key-199".as_bytes().to_vec());
+        }
+        assert_eq!(199, keys.len());
+        assert!(bt.list_keys().unwrap().is_empty());
+
+        // 反向遍历时从满足前缀的最大的 key 开始
+        bt.put(vec![b'a'], pos);
+        bt.put(vec![b'a', 0xff], pos);
+        bt.put(vec![b'a', 0xff, 0xff], pos);
+        bt.put(vec![b'b'], pos);
+        let iter_opts = IteratorOptions {
+            prefix: vec![b'a', 0xff],
+            reverse: true,
+            ..Default::default()
+        };
+        let mut iter = bt.iterator(iter_opts);
+        assert_eq!(Some(&vec![b'a', 0xff, 0xff]), iter.next().map(|i| i.0));
+        assert_eq!(Some(&vec![b'a', 0xff]), iter.next().map(|i| i.0));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_btree_key_order() {
+        // key 中只保存一个指针
+        let comparator: Comparator = Arc::new(|a: &[u8], b: &[u8]| b.cmp(a));
+        assert!(!KeyOrder::new(None).is_custom());
+        assert_eq!(
+            mem::size_of::<Vec<u8>>() + mem::size_of::<usize>(),
+            mem::size_of::<IndexKey>()
+        );
+
+        // 索引只持有一份比较函数，写入 key 和创建迭代器时不会增加比较函数的引用计数
+        let count = Arc::strong_count(&comparator);
+        let bt = BTree::with_comparator(Some(comparator.clone()));
+        for i in 0..10 {
+            bt.put(
+                vec![i],
+                LogRecordPos {
+                    file_id: 1,
+                    offset: i as u64,
+                    inline: None,
+                    version: 0,
+                },
+            );
         }
+        assert_eq!(count + 1, Arc::strong_count(&comparator));
+
+        // 按照比较函数倒序排列
+        let mut iter = bt.iterator(IteratorOptions::default());
+        assert_eq!(Some(&vec![9]), iter.next().map(|i| i.0));
+        assert_eq!(Some(&vec![8]), iter.next().map(|i| i.0));
+        assert_eq!(count + 1, Arc::strong_count(&comparator));
+
+        // 迭代器、快照和 key 都持有比较函数，索引和排序方式释放之后仍然有效，全部释放之后比较函数也被释放
+        let (tree, order) = bt.snapshot();
+        std::mem::drop(bt);
+        assert_eq!(Some(&vec![7]), iter.next().map(|i| i.0));
+        let key = order.key(vec![1]);
+        std::mem::drop(order);
+        assert_eq!(Some(&1), tree.get(&key).map(|pos| &pos.offset));
+        std::mem::drop(iter);
+        std::mem::drop(tree);
+        assert_eq!(count + 1, Arc::strong_count(&comparator));
+        std::mem::drop(key);
+        assert_eq!(count, Arc::strong_count(&comparator));
     }
 }
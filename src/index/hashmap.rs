@@ -0,0 +1,193 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use parking_lot::RwLock;
+
+use crate::{
+    data::log_record::LogRecordPos,
+    errors::Result,
+    options::{Comparator, IteratorOptions},
+};
+
+use super::{
+    btree::{BTreeIterator, IndexKey, KeyOrder},
+    IndexIterator, Indexer,
+};
+
+// 哈希索引，封装了标准库中的 HashMap，适合只有点查的场景
+// 索引本身不维护 key 的顺序，list_keys 和迭代器会先把 key 排序，结果和其他索引一致
+pub struct HashMapIndex {
+    map: RwLock<HashMap<Vec<u8>, LogRecordPos>>,
+    order: KeyOrder, // key 的排序方式，只在迭代时使用
+}
+
+impl HashMapIndex {
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::with_comparator(None)
+    }
+
+    pub fn with_comparator(comparator: Option<Comparator>) -> Self {
+        Self {
+            map: RwLock::new(HashMap::new()),
+            order: KeyOrder::new(comparator),
+        }
+    }
+}
+
+impl HashMapIndex {
+    // 将满足前缀的 key 按照顺序拷贝到 BTreeMap 中
+    fn sorted(&self, prefix: &[u8]) -> BTreeMap<IndexKey, LogRecordPos> {
+        let read_guard = self.map.read();
+        read_guard
+            .iter()
+            .filter(|(k, _)| k.starts_with(prefix))
+            .map(|(k, pos)| (self.order.key(k.clone()), *pos))
+            .collect()
+    }
+}
+
+impl Indexer for HashMapIndex {
+    fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool {
+        let mut write_guard = self.map.write();
+        write_guard.insert(key, pos);
+        true
+    }
+
+    fn bulk_put(&self, entries: Vec<(Vec<u8>, LogRecordPos)>) -> bool {
+        let mut write_guard = self.map.write();
+        write_guard.extend(entries);
+        true
+    }
+
+    fn get(&self, key: Vec<u8>) -> Option<LogRecordPos> {
+        let read_guard = self.map.read();
+        read_guard.get(&key).copied()
+    }
+
+    fn delete(&self, key: Vec<u8>) -> bool {
+        let mut write_guard = self.map.write();
+        write_guard.remove(&key).is_some()
+    }
+
+    fn clear(&self) {
+        self.map.write().clear();
+    }
+
+    /// 按照 key 的顺序返回所有的 key，需要先拷贝并排序
+    fn list_keys(&self) -> Result<Vec<Bytes>> {
+        Ok(self
+            .sorted(&[])
+            .into_keys()
+            .map(|k| Bytes::from(k.key))
+            .collect())
+    }
+
+    /// 哈希索引没有顺序，迭代器会把当前的数据拷贝一份后再遍历，
+    /// 代价和索引的大小成正比，遍历过程中看不到之后写入的数据
+    fn iterator(&self, options: IteratorOptions) -> Box<dyn IndexIterator> {
+        let tree = self.sorted(&options.prefix);
+        Box::new(BTreeIterator::new(
+            Arc::new(tree),
+            options,
+            self.order.clone(),
+        ))
+    }
+
+    /// 按照 key 的顺序拷贝一份当前的索引，代价和索引的大小成正比
+    fn snapshot(&self) -> (Arc<BTreeMap<IndexKey, LogRecordPos>>, KeyOrder) {
+        (Arc::new(self.sorted(&[])), self.order.clone())
+    }
+
+    /// 每个条目包括 key 的内容、Vec 和 LogRecordPos 本身，以及哈希表中一个字节的控制信息
+    fn memory_usage(&self) -> usize {
+        let entry_size = mem::size_of::<Vec<u8>>() + mem::size_of::<LogRecordPos>() + 1;
+        let read_guard = self.map.read();
+        read_guard.keys().map(|k| k.capacity() + entry_size).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashmap_put_get_delete() {
+        let hm = HashMapIndex::new();
+        let res1 = hm.put(
+            "aa".as_bytes().to_vec(),
+            LogRecordPos {
+                file_id: 11,
+                offset: 22,
+                inline: None,
+                version: 0,
+            },
+        );
+        assert!(res1);
+        let res2 = hm.put(
+            "aa".as_bytes().to_vec(),
+            LogRecordPos {
+                file_id: 11,
+                offset: 33,
+                inline: None,
+                version: 0,
+            },
+        );
+        assert!(res2);
+        let pos1 = hm.get("aa".as_bytes().to_vec());
+        assert_eq!(33, pos1.unwrap().offset);
+        assert!(hm.get("not exist".as_bytes().to_vec()).is_none());
+
+        let res3 = hm.delete("aa".as_bytes().to_vec());
+        assert!(res3);
+        let res4 = hm.delete("aa".as_bytes().to_vec());
+        assert!(!res4);
+        assert!(hm.get("aa".as_bytes().to_vec()).is_none());
+    }
+
+    #[test]
+    fn test_hashmap_list_keys_and_iterator() {
+        let hm = HashMapIndex::new();
+        for i in 0..10 {
+            hm.put(
+                format!("key-{}", i).into_bytes(),
+                LogRecordPos {
+                    file_id: 1,
+                    offset: i,
+                    inline: None,
+                    version: 0,
+                },
+            );
+        }
+
+        // list_keys 按照 key 的顺序返回
+        let keys = hm.list_keys().unwrap();
+        assert_eq!(10, keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(Bytes::from(format!("key-{}", i)), key);
+        }
+
+        // 迭代器返回所有满足前缀的 key
+        let iter_opts = IteratorOptions {
+            prefix: "key-1".as_bytes().to_vec(),
+            ..Default::default()
+        };
+        let mut iter = hm.iterator(iter_opts);
+        let item = iter.next();
+        assert_eq!(Some(1), item.map(|(_, pos)| pos.offset));
+        assert!(iter.next().is_none());
+
+        let mut iter2 = hm.iterator(IteratorOptions::default());
+        hm.clear();
+        let mut count = 0;
+        while iter2.next().is_some() {
+            count += 1;
+        }
+        assert_eq!(10, count);
+        assert!(hm.list_keys().unwrap().is_empty());
+    }
+}
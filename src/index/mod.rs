@@ -1,11 +1,16 @@
 pub mod btree;
+pub mod hashmap;
+pub mod snapshot_btree;
+
+use std::{collections::BTreeMap, sync::Arc};
 
 use bytes::Bytes;
 
+use self::btree::{IndexKey, KeyOrder};
 use crate::{
     data::log_record::LogRecordPos,
     errors::Result,
-    options::{IndexType, IteratorOptions},
+    options::{Comparator, IndexType, IteratorOptions},
 };
 
 /// Indexr 抽象索引接口，后续如果想要接入其他的数据结构，则直接实现这个接口即可
@@ -13,23 +18,51 @@ pub trait Indexer: Sync + Send {
     /// 向索引中存储 key 对应的数据位置信息
     fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool;
 
+    /// 批量存储 key 对应的数据位置信息，同一个 key 出现多次时以最后一次为准
+    ///
+    /// 默认实现逐条调用 put，索引实现可以覆盖此方法只加一次锁
+    fn bulk_put(&self, entries: Vec<(Vec<u8>, LogRecordPos)>) -> bool {
+        for (key, pos) in entries {
+            if !self.put(key, pos) {
+                return false;
+            }
+        }
+        true
+    }
+
     /// 根据 key 取出对应的索引位置信息
     fn get(&self, key: Vec<u8>) -> Option<LogRecordPos>;
 
     /// 根据 key 删除对应的索引位置信息
     fn delete(&self, key: Vec<u8>) -> bool;
 
+    /// 清空索引中的所有数据
+    fn clear(&self);
+
     /// 获取索引存储所有的 key
     fn list_keys(&self) -> Result<Vec<Bytes>>;
     /// 返回索引迭代器
     fn iterator(&self, options: IteratorOptions) -> Box<dyn IndexIterator>;
+
+    /// 返回索引当前状态的快照和 key 的排序方式，之后对索引的修改不会影响快照
+    /// 快照中的 key 依赖排序方式持有的比较函数，两者需要保存在一起
+    fn snapshot(&self) -> (Arc<BTreeMap<IndexKey, LogRecordPos>>, KeyOrder);
+
+    /// 估算索引占用的内存大小，包括 key 的长度和每个条目中 key、位置信息本身的大小
+    /// 不包括数据结构内部没有使用的空间，只能用于粗略估计，需要遍历整个索引
+    fn memory_usage(&self) -> usize;
 }
 
 /// 根据类型打开内存索引
-pub fn new_indexer(index_type: IndexType) -> impl Indexer {
+pub fn new_indexer(index_type: IndexType, comparator: Option<Comparator>) -> Box<dyn Indexer> {
     match index_type {
-        IndexType::BTree => btree::BTree::new(),
-        IndexType::SkipList => todo!(),
+        IndexType::BTree => Box::new(btree::BTree::with_comparator(comparator)),
+        // 打开数据库时 check_options 已经拒绝了还没有实现的跳表索引
+        IndexType::SkipList => unreachable!("skiplist index is not supported"),
+        IndexType::SnapshotBTree => {
+            Box::new(snapshot_btree::SnapshotBTree::with_comparator(comparator))
+        }
+        IndexType::HashMap => Box::new(hashmap::HashMapIndex::with_comparator(comparator)),
     }
 }
 
@@ -41,6 +74,12 @@ pub trait IndexIterator: Sync + Send {
     /// seek 根据传入的 key 查找到第一个大于（或小于）等于的目标 key，根据从这个 key 开始遍历
     fn seek(&mut self, key: Vec<u8>);
 
+    /// seek_to_first 跳转到最小的 key，之后调用 next 会先返回这个 key
+    fn seek_to_first(&mut self);
+
+    /// seek_to_last 跳转到最大的 key，之后调用 next 会先返回这个 key
+    fn seek_to_last(&mut self);
+
     /// next 跳转到下一个 key，返回 none 则说明迭代完毕
     fn next(&mut self) -> Option<(&Vec<u8>, &LogRecordPos)>;
 }
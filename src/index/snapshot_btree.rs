@@ -0,0 +1,220 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use arc_swap::ArcSwap;
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::{
+    data::log_record::LogRecordPos,
+    errors::Result,
+    options::{Comparator, IteratorOptions},
+};
+
+use super::{
+    btree::{tree_memory_usage, BTreeIterator, IndexKey, KeyOrder},
+    IndexIterator, Indexer,
+};
+
+// 快照 BTree 索引，读取时直接拿到当前 BTreeMap 的快照，不会被写入阻塞
+// 写入时拷贝一份新的 BTreeMap 并修改，然后替换掉旧的快照，写入的代价和索引的大小成正比
+pub struct SnapshotBTree {
+    tree: ArcSwap<BTreeMap<IndexKey, LogRecordPos>>,
+    write_lock: Mutex<()>, // 写锁，保证多个写入之间不会互相覆盖
+    order: KeyOrder,       // key 的排序方式
+}
+
+impl SnapshotBTree {
+    // 按照字节序排序的索引
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::with_comparator(None)
+    }
+
+    pub fn with_comparator(comparator: Option<Comparator>) -> Self {
+        Self {
+            tree: ArcSwap::from_pointee(BTreeMap::new()),
+            write_lock: Mutex::new(()),
+            order: KeyOrder::new(comparator),
+        }
+    }
+
+    fn index_key(&self, key: Vec<u8>) -> IndexKey {
+        self.order.key(key)
+    }
+
+    // 拷贝当前的快照并修改，然后替换为新的快照
+    fn update<R>(&self, f: impl FnOnce(&mut BTreeMap<IndexKey, LogRecordPos>) -> R) -> R {
+        let _write_guard = self.write_lock.lock();
+        let mut tree = BTreeMap::clone(&self.tree.load());
+        let res = f(&mut tree);
+        self.tree.store(Arc::new(tree));
+        res
+    }
+}
+
+impl Indexer for SnapshotBTree {
+    fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool {
+        let key = self.index_key(key);
+        self.update(|tree| tree.insert(key, pos));
+        true
+    }
+
+    fn bulk_put(&self, entries: Vec<(Vec<u8>, LogRecordPos)>) -> bool {
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(key, pos)| (self.index_key(key), pos))
+            .collect();
+        self.update(|tree| tree.extend(entries));
+        true
+    }
+
+    fn get(&self, key: Vec<u8>) -> Option<LogRecordPos> {
+        self.tree.load().get(&self.index_key(key)).copied()
+    }
+
+    fn delete(&self, key: Vec<u8>) -> bool {
+        // key 不存在时不需要拷贝索引
+        let key = self.index_key(key);
+        if !self.tree.load().contains_key(&key) {
+            return false;
+        }
+        self.update(|tree| tree.remove(&key)).is_some()
+    }
+
+    fn clear(&self) {
+        let _write_guard = self.write_lock.lock();
+        self.tree.store(Arc::new(BTreeMap::new()));
+    }
+
+    fn list_keys(&self) -> Result<Vec<Bytes>> {
+        let tree = self.tree.load();
+        let mut keys = Vec::with_capacity(tree.len());
+        for (k, _) in tree.iter() {
+            keys.push(Bytes::copy_from_slice(&k.key));
+        }
+        Ok(keys)
+    }
+
+    /// 迭代器持有创建时的快照，遍历过程中不会阻塞写入，也看不到之后写入的数据
+    fn iterator(&self, options: IteratorOptions) -> Box<dyn IndexIterator> {
+        Box::new(BTreeIterator::new(
+            self.tree.load_full(),
+            options,
+            self.order.clone(),
+        ))
+    }
+
+    /// 直接返回当前的快照，不需要拷贝
+    fn snapshot(&self) -> (Arc<BTreeMap<IndexKey, LogRecordPos>>, KeyOrder) {
+        (self.tree.load_full(), self.order.clone())
+    }
+
+    /// 只统计当前的快照，旧的快照被迭代器持有时占用的内存不计算在内
+    fn memory_usage(&self) -> usize {
+        tree_memory_usage(&self.tree.load())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_btree_put_get_delete() {
+        let bt = SnapshotBTree::new();
+        let res1 = bt.put(
+            "aa".as_bytes().to_vec(),
+            LogRecordPos {
+                file_id: 11,
+                offset: 22,
+                inline: None,
+                version: 0,
+            },
+        );
+        assert!(res1);
+        let pos1 = bt.get("aa".as_bytes().to_vec());
+        assert_eq!(22, pos1.unwrap().offset);
+
+        let res2 = bt.delete("aa".as_bytes().to_vec());
+        assert!(res2);
+        let res3 = bt.delete("not exist".as_bytes().to_vec());
+        assert!(!res3);
+        assert!(bt.get("aa".as_bytes().to_vec()).is_none());
+    }
+
+    #[test]
+    fn test_snapshot_btree_iterator_snapshot() {
+        let bt = SnapshotBTree::new();
+        for i in 0..10 {
+            bt.put(
+                format!("key-{}", i).into_bytes(),
+                LogRecordPos {
+                    file_id: 1,
+                    offset: i,
+                    inline: None,
+                    version: 0,
+                },
+            );
+        }
+
+        // 迭代器存在时可以继续写入，迭代器看不到新写入的数据
+        let mut iter = bt.iterator(IteratorOptions::default());
+        bt.put(
+            "key-100".as_bytes().to_vec(),
+            LogRecordPos {
+                file_id: 1,
+                offset: 100,
+                inline: None,
+                version: 0,
+            },
+        );
+        bt.clear();
+        let mut count = 0;
+        while iter.next().is_some() {
+            count += 1;
+        }
+        assert_eq!(10, count);
+        assert!(bt.list_keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_btree_concurrent_reads() {
+        let bt = Arc::new(SnapshotBTree::new());
+
+        // 写入线程每次成对地写入两个 key，它们的 offset 总是相同
+        let writer = {
+            let bt = bt.clone();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    let pos = LogRecordPos {
+                        file_id: 1,
+                        offset: i,
+                        inline: None,
+                        version: 0,
+                    };
+                    bt.bulk_put(vec![(b"a".to_vec(), pos), (b"b".to_vec(), pos)]);
+                }
+            })
+        };
+
+        // 读取线程从快照中读到的两个 key 总是一致的
+        let mut readers = Vec::new();
+        for _ in 0..4 {
+            let bt = bt.clone();
+            readers.push(std::thread::spawn(move || {
+                for _ in 0..1000 {
+                    let mut iter = bt.iterator(IteratorOptions::default());
+                    let a = iter.next().map(|(_, pos)| pos.offset);
+                    let b = iter.next().map(|(_, pos)| pos.offset);
+                    assert_eq!(a, b);
+                }
+            }));
+        }
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(999, bt.get(b"a".to_vec()).unwrap().offset);
+    }
+}
@@ -0,0 +1,1019 @@
+use std::{
+    collections::BTreeMap,
+    sync::{atomic::Ordering, Arc},
+};
+
+use bytes::Bytes;
+use log::error;
+use parking_lot::RwLock;
+
+use crate::{
+    data::log_record::{LogRecordPos, LogRecordType},
+    db::Engine,
+    errors::{Errors, Result},
+    file_pins::FilePin,
+    index::{btree::KeyOrder, IndexIterator},
+    options::IteratorOptions,
+};
+
+/// 迭代器接口
+///
+/// 迭代器释放之前，之后的合并不会删除迭代器可能读取的数据文件，
+/// 迭代器创建之后清空了数据库时，迭代器中的位置已经失效，之后的 next 返回 None
+pub struct Iterator<'a> {
+    index_iter: Arc<RwLock<Box<dyn IndexIterator>>>, // 索引迭代器
+    engine: &'a Engine,
+    epoch: u64,        // 创建时清空数据库的次数
+    _pin: FilePin<'a>, // 保留迭代器创建之后被合并的数据文件
+}
+
+/// 前缀迭代器，每次只从数据文件中读取一条数据，不会一次性将所有的数据加载到内存中
+pub struct PrefixIter<'a> {
+    index_iter: Box<dyn IndexIterator>, // 带有前缀的索引迭代器
+    engine: &'a Engine,
+    epoch: u64,        // 创建时清空数据库的次数
+    _pin: FilePin<'a>, // 保留迭代器创建之后被合并的数据文件
+}
+
+/// 包含删除记录的迭代器，删除的 key 对应的 value 为 None
+pub struct DeletedIter<'a> {
+    records: std::vec::IntoIter<(Vec<u8>, Option<LogRecordPos>)>, // key 和最新一条记录的位置，删除记录为 None
+    engine: &'a Engine,
+    epoch: u64,        // 创建时清空数据库的次数
+    _pin: FilePin<'a>, // 保留迭代器创建之后被合并的数据文件
+}
+
+impl Engine {
+    /// 获取迭代器
+    pub fn iter(&self, options: IteratorOptions) -> Iterator<'_> {
+        // 先记录版本再读取索引，读取到的位置所在的数据文件都会被保留
+        let pin = self.file_pins.pin();
+        Iterator {
+            index_iter: Arc::new(RwLock::new(self.index.iterator(options))),
+            engine: self,
+            epoch: self.clear_epoch.load(Ordering::SeqCst),
+            _pin: pin,
+        }
+    }
+
+    /// 获取以 prefix 开头的 key 的迭代器，按照 key 从小到大返回
+    pub fn prefix_iter(&self, prefix: Bytes) -> PrefixIter<'_> {
+        let pin = self.file_pins.pin();
+        let epoch = self.clear_epoch.load(Ordering::SeqCst);
+        PrefixIter {
+            index_iter: self.index.iterator(IteratorOptions {
+                prefix: prefix.to_vec(),
+                ..Default::default()
+            }),
+            engine: self,
+            epoch,
+            _pin: pin,
+        }
+    }
+
+    /// 获取包含已经删除的 key 的迭代器，options.include_deleted 为 true 时删除的 key 返回的 value 为 None
+    /// 索引中不保存删除的 key，创建时会扫描所有的数据文件记录每个 key 最新的状态，
+    /// 代价和数据文件的大小成正比，之后写入的数据不会出现在迭代器中
+    pub fn iter_with_deleted(&self, options: IteratorOptions) -> Result<DeletedIter<'_>> {
+        let pin = self.file_pins.pin();
+        let epoch = self.clear_epoch.load(Ordering::SeqCst);
+        let order = KeyOrder::new(self.options.comparator.clone());
+        let mut tree = BTreeMap::new();
+        self.for_each_record(|pos, record| {
+            if !record.key.starts_with(&options.prefix) {
+                return;
+            }
+            let pos = match record.rec_type {
+                LogRecordType::NORMAL => Some(pos),
+                LogRecordType::DELETED => None,
+                LogRecordType::FOOTER => return,
+            };
+            tree.insert(order.key(record.key), pos);
+        })?;
+
+        let mut records: Vec<(Vec<u8>, Option<LogRecordPos>)> = tree
+            .into_iter()
+            .filter(|(_, pos)| options.include_deleted || pos.is_some())
+            .map(|(key, pos)| (key.key, pos))
+            .collect();
+        if options.reverse {
+            records.reverse();
+        }
+
+        Ok(DeletedIter {
+            records: records.into_iter(),
+            engine: self,
+            epoch,
+            _pin: pin,
+        })
+    }
+
+    /// 返回数据库中所有的 key
+    pub fn list_keys(&self) -> Result<Vec<Bytes>> {
+        self.index.list_keys()
+    }
+
+    /// 对数据库中当中的所有数据执行函数操作，函数返回 false 时终止
+    pub fn fold<F>(&self, f: F) -> Result<()>
+    where
+        Self: Sized,
+        F: Fn(Bytes, Bytes) -> bool,
+    {
+        let iter = self.iter(IteratorOptions::default());
+        while let Some((key, value)) = iter.next() {
+            if !f(key, value) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 按照 key 的顺序对所有的 value 执行函数操作，函数返回 false 时终止
+    /// 不会为每个 key 创建 Bytes，只需要 value 时比 fold 分配更少的内存
+    pub fn for_each_value<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(Bytes) -> bool,
+    {
+        // 和 iter 一样先记录版本再读取索引，遍历过程中合并的数据文件不会被删除
+        let _pin = self.file_pins.pin();
+        let mut index_iter = self.index.iterator(IteratorOptions::default());
+        while let Some((_, pos)) = index_iter.next() {
+            let value = self.get_value_by_position(pos)?;
+            if !f(value) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 删除所有以 prefix 开头的 key，返回删除的数量
+    pub fn delete_prefix(&self, prefix: Bytes) -> Result<usize> {
+        if prefix.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        // 先取出所有匹配的 key，释放索引迭代器之后再进行删除
+        let mut keys = Vec::new();
+        {
+            let _pin = self.file_pins.pin();
+            let mut index_iter = self.index.iterator(IteratorOptions {
+                prefix: prefix.to_vec(),
+                ..Default::default()
+            });
+            while let Some((key, _)) = index_iter.next() {
+                keys.push(Bytes::copy_from_slice(key));
+            }
+        }
+
+        for key in keys.iter() {
+            self.delete(key.clone())?;
+        }
+        Ok(keys.len())
+    }
+
+    /// 删除 [start, end) 范围内的所有 key，返回删除的数量，start 不小于 end 时不删除任何 key
+    /// 使用了自定义的比较函数时按照比较函数判断 key 是否在范围内
+    pub fn delete_range(&self, start: Bytes, end: Bytes) -> Result<usize> {
+        let compare = |a: &[u8], b: &[u8]| match &self.options.comparator {
+            Some(comparator) => comparator(a, b),
+            None => a.cmp(b),
+        };
+        if compare(&start, &end) != std::cmp::Ordering::Less {
+            return Ok(0);
+        }
+
+        // 先取出范围内的所有 key，释放索引迭代器之后再进行删除
+        let mut keys = Vec::new();
+        {
+            let _pin = self.file_pins.pin();
+            let mut index_iter = self.index.iterator(IteratorOptions::default());
+            index_iter.seek(start.to_vec());
+            while let Some((key, _)) = index_iter.next() {
+                if compare(key, &end) != std::cmp::Ordering::Less {
+                    break;
+                }
+                keys.push(Bytes::copy_from_slice(key));
+            }
+        }
+
+        for key in keys.iter() {
+            self.delete(key.clone())?;
+        }
+        Ok(keys.len())
+    }
+
+    /// 按照 key 从小到大返回大于 start 的最多 limit 条数据，start 为空时从第一个 key 开始
+    /// 用于分页读取，下一次调用时传入上一页最后一个 key 即可继续读取
+    pub fn scan(&self, start: Option<Bytes>, limit: usize) -> Result<Vec<(Bytes, Bytes)>> {
+        let _pin = self.file_pins.pin();
+        let mut index_iter = self.index.iterator(IteratorOptions::default());
+        if let Some(start) = &start {
+            index_iter.seek(start.to_vec());
+        }
+
+        let mut entries = Vec::with_capacity(limit);
+        while entries.len() < limit {
+            let (key, pos) = match index_iter.next() {
+                Some(item) => item,
+                None => break,
+            };
+            // seek 会定位到等于 start 的 key，上一页已经返回过这个 key
+            if start.as_deref() == Some(key.as_slice()) {
+                continue;
+            }
+            let value = self.get_value_by_position(pos)?;
+            entries.push((Bytes::copy_from_slice(key), value));
+        }
+        Ok(entries)
+    }
+
+    /// 返回数据库中最小的 key，数据库为空时返回 None
+    pub fn first_key(&self) -> Result<Option<Bytes>> {
+        let _pin = self.file_pins.pin();
+        let mut index_iter = self.index.iterator(IteratorOptions::default());
+        index_iter.seek_to_first();
+        Ok(index_iter
+            .next()
+            .map(|(key, _)| Bytes::copy_from_slice(key)))
+    }
+
+    /// 返回数据库中最大的 key，数据库为空时返回 None
+    pub fn last_key(&self) -> Result<Option<Bytes>> {
+        let _pin = self.file_pins.pin();
+        let mut index_iter = self.index.iterator(IteratorOptions::default());
+        index_iter.seek_to_last();
+        Ok(index_iter
+            .next()
+            .map(|(key, _)| Bytes::copy_from_slice(key)))
+    }
+
+    // 读取迭代器中的位置对应的 value，清空数据库时会删除所有的数据文件，
+    // 和清空同时进行的读取可能找不到数据文件，迭代器创建之后清空了数据库时读取失败返回 None
+    fn iter_value(&self, pos: &LogRecordPos, epoch: u64) -> Option<Result<Bytes>> {
+        match self.get_value_by_position(pos) {
+            Err(_) if self.clear_epoch.load(Ordering::SeqCst) != epoch => None,
+            res => Some(res),
+        }
+    }
+}
+
+impl Iterator<'_> {
+    /// Rewind 重新回到迭代器的起点，即第一个数据
+    pub fn rewind(&self) {
+        let mut index_iter = self.index_iter.write();
+        index_iter.rewind();
+    }
+
+    /// Seek 根据传入的 key 查找到第一个大于（或小于）等于的目标 key，根据从这个 key 开始遍历
+    pub fn seek(&self, key: Vec<u8>) {
+        let mut index_iter = self.index_iter.write();
+        index_iter.seek(key);
+    }
+
+    /// SeekToFirst 跳转到最小的 key，正向遍历时等同于 rewind，反向遍历时之后只会返回这一个 key
+    pub fn seek_to_first(&self) {
+        let mut index_iter = self.index_iter.write();
+        index_iter.seek_to_first();
+    }
+
+    /// SeekToLast 跳转到最大的 key，反向遍历时等同于 rewind，正向遍历时之后只会返回这一个 key
+    pub fn seek_to_last(&self) {
+        let mut index_iter = self.index_iter.write();
+        index_iter.seek_to_last();
+    }
+
+    /// Next 跳转到下一个 key，返回 None 则说明迭代完毕
+    /// 迭代器创建之后清空了数据库，或者从数据文件中读取 value 失败时同样返回 None
+    pub fn next(&self) -> Option<(Bytes, Bytes)> {
+        let mut index_iter = self.index_iter.write();
+        if self.engine.clear_epoch.load(Ordering::SeqCst) != self.epoch {
+            return None;
+        }
+        let (key, pos) = index_iter.next()?;
+        match self.engine.iter_value(pos, self.epoch)? {
+            Ok(value) => Some((Bytes::from(key.to_vec()), value)),
+            Err(e) => {
+                error!("failed to get value from data file: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl std::iter::Iterator for PrefixIter<'_> {
+    type Item = Result<(Bytes, Bytes)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.engine.clear_epoch.load(Ordering::SeqCst) != self.epoch {
+            return None;
+        }
+        let (key, pos) = self.index_iter.next()?;
+        let key = Bytes::copy_from_slice(key);
+        let pos = *pos;
+        Some(
+            self.engine
+                .iter_value(&pos, self.epoch)?
+                .map(|value| (key, value)),
+        )
+    }
+}
+
+impl std::iter::Iterator for DeletedIter<'_> {
+    type Item = Result<(Bytes, Option<Bytes>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.engine.clear_epoch.load(Ordering::SeqCst) != self.epoch {
+            return None;
+        }
+        let (key, pos) = self.records.next()?;
+        let key = Bytes::from(key);
+        match pos {
+            Some(pos) => Some(
+                self.engine
+                    .iter_value(&pos, self.epoch)?
+                    .map(|value| (key, Some(value))),
+            ),
+            None => Some(Ok((key, None))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{options::Options, util};
+
+    use super::*;
+
+    #[test]
+    fn test_list_keys() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-list-keys"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let keys1 = engine.list_keys();
+        assert_eq!(keys1.ok().unwrap().len(), 0);
+
+        let put_res1 = engine.put(Bytes::from("aacc"), util::rand_kv::get_test_value(10));
+        assert!(put_res1.is_ok());
+        let put_res2 = engine.put(Bytes::from("eecc"), util::rand_kv::get_test_value(10));
+        assert!(put_res2.is_ok());
+        let put_res3 = engine.put(Bytes::from("bbac"), util::rand_kv::get_test_value(10));
+        assert!(put_res3.is_ok());
+        let put_res4 = engine.put(Bytes::from("ccde"), util::rand_kv::get_test_value(10));
+        assert!(put_res4.is_ok());
+
+        let keys2 = engine.list_keys();
+        assert_eq!(keys2.ok().unwrap().len(), 4);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_fold() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-fold"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let put_res1 = engine.put(Bytes::from("aacc"), util::rand_kv::get_test_value(10));
+        assert!(put_res1.is_ok());
+        let put_res2 = engine.put(Bytes::from("eecc"), util::rand_kv::get_test_value(10));
+        assert!(put_res2.is_ok());
+        let put_res3 = engine.put(Bytes::from("bbac"), util::rand_kv::get_test_value(10));
+        assert!(put_res3.is_ok());
+        let put_res4 = engine.put(Bytes::from("ccde"), util::rand_kv::get_test_value(10));
+        assert!(put_res4.is_ok());
+
+        engine
+            .fold(|key, value| {
+                assert!(!key.is_empty());
+                assert!(!value.is_empty());
+                true
+            })
+            .unwrap();
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_seek() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-iter-seek"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 没有数据的情况
+        let iter1 = engine.iter(IteratorOptions::default());
+        iter1.seek("aa".as_bytes().to_vec());
+        assert!(iter1.next().is_none());
+
+        // 有一条数据的情况
+        let put_res1 = engine.put(Bytes::from("aacc"), util::rand_kv::get_test_value(10));
+        assert!(put_res1.is_ok());
+        let iter2 = engine.iter(IteratorOptions::default());
+        iter2.seek("a".as_bytes().to_vec());
+        assert!(iter2.next().is_some());
+
+        // 有多条数据的情况
+        let put_res2 = engine.put(Bytes::from("eecc"), util::rand_kv::get_test_value(10));
+        assert!(put_res2.is_ok());
+        let put_res3 = engine.put(Bytes::from("bbac"), util::rand_kv::get_test_value(10));
+        assert!(put_res3.is_ok());
+        let put_res4 = engine.put(Bytes::from("ccde"), util::rand_kv::get_test_value(10));
+        assert!(put_res4.is_ok());
+
+        let iter3 = engine.iter(IteratorOptions::default());
+        iter3.seek("a".as_bytes().to_vec());
+        assert_eq!(Bytes::from("aacc"), iter3.next().unwrap().0);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_seek_reverse() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-iter-seek-reverse"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for c in 'a'..='z' {
+            let put_res = engine.put(
+                Bytes::from(c.to_string()),
+                util::rand_kv::get_test_value(10),
+            );
+            assert!(put_res.is_ok());
+        }
+
+        let collect = |iter: &Iterator| {
+            let mut keys = String::new();
+            while let Some((key, _)) = iter.next() {
+                keys.push_str(std::str::from_utf8(&key).unwrap());
+            }
+            keys
+        };
+
+        let iter_opts = IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        let iter1 = engine.iter(iter_opts);
+
+        // 反向 seek，返回小于等于目标的 key，并且从大到小排列
+        iter1.seek("m".as_bytes().to_vec());
+        assert_eq!("mlkjihgfedcba", collect(&iter1));
+        iter1.seek("mm".as_bytes().to_vec());
+        assert_eq!("mlkjihgfedcba", collect(&iter1));
+
+        // 反向遍历时跳转到最大的 key 会返回所有的数据，跳转到最小的 key 只返回一条数据
+        iter1.seek_to_last();
+        assert_eq!("zyxwvutsrqponmlkjihgfedcba", collect(&iter1));
+        iter1.seek_to_first();
+        assert_eq!("a", collect(&iter1));
+
+        // 正向遍历
+        let iter2 = engine.iter(IteratorOptions::default());
+        iter2.seek("m".as_bytes().to_vec());
+        assert_eq!("mnopqrstuvwxyz", collect(&iter2));
+        iter2.seek_to_last();
+        assert_eq!("z", collect(&iter2));
+        iter2.seek_to_first();
+        assert_eq!("abcdefghijklmnopqrstuvwxyz", collect(&iter2));
+
+        // 带有前缀时跳转到满足前缀的最大和最小的 key
+        for k in ["ma", "mb", "mc"] {
+            let put_res = engine.put(Bytes::from(k), util::rand_kv::get_test_value(10));
+            assert!(put_res.is_ok());
+        }
+        let iter_opts3 = IteratorOptions {
+            prefix: "m".as_bytes().to_vec(),
+            reverse: true,
+            ..Default::default()
+        };
+        let iter3 = engine.iter(iter_opts3);
+        iter3.seek_to_last();
+        assert_eq!("mcmbmam", collect(&iter3));
+        iter3.seek_to_first();
+        assert_eq!("m", collect(&iter3));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_next() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-iter-next"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 有一条数据的情况
+        let put_res1 = engine.put(Bytes::from("eecc"), util::rand_kv::get_test_value(10));
+        assert!(put_res1.is_ok());
+        let iter1 = engine.iter(IteratorOptions::default());
+        assert!(iter1.next().is_some());
+        iter1.rewind();
+        assert!(iter1.next().is_some());
+        assert!(iter1.next().is_none());
+
+        // 有多条数据的情况
+        let put_res2 = engine.put(Bytes::from("aade"), util::rand_kv::get_test_value(10));
+        assert!(put_res2.is_ok());
+        let put_res3 = engine.put(Bytes::from("ddce"), util::rand_kv::get_test_value(10));
+        assert!(put_res3.is_ok());
+        let put_res4 = engine.put(Bytes::from("bbcc"), util::rand_kv::get_test_value(10));
+        assert!(put_res4.is_ok());
+
+        let iter_opts1 = IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        let iter2 = engine.iter(iter_opts1);
+        while let Some(item) = iter2.next() {
+            assert!(!item.0.is_empty());
+        }
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_prefix() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-iter-prefix"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let put_res1 = engine.put(Bytes::from("eecc"), util::rand_kv::get_test_value(10));
+        assert!(put_res1.is_ok());
+        let put_res2 = engine.put(Bytes::from("aade"), util::rand_kv::get_test_value(10));
+        assert!(put_res2.is_ok());
+        let put_res3 = engine.put(Bytes::from("ddce"), util::rand_kv::get_test_value(10));
+        assert!(put_res3.is_ok());
+        let put_res4 = engine.put(Bytes::from("bbcc"), util::rand_kv::get_test_value(10));
+        assert!(put_res4.is_ok());
+        let put_res4 = engine.put(Bytes::from("ddaa"), util::rand_kv::get_test_value(10));
+        assert!(put_res4.is_ok());
+
+        let iter_opt1 = IteratorOptions {
+            prefix: "dd".as_bytes().to_vec(),
+            ..Default::default()
+        };
+        let iter1 = engine.iter(iter_opt1);
+        while let Some(item) = iter1.next() {
+            assert!(!item.0.is_empty());
+        }
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_delete_prefix() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-delete-prefix"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for key in [
+            "tmp:1",
+            "tmp:2",
+            "tmp:3",
+            "tm",
+            "atmp:1",
+            "user:1",
+            "user:tmp:1",
+        ] {
+            let put_res = engine.put(Bytes::from(key), util::rand_kv::get_test_value(10));
+            assert!(put_res.is_ok());
+        }
+
+        // 删除所有 tmp: 开头的 key
+        let del_res1 = engine.delete_prefix(Bytes::from("tmp:"));
+        assert_eq!(3, del_res1.unwrap());
+        for key in ["tmp:1", "tmp:2", "tmp:3"] {
+            let get_res = engine.get(Bytes::from(key));
+            assert_eq!(Errors::KeyNotFound, get_res.err().unwrap());
+        }
+        let keys = engine.list_keys().unwrap();
+        assert_eq!(
+            vec!["atmp:1", "tm", "user:1", "user:tmp:1"],
+            keys.iter()
+                .map(|k| std::str::from_utf8(k).unwrap())
+                .collect::<Vec<&str>>()
+        );
+
+        // 没有匹配的 key
+        let del_res2 = engine.delete_prefix(Bytes::from("tmp:"));
+        assert_eq!(0, del_res2.unwrap());
+
+        // 前缀为空
+        let del_res3 = engine.delete_prefix(Bytes::new());
+        assert_eq!(Errors::KeyIsEmpty, del_res3.err().unwrap());
+
+        // 重启之后删除的 key 仍然不存在
+        std::mem::drop(engine);
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(4, engine2.list_keys().unwrap().len());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_prefix_iter() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-prefix-iter"),
+            data_file_size: 64 * 1024,
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 数据分布在多个数据文件中
+        for i in 0..10000 {
+            let key = Bytes::from(format!("{}-{:05}", ["a", "b"][i % 2], i));
+            let put_res = engine.put(key, util::rand_kv::get_test_value(i));
+            assert!(put_res.is_ok());
+        }
+        assert!(engine.stat().unwrap().data_file_num > 1);
+
+        let mut count = 0;
+        for (i, item) in engine.prefix_iter(Bytes::from("b-")).enumerate() {
+            let (key, value) = item.unwrap();
+            let n = i * 2 + 1;
+            assert_eq!(Bytes::from(format!("b-{:05}", n)), key);
+            assert_eq!(util::rand_kv::get_test_value(n), value);
+            count += 1;
+        }
+        assert_eq!(5000, count);
+
+        // 没有匹配的 key
+        assert!(engine.prefix_iter(Bytes::from("c")).next().is_none());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_scan() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-scan"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..100 {
+            let put_res = engine.put(
+                util::rand_kv::get_test_key(i),
+                util::rand_kv::get_test_value(i),
+            );
+            assert!(put_res.is_ok());
+        }
+
+        // 每页 10 条，依次读取所有的数据
+        let mut keys = Vec::new();
+        let mut start = None;
+        loop {
+            let page = engine.scan(start.clone(), 10).unwrap();
+            if page.is_empty() {
+                break;
+            }
+            assert_eq!(10, page.len());
+            for (key, value) in page.iter() {
+                assert_eq!(engine.get(key.clone()).unwrap(), value);
+            }
+            start = page.last().map(|(key, _)| key.clone());
+            keys.extend(page.into_iter().map(|(key, _)| key));
+        }
+
+        // 所有的 key 都出现且只出现一次，并且按照顺序返回
+        assert_eq!(100, keys.len());
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+        // start 不存在时从大于它的第一个 key 开始
+        let page = engine.scan(Some(Bytes::from("bitcask-rs-key")), 1).unwrap();
+        assert_eq!(keys[0], page[0].0);
+        assert!(engine.scan(None, 0).unwrap().is_empty());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_first_and_last_key() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-first-last-key"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 空的数据库
+        assert_eq!(None, engine.first_key().unwrap());
+        assert_eq!(None, engine.last_key().unwrap());
+
+        let put_res1 = engine.put(Bytes::from("ccde"), util::rand_kv::get_test_value(10));
+        assert!(put_res1.is_ok());
+        assert_eq!(Some(Bytes::from("ccde")), engine.first_key().unwrap());
+        assert_eq!(Some(Bytes::from("ccde")), engine.last_key().unwrap());
+
+        let put_res2 = engine.put(Bytes::from("aacc"), util::rand_kv::get_test_value(10));
+        assert!(put_res2.is_ok());
+        let put_res3 = engine.put(Bytes::from("eecc"), util::rand_kv::get_test_value(10));
+        assert!(put_res3.is_ok());
+        let put_res4 = engine.put(Bytes::from("bbac"), util::rand_kv::get_test_value(10));
+        assert!(put_res4.is_ok());
+        assert_eq!(Some(Bytes::from("aacc")), engine.first_key().unwrap());
+        assert_eq!(Some(Bytes::from("eecc")), engine.last_key().unwrap());
+
+        // 删除之后返回剩下的 key 中的最小值和最大值
+        let del_res1 = engine.delete(Bytes::from("aacc"));
+        assert!(del_res1.is_ok());
+        let del_res2 = engine.delete(Bytes::from("eecc"));
+        assert!(del_res2.is_ok());
+        assert_eq!(Some(Bytes::from("bbac")), engine.first_key().unwrap());
+        assert_eq!(Some(Bytes::from("ccde")), engine.last_key().unwrap());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_for_each_value() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-for-each-value"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 空的数据库不会调用函数
+        let res1 = engine.for_each_value(|_| panic!("no values"));
+        assert!(res1.is_ok());
+
+        let mut total = 0;
+        for i in 0..100 {
+            let value = util::rand_kv::get_test_value(i);
+            total += value.len();
+            let put_res = engine.put(util::rand_kv::get_test_key(i), value);
+            assert!(put_res.is_ok());
+        }
+
+        // 统计所有 value 的长度
+        let mut sum = 0;
+        let res2 = engine.for_each_value(|value| {
+            sum += value.len();
+            true
+        });
+        assert!(res2.is_ok());
+        assert_eq!(total, sum);
+
+        // 返回 false 时提前终止
+        let mut count = 0;
+        let res3 = engine.for_each_value(|_| {
+            count += 1;
+            count < 10
+        });
+        assert!(res3.is_ok());
+        assert_eq!(10, count);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_for_each_value_while_merging() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-for-each-value-merging"),
+            data_file_size: 4 * 1024,
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 每个 key 写入两次，旧的数据文件中都有可以回收的空间
+        for i in 0..200 {
+            let put_res = engine.put(
+                util::rand_kv::get_test_key(i / 2),
+                util::rand_kv::get_test_value(i / 2),
+            );
+            assert!(put_res.is_ok());
+        }
+        let rotate_res = engine.rotate();
+        assert!(rotate_res.is_ok());
+        assert!(engine.older_files.read().len() > 1);
+
+        // 遍历过程中合并并删除旧的数据文件，已经从索引中读取的位置仍然可以读取
+        let mut count = 0;
+        let res = engine.for_each_value(|_| {
+            if count == 0 {
+                let merge_res = engine.merge_partial(usize::MAX);
+                assert!(merge_res.unwrap() > 1);
+            }
+            count += 1;
+            true
+        });
+        assert!(res.is_ok());
+        assert_eq!(100, count);
+
+        // scan 以及 first_key、last_key 同样可以正常读取
+        assert_eq!(100, engine.scan(None, 1000).unwrap().len());
+        assert!(engine.first_key().unwrap().is_some());
+        assert!(engine.last_key().unwrap().is_some());
+
+        // 删除测试的文件夹
+        std::mem::drop(engine);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iter_with_deleted() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-iter-with-deleted"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let put_res1 = engine.put(Bytes::from("aa"), Bytes::from("v1"));
+        assert!(put_res1.is_ok());
+        let put_res2 = engine.put(Bytes::from("bb"), Bytes::from("v2"));
+        assert!(put_res2.is_ok());
+        let put_res3 = engine.put(Bytes::from("cc"), Bytes::from("v3"));
+        assert!(put_res3.is_ok());
+        let del_res1 = engine.delete(Bytes::from("bb"));
+        assert!(del_res1.is_ok());
+        // 删除之后重新写入的 key 返回最新的 value
+        let del_res2 = engine.delete(Bytes::from("cc"));
+        assert!(del_res2.is_ok());
+        let put_res4 = engine.put(Bytes::from("cc"), Bytes::from("v4"));
+        assert!(put_res4.is_ok());
+
+        // 1.返回删除的 key，value 为 None
+        let iter_opts = IteratorOptions {
+            include_deleted: true,
+            ..Default::default()
+        };
+        let items: Vec<(Bytes, Option<Bytes>)> = engine
+            .iter_with_deleted(iter_opts)
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                (Bytes::from("aa"), Some(Bytes::from("v1"))),
+                (Bytes::from("bb"), None),
+                (Bytes::from("cc"), Some(Bytes::from("v4"))),
+            ],
+            items
+        );
+
+        // 2.反向遍历
+        let iter_opts2 = IteratorOptions {
+            include_deleted: true,
+            reverse: true,
+            ..Default::default()
+        };
+        let keys: Vec<Bytes> = engine
+            .iter_with_deleted(iter_opts2)
+            .unwrap()
+            .map(|item| item.unwrap().0)
+            .collect();
+        assert_eq!(
+            vec![Bytes::from("cc"), Bytes::from("bb"), Bytes::from("aa")],
+            keys
+        );
+
+        // 3.不设置 include_deleted 时不返回删除的 key
+        let iter_opts3 = IteratorOptions {
+            prefix: "b".as_bytes().to_vec(),
+            ..Default::default()
+        };
+        let mut iter = engine.iter_with_deleted(iter_opts3).unwrap();
+        assert!(iter.next().is_none());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_write_while_iterating() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-iter-write-while-iterating"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..200 {
+            let put_res = engine.put(
+                Bytes::from(format!("key-{:03}", i)),
+                util::rand_kv::get_test_value(10),
+            );
+            assert!(put_res.is_ok());
+        }
+
+        // 迭代器不持有索引的锁，遍历过程中可以在当前线程读写数据
+        let iter = engine.iter(IteratorOptions::default());
+        let mut count = 0;
+        while let Some((key, _)) = iter.next() {
+            assert!(engine.get(key.clone()).is_ok());
+            let del_res = engine.delete(key.clone());
+            assert!(del_res.is_ok());
+            if key != "zzz" {
+                let put_res = engine.put(Bytes::from("zzz"), util::rand_kv::get_test_value(10));
+                assert!(put_res.is_ok());
+            }
+            count += 1;
+        }
+        // 遍历过程中写入的 key 在当前位置之后，同样会被遍历到
+        assert_eq!(201, count);
+        assert!(engine.list_keys().unwrap().is_empty());
+
+        // fold 的函数中同样可以写入数据
+        for i in 0..10 {
+            let put_res = engine.put(
+                Bytes::from(format!("key-{:03}", i)),
+                util::rand_kv::get_test_value(10),
+            );
+            assert!(put_res.is_ok());
+        }
+        engine
+            .fold(|key, _| {
+                assert!(engine.delete(key).is_ok());
+                true
+            })
+            .unwrap();
+        assert!(engine.list_keys().unwrap().is_empty());
+
+        // 删除测试的文件夹
+        std::mem::drop(iter);
+        std::mem::drop(engine);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_after_clear() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-iter-after-clear"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..10 {
+            let put_res = engine.put(
+                Bytes::from(format!("key-{:03}", i)),
+                util::rand_kv::get_test_value(i),
+            );
+            assert!(put_res.is_ok());
+        }
+
+        // 清空之前创建的迭代器已经读取过数据，缓冲区中还有清空之前的位置
+        let iter = engine.iter(IteratorOptions::default());
+        assert!(iter.next().is_some());
+        let mut prefix_iter = engine.prefix_iter(Bytes::from("key-"));
+        assert!(prefix_iter.next().unwrap().is_ok());
+        let mut deleted_iter = engine
+            .iter_with_deleted(IteratorOptions::default())
+            .unwrap();
+        assert!(deleted_iter.next().unwrap().is_ok());
+
+        // 清空之后写入新的数据，之前创建的迭代器不会返回任何数据
+        let clear_res = engine.clear();
+        assert!(clear_res.is_ok());
+        for i in 0..10 {
+            let put_res = engine.put(
+                Bytes::from(format!("key-{:03}", i)),
+                util::rand_kv::get_test_value(i + 100),
+            );
+            assert!(put_res.is_ok());
+        }
+        assert!(iter.next().is_none());
+        assert!(prefix_iter.next().is_none());
+        assert!(deleted_iter.next().is_none());
+
+        // 清空之后创建的迭代器可以读取到新的数据
+        let iter2 = engine.iter(IteratorOptions::default());
+        let mut count = 0;
+        while let Some((key, value)) = iter2.next() {
+            assert_eq!(engine.get(key).unwrap(), value);
+            count += 1;
+        }
+        assert_eq!(10, count);
+
+        // 删除测试的文件夹
+        std::mem::drop(iter);
+        std::mem::drop(iter2);
+        std::mem::drop(prefix_iter);
+        std::mem::drop(deleted_iter);
+        std::mem::drop(engine);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
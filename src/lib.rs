@@ -1,11 +1,23 @@
-mod data;
+#[cfg(feature = "tokio")]
+pub mod async_engine;
+pub mod bulk_load;
+pub mod data;
 pub mod db;
 pub mod errors;
+mod export;
+mod file_pins;
 mod fio;
+mod group_commit;
 mod index;
+pub mod iterator;
+mod meta;
+pub mod observer;
 pub mod options;
+pub mod snapshot;
+#[cfg(feature = "serde")]
+mod typed;
 
-mod util;
+pub mod util;
 
 #[cfg(test)]
 mod db_tests;
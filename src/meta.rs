@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use log::{error, warn};
+use parking_lot::Mutex;
+
+use crate::{
+    errors::{Errors, Result},
+    options::Options,
+};
+
+pub(crate) const META_FILE_NAME: &str = "bitcask.meta";
+pub(crate) const UNSEALED_FILE_NAME: &str = "bitcask.unsealed";
+
+// 元数据文件的格式，记录数据文件的格式版本和数据库第一次打开时使用的配置项
+//
+// +--------------+----------------+------------+------------+
+// | magic "BCKM" | format version | index type | comparator |
+// +--------------+----------------+------------+------------+
+//      4字节           1字节           1字节        1字节
+const META_MAGIC: &[u8; 4] = b"BCKM";
+
+// 当前支持的数据文件格式版本，修改数据文件的格式时需要递增
+// 版本 2 增加了数据文件末尾的 footer 记录，版本 3 增加了记录末尾的对齐填充，版本 4 增加了记录中 key 的版本
+pub(crate) const FORMAT_VERSION: u8 = 4;
+
+/// 数据库目录中保存的元数据
+#[derive(Debug, PartialEq)]
+pub(crate) struct Meta {
+    format_version: u8,   // 数据文件的格式版本
+    index_type: u8,       // 创建时的索引类型，只做记录
+    has_comparator: bool, // 是否使用了自定义的 key 比较函数
+}
+
+impl Meta {
+    pub(crate) fn from_options(opts: &Options) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            index_type: opts.index_type.clone() as u8,
+            has_comparator: opts.comparator.is_some(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = META_MAGIC.to_vec();
+        buf.push(self.format_version);
+        buf.push(self.index_type);
+        buf.push(self.has_comparator as u8);
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() <= META_MAGIC.len() || &buf[..4] != META_MAGIC {
+            return Err(Errors::DataDirectoryCorrupted);
+        }
+        // 更新的版本可能修改了元数据的格式，先校验版本再解析后面的内容
+        if buf[4] > FORMAT_VERSION {
+            return Err(Errors::UnsupportedFormatVersion);
+        }
+        if buf.len() != META_MAGIC.len() + 3 {
+            return Err(Errors::DataDirectoryCorrupted);
+        }
+        Ok(Self {
+            format_version: buf[4],
+            index_type: buf[5],
+            has_comparator: buf[6] != 0,
+        })
+    }
+}
+
+// 校验数据文件的格式版本，以及元数据文件中记录的配置项和当前的配置项是否一致
+// 索引每次打开时都从数据文件中重建，数据文件的格式和索引类型无关，可以使用不同的索引类型打开，只记录不校验
+// 元数据文件不存在时说明是第一次打开，写入当前的配置项，只读模式下不写入
+pub(crate) fn check_meta(opts: &Options) -> Result<()> {
+    let path = opts.dir_path.join(META_FILE_NAME);
+    let meta = Meta::from_options(opts);
+
+    match fs::read(&path) {
+        Ok(buf) => {
+            let stored = Meta::decode(&buf)?;
+            if stored.has_comparator != meta.has_comparator {
+                return Err(Errors::OptionsMismatch);
+            }
+            // 旧版本的数据文件仍然可以读取，之后会写入新格式的数据，更新元数据中的版本，避免旧版本的程序打开
+            if stored.format_version < FORMAT_VERSION && !opts.read_only {
+                return write_meta(&path, &meta);
+            }
+            Ok(())
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if opts.read_only {
+                return Ok(());
+            }
+            write_meta(&path, &meta)
+        }
+        Err(e) => {
+            error!("failed to read meta file: {}", e);
+            Err(Errors::FailedToReadDatabaseDir)
+        }
+    }
+}
+
+fn write_meta(path: &Path, meta: &Meta) -> Result<()> {
+    if let Err(e) = fs::write(path, meta.encode()) {
+        error!("failed to write meta file: {}", e);
+        return Err(Errors::FailedToWriteMetaFile);
+    }
+    Ok(())
+}
+
+// 记录可能没有封存的数据文件，也就是每个写入分片正在写入的数据文件，以及在后台持久化还没有完成的数据文件
+// 崩溃之后只有这些数据文件的末尾可能有没有写完的记录，其他数据文件中无法解析的记录都是数据损坏
+//
+// +--------------+---------+---------+-----+
+// | magic "BCKU" | file id | file id | ... |
+// +--------------+---------+---------+-----+
+//      4字节         8字节      8字节
+const UNSEALED_MAGIC: &[u8; 4] = b"BCKU";
+
+/// 可能没有封存的数据文件，修改之后先写入临时文件再重命名，保证崩溃时文件内容完整
+pub(crate) struct UnsealedFiles {
+    path: PathBuf,
+    file_ids: Mutex<BTreeSet<u64>>,
+}
+
+impl UnsealedFiles {
+    /// 读取数据库目录中记录的数据文件，文件不存在时返回 None，说明是之前的版本创建的数据库
+    pub(crate) fn load(dir_path: &Path) -> Result<(Self, Option<BTreeSet<u64>>)> {
+        let path = dir_path.join(UNSEALED_FILE_NAME);
+        let file_ids = match fs::read(&path) {
+            Ok(buf) => Some(decode_file_ids(&buf)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => {
+                error!("failed to read unsealed file: {}", e);
+                return Err(Errors::FailedToReadDatabaseDir);
+            }
+        };
+        let unsealed = Self {
+            path,
+            file_ids: Mutex::new(file_ids.clone().unwrap_or_default()),
+        };
+        Ok((unsealed, file_ids))
+    }
+
+    /// 修改记录的数据文件并持久化，需要在开始写入新的数据文件之前调用
+    pub(crate) fn update(&self, f: impl FnOnce(&mut BTreeSet<u64>)) -> Result<()> {
+        let mut file_ids = self.file_ids.lock();
+        f(&mut file_ids);
+        if let Err(e) = self.write(&file_ids) {
+            error!("failed to write unsealed file: {}", e);
+            return Err(Errors::FailedToWriteMetaFile);
+        }
+        Ok(())
+    }
+
+    /// 后台持久化完成之后移除数据文件，失败时保留记录，之后打开时仍然会检查文件末尾
+    pub(crate) fn seal_in_background(&self, file_id: u64) {
+        if let Err(e) = self.update(|file_ids| {
+            file_ids.remove(&file_id);
+        }) {
+            warn!("failed to seal data file {}: {}", file_id, e);
+        }
+    }
+
+    fn write(&self, file_ids: &BTreeSet<u64>) -> std::io::Result<()> {
+        let mut buf = UNSEALED_MAGIC.to_vec();
+        for file_id in file_ids {
+            buf.extend_from_slice(&file_id.to_be_bytes());
+        }
+        let tmp_path = self.path.with_extension("unsealed.tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        match self.path.parent() {
+            Some(dir_path) => fs::File::open(dir_path)?.sync_all(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn decode_file_ids(buf: &[u8]) -> Result<BTreeSet<u64>> {
+    let ids = match buf.strip_prefix(UNSEALED_MAGIC) {
+        Some(ids) if ids.len().is_multiple_of(8) => ids,
+        _ => return Err(Errors::DataDirectoryCorrupted),
+    };
+    Ok(ids
+        .chunks(8)
+        .map(|id| u64::from_be_bytes(id.try_into().unwrap()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::IndexType;
+
+    #[test]
+    fn test_meta_encode_and_decode() {
+        let opts = Options {
+            index_type: IndexType::HashMap,
+            ..Default::default()
+        };
+        let meta = Meta::from_options(&opts);
+        assert_eq!(meta, Meta::decode(&meta.encode()).unwrap());
+
+        let res1 = Meta::decode(b"BCKM");
+        assert_eq!(Errors::DataDirectoryCorrupted, res1.err().unwrap());
+        let res2 = Meta::decode(&[b'B', b'C', b'K', b'M', FORMAT_VERSION + 1]);
+        assert_eq!(Errors::UnsupportedFormatVersion, res2.err().unwrap());
+    }
+
+    #[test]
+    fn test_unsealed_files() {
+        let dir_path = PathBuf::from("/tmp/bitcask-rs-unsealed-files");
+        fs::create_dir_all(&dir_path).unwrap();
+
+        // 文件不存在时返回 None
+        let (unsealed, file_ids) = UnsealedFiles::load(&dir_path).unwrap();
+        assert!(file_ids.is_none());
+
+        // 修改之后重新读取得到相同的数据文件
+        let res = unsealed.update(|file_ids| file_ids.extend([3, 1, 7]));
+        assert!(res.is_ok());
+        unsealed.seal_in_background(3);
+        let (_, file_ids) = UnsealedFiles::load(&dir_path).unwrap();
+        assert_eq!(Some(BTreeSet::from([1, 7])), file_ids);
+        assert!(!dir_path.join("bitcask.unsealed.tmp").exists());
+
+        // 内容损坏时返回错误
+        fs::write(dir_path.join(UNSEALED_FILE_NAME), b"BCKU\x01").unwrap();
+        let res = UnsealedFiles::load(&dir_path);
+        assert_eq!(Errors::DataDirectoryCorrupted, res.err().unwrap());
+
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+}
@@ -0,0 +1,16 @@
+/// 存储引擎的事件回调，用于导出监控指标
+/// 回调在写入或读取的线程中同步调用，写入相关的回调调用时持有写锁，实现中不能执行耗时的操作，
+/// 也不能再调用存储引擎的写入方法
+pub trait EngineObserver: Send + Sync {
+    /// 写入了一条数据
+    fn on_put(&self, _key_len: usize, _value_len: usize) {}
+
+    /// 读取了一条数据，hit 表示 key 是否存在
+    fn on_get(&self, _hit: bool) {}
+
+    /// 删除了一条数据
+    fn on_delete(&self) {}
+
+    /// 活跃文件写满之后切换到了新的数据文件
+    fn on_rollover(&self, _new_file_id: u64) {}
+}
@@ -1,10 +1,19 @@
-use std::path::PathBuf;
+use std::{cmp::Ordering, path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    data::log_record::{MAX_INLINE_VALUE_SIZE, MAX_RECORD_ALIGNMENT},
+    errors::{Errors, Result},
+    observer::EngineObserver,
+};
 
 #[derive(Clone)]
 pub struct Options {
     // 数据库目录
     pub dir_path: PathBuf,
 
+    // 数据文件存放的目录，新的数据文件会在这些目录之间轮流存放，为空时存放到 dir_path 中
+    pub data_dirs: Vec<PathBuf>,
+
     // 数据文件大小
     pub data_file_size: u64,
 
@@ -13,39 +22,564 @@ pub struct Options {
 
     // 索引类型
     pub index_type: IndexType,
+
+    // 是否以只读模式打开，只读模式下不会创建目录，也不允许写入数据
+    // 只读模式下不获取目录的文件锁，可以在其他进程写入的同时打开，但是写入的进程不知道有只读的进程存在，
+    // 写入的进程调用 clear、merge_partial，达到 max_data_files 时自动合并，以及打开时截断活跃文件末尾不完整的记录，
+    // 都可能删除或者截断只读的进程正在读取的数据文件，之后读取这部分数据会返回错误
+    pub read_only: bool,
+
+    // 写缓冲区大小，写入的数据先攒在缓冲区中，缓冲区满了或者 sync 时才写入文件，为 0 时不进行缓冲
+    pub write_buffer_size: usize,
+
+    // 活跃文件 io管理接口的写缓冲区大小，为 0 时不缓冲
+    // 和 write_buffer_size 不同，读取之前会先把缓冲区中的数据写入文件，Engine::flush 可以只写入操作系统而不持久化
+    pub io_buffer_size: usize,
+
+    // 数据记录的校验算法，只影响新写入的记录，读取时根据记录中保存的算法进行校验
+    pub checksum: ChecksumKind,
+
+    // 是否预分配数据文件的空间，活跃文件创建时直接分配 data_file_size 大小，封存或关闭时截断到实际写入的大小
+    pub preallocate: bool,
+
+    // key 的比较函数，决定索引和迭代器中 key 的顺序，为空时按照字节序排序
+    // 比较函数返回相等时必须是相同的 key，索引持有一份比较函数，和索引一起释放
+    // 哈希索引按照 key 的字节查找，不能和比较函数一起使用
+    pub comparator: Option<Comparator>,
+
+    // 读取时发现记录的 crc 校验失败，是否从数据文件中查找这个 key 更早的有效版本返回
+    pub repair_on_read: bool,
+
+    // 读取 value 时是否校验记录的 crc，存储可靠时可以关闭以减少读取的开销，关闭之后 repair_on_read 不再生效
+    // 只影响 get 等读取 value 的路径，启动时加载索引以及 verify 和 repair 仍然会校验
+    pub verify_crc_on_read: bool,
+
+    // 创建新的数据文件之后是否持久化所在的目录，部分文件系统上不持久化目录时，崩溃后可能丢失新创建的文件
+    pub sync_dir: bool,
+
+    // 读缓存可以缓存的记录数量，按照数据位置缓存最近读取的 value，为 0 时不使用缓存
+    pub read_cache_size: usize,
+
+    // 索引指向的数据文件不存在时，是否删除这条索引，之后读取这个 key 时返回 KeyNotFound
+    pub heal_dangling_index: bool,
+
+    // 启动时加载索引，顺序读取数据文件时每次预读的大小，为 0 时不进行预读
+    pub read_ahead_size: usize,
+
+    // 最多同时打开的旧的数据文件数量，超出时关闭最久没有访问的文件，之后访问时重新打开
+    // 活跃文件一直保持打开，为 0 时不限制
+    pub max_open_files: usize,
+
+    // 开启 sync_writes 时是否批量持久化，put 和 delete 写入之后交给后台线程持久化
+    // 多个线程并发写入时，一次持久化可以覆盖多个写入，其他的写入方法不受影响
+    pub group_commit: bool,
+
+    // 启动时并行加载数据文件的线程数量，为 None 时使用机器可用的并行度
+    pub load_threads: Option<usize>,
+
+    // 长度不超过这个值的 value 会内联在索引中，读取时不需要访问数据文件，为 0 时不内联
+    // 最大为 16 字节，数据文件中仍然会写入完整的记录
+    pub inline_value_threshold: usize,
+
+    // 打开数据库时是否跳过文件名不是数字的 .data 文件，比如手动复制的 backup.data，默认直接返回错误
+    pub skip_unparsable_files: bool,
+
+    // 存储引擎的事件回调，用于导出监控指标
+    pub observer: Option<Arc<dyn EngineObserver>>,
+
+    // 数据文件存放在 dir_path 下的这个子目录中，元数据文件仍然放在 dir_path 中，为 None 时直接存放到 dir_path 中
+    // 只在没有设置 data_dirs 时生效
+    pub data_subdir: Option<String>,
+
+    // 写入数据之前数据文件所在的磁盘至少要保留的可用空间，不足时拒绝写入，为 0 时不检查
+    // 每次写入都会查询一次磁盘的可用空间
+    pub min_free_bytes: u64,
+
+    // merge_partial 时保留文件 id 不小于活跃文件 id 减去这个值的数据文件中的删除记录，
+    // 为 0 时只要不会让删除的 key 重新出现就丢弃删除记录
+    // 使用 changes_since 同步数据时，需要保留同步端还没有读取到的文件中的删除记录
+    pub tombstone_retention: u64,
+
+    // 切换活跃文件时在后台线程中持久化旧的数据文件，写入不需要等待持久化完成
+    // 之后持久化新的活跃文件时会先等待旧的文件持久化完成，保证数据按照写入的顺序持久化
+    pub async_rollover_sync: bool,
+
+    // 写入的记录对齐到这个大小，记录末尾填充 0，下一条记录从对齐的位置开始，为 1 时不填充
+    // 必须是 2 的幂，并且不能超过 MAX_RECORD_ALIGNMENT，数据文件中可以同时存在对齐和没有对齐的记录
+    pub record_alignment: u64,
+
+    // 打开数据库时活跃文件中最后一条完整的记录之后还有数据，比如崩溃时没有写完的记录，是否截断这部分数据
+    // 为 false 时打开数据库返回 TrailingDataInActiveFile，不修改数据文件，只读模式下不会截断也不会报错
+    pub truncate_on_recovery: bool,
+
+    // 数据文件数量的上限，包括活跃文件，切换活跃文件会超过上限时先合并旧的数据文件，为 0 时不限制
+    // 合并之后仍然无法切换时写入返回 TooManyDataFiles，不为 0 时至少为 2
+    pub max_data_files: usize,
+
+    // 打开数据库时获取目录文件锁的等待时间，锁被其他进程持有时在这段时间内不断重试，为 None 时不等待
+    // 超时之后返回 DatabaseIsUsing，只读模式下获取的锁不会和写入的进程以及其他只读的进程冲突，不需要等待
+    pub lock_timeout: Option<Duration>,
+
+    // 写入分片的数量，每个分片有自己的活跃文件和写锁，按照 key 的哈希选择分片，不同分片的写入可以并发执行
+    // 每个分片的活跃文件独立切换，涉及多个 key 的写操作需要获取所有分片的写锁，不能和 max_data_files 一起使用
+    // 数据库目录中记录了每个分片正在写入的数据文件，打开数据库时只截断这些数据文件末尾不完整的记录
+    pub write_shards: usize,
 }
 
+/// key 的比较函数
+pub type Comparator = Arc<dyn Fn(&[u8], &[u8]) -> Ordering + Send + Sync>;
+
 #[derive(Clone)]
 pub enum IndexType {
     /// BTree 索引
     BTree,
 
-    /// 跳表索引
+    /// 跳表索引，还没有实现，打开数据库时返回 UnsupportedIndexType
     SkipList,
+
+    /// 快照 BTree 索引，读取不会被写入阻塞，但每次写入都需要拷贝整个索引
+    SnapshotBTree,
+
+    /// 哈希索引，点查更快、占用内存更少，但不维护 key 的顺序，
+    /// list_keys 和迭代时需要拷贝并排序全部数据
+    HashMap,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             dir_path: std::env::temp_dir().join("bitcask-rs"),
+            data_dirs: Vec::new(),
             data_file_size: 256 * 1024 * 1024, // 256MB,
             sync_writes: false,
             index_type: IndexType::BTree,
+            read_only: false,
+            write_buffer_size: 0,
+            io_buffer_size: 0,
+            checksum: ChecksumKind::Crc32,
+            preallocate: false,
+            comparator: None,
+            repair_on_read: false,
+            verify_crc_on_read: true,
+            sync_dir: false,
+            read_cache_size: 0,
+            heal_dangling_index: false,
+            read_ahead_size: 64 * 1024, // 64KB
+            max_open_files: 0,
+            group_commit: false,
+            load_threads: None,
+            inline_value_threshold: 0,
+            skip_unparsable_files: false,
+            observer: None,
+            data_subdir: None,
+            min_free_bytes: 0,
+            tombstone_retention: 0,
+            async_rollover_sync: false,
+            record_alignment: 1,
+            truncate_on_recovery: true,
+            max_data_files: 0,
+            lock_timeout: None,
+            write_shards: 1,
         }
     }
 }
 
+impl Options {
+    /// 获取配置项构造器
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+
+    // 获取所有存放数据文件的目录
+    pub(crate) fn get_data_dirs(&self) -> Vec<PathBuf> {
+        if self.data_dirs.is_empty() {
+            return match &self.data_subdir {
+                Some(subdir) => vec![self.dir_path.join(subdir)],
+                None => vec![self.dir_path.clone()],
+            };
+        }
+        self.data_dirs.clone()
+    }
+
+    // 根据文件 id 获取新的数据文件存放的目录
+    pub(crate) fn get_data_dir(&self, file_id: u64) -> PathBuf {
+        let data_dirs = self.get_data_dirs();
+        data_dirs[file_id as usize % data_dirs.len()].clone()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChecksumKind {
+    /// CRC32
+    Crc32 = 0,
+
+    /// CRC32C，支持硬件加速
+    Crc32c = 1,
+
+    /// XxHash64，只保存低 32 位
+    XxHash64 = 2,
+}
+
+/// 配置项构造器，在 build 的时候对配置项进行校验
+#[derive(Default)]
+pub struct OptionsBuilder {
+    opts: Options,
+}
+
+impl OptionsBuilder {
+    /// 设置数据库目录
+    pub fn dir_path(mut self, dir_path: PathBuf) -> Self {
+        self.opts.dir_path = dir_path;
+        self
+    }
+
+    /// 设置数据文件存放的目录
+    pub fn data_dirs(mut self, data_dirs: Vec<PathBuf>) -> Self {
+        self.opts.data_dirs = data_dirs;
+        self
+    }
+
+    /// 设置数据文件大小
+    pub fn data_file_size(mut self, data_file_size: u64) -> Self {
+        self.opts.data_file_size = data_file_size;
+        self
+    }
+
+    /// 设置是否每次写都持久化
+    pub fn sync_writes(mut self, sync_writes: bool) -> Self {
+        self.opts.sync_writes = sync_writes;
+        self
+    }
+
+    /// 设置索引类型
+    pub fn index_type(mut self, index_type: IndexType) -> Self {
+        self.opts.index_type = index_type;
+        self
+    }
+
+    /// 设置是否以只读模式打开
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.opts.read_only = read_only;
+        self
+    }
+
+    /// 设置写缓冲区大小
+    pub fn write_buffer_size(mut self, write_buffer_size: usize) -> Self {
+        self.opts.write_buffer_size = write_buffer_size;
+        self
+    }
+
+    /// 设置活跃文件 io管理接口的写缓冲区大小
+    pub fn io_buffer_size(mut self, io_buffer_size: usize) -> Self {
+        self.opts.io_buffer_size = io_buffer_size;
+        self
+    }
+
+    /// 设置数据记录的校验算法
+    pub fn checksum(mut self, checksum: ChecksumKind) -> Self {
+        self.opts.checksum = checksum;
+        self
+    }
+
+    /// 设置是否预分配数据文件的空间
+    pub fn preallocate(mut self, preallocate: bool) -> Self {
+        self.opts.preallocate = preallocate;
+        self
+    }
+
+    /// 设置读取时 crc 校验失败是否返回 key 更早的有效版本
+    pub fn repair_on_read(mut self, repair_on_read: bool) -> Self {
+        self.opts.repair_on_read = repair_on_read;
+        self
+    }
+
+    /// 设置读取 value 时是否校验记录的 crc
+    pub fn verify_crc_on_read(mut self, verify_crc_on_read: bool) -> Self {
+        self.opts.verify_crc_on_read = verify_crc_on_read;
+        self
+    }
+
+    /// 设置创建新的数据文件之后是否持久化所在的目录
+    pub fn sync_dir(mut self, sync_dir: bool) -> Self {
+        self.opts.sync_dir = sync_dir;
+        self
+    }
+
+    /// 设置读缓存可以缓存的记录数量
+    pub fn read_cache_size(mut self, read_cache_size: usize) -> Self {
+        self.opts.read_cache_size = read_cache_size;
+        self
+    }
+
+    /// 设置索引指向的数据文件不存在时是否删除这条索引
+    pub fn heal_dangling_index(mut self, heal_dangling_index: bool) -> Self {
+        self.opts.heal_dangling_index = heal_dangling_index;
+        self
+    }
+
+    /// 设置启动时加载索引的预读大小
+    pub fn read_ahead_size(mut self, read_ahead_size: usize) -> Self {
+        self.opts.read_ahead_size = read_ahead_size;
+        self
+    }
+
+    /// 设置最多同时打开的旧的数据文件数量
+    pub fn max_open_files(mut self, max_open_files: usize) -> Self {
+        self.opts.max_open_files = max_open_files;
+        self
+    }
+
+    /// 设置开启 sync_writes 时是否批量持久化
+    pub fn group_commit(mut self, group_commit: bool) -> Self {
+        self.opts.group_commit = group_commit;
+        self
+    }
+
+    /// 设置启动时并行加载数据文件的线程数量
+    pub fn load_threads(mut self, load_threads: usize) -> Self {
+        self.opts.load_threads = Some(load_threads);
+        self
+    }
+
+    /// 设置内联在索引中的 value 的最大长度
+    pub fn inline_value_threshold(mut self, inline_value_threshold: usize) -> Self {
+        self.opts.inline_value_threshold = inline_value_threshold;
+        self
+    }
+
+    /// 设置打开数据库时是否跳过文件名不是数字的数据文件
+    pub fn skip_unparsable_files(mut self, skip_unparsable_files: bool) -> Self {
+        self.opts.skip_unparsable_files = skip_unparsable_files;
+        self
+    }
+
+    /// 设置存储引擎的事件回调
+    pub fn observer(mut self, observer: Arc<dyn EngineObserver>) -> Self {
+        self.opts.observer = Some(observer);
+        self
+    }
+
+    /// 设置数据文件存放的子目录
+    pub fn data_subdir(mut self, data_subdir: String) -> Self {
+        self.opts.data_subdir = Some(data_subdir);
+        self
+    }
+
+    /// 设置写入数据时磁盘至少要保留的可用空间
+    pub fn min_free_bytes(mut self, min_free_bytes: u64) -> Self {
+        self.opts.min_free_bytes = min_free_bytes;
+        self
+    }
+
+    /// 设置 merge_partial 时保留删除记录的数据文件数量
+    pub fn tombstone_retention(mut self, tombstone_retention: u64) -> Self {
+        self.opts.tombstone_retention = tombstone_retention;
+        self
+    }
+
+    /// 设置切换活跃文件时是否在后台持久化旧的数据文件
+    pub fn async_rollover_sync(mut self, async_rollover_sync: bool) -> Self {
+        self.opts.async_rollover_sync = async_rollover_sync;
+        self
+    }
+
+    /// 设置记录对齐的大小
+    pub fn record_alignment(mut self, record_alignment: u64) -> Self {
+        self.opts.record_alignment = record_alignment;
+        self
+    }
+
+    /// 设置打开数据库时是否截断活跃文件末尾无法解析的数据
+    pub fn truncate_on_recovery(mut self, truncate_on_recovery: bool) -> Self {
+        self.opts.truncate_on_recovery = truncate_on_recovery;
+        self
+    }
+
+    /// 设置数据文件数量的上限
+    pub fn max_data_files(mut self, max_data_files: usize) -> Self {
+        self.opts.max_data_files = max_data_files;
+        self
+    }
+
+    /// 设置打开数据库时获取目录文件锁的等待时间
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.opts.lock_timeout = Some(lock_timeout);
+        self
+    }
+
+    /// 设置写入分片的数量
+    pub fn write_shards(mut self, write_shards: usize) -> Self {
+        self.opts.write_shards = write_shards;
+        self
+    }
+
+    /// 设置 key 的比较函数
+    pub fn comparator(mut self, comparator: Comparator) -> Self {
+        self.opts.comparator = Some(comparator);
+        self
+    }
+
+    /// 校验并构造配置项
+    pub fn build(self) -> Result<Options> {
+        if let Some(e) = check_options(&self.opts) {
+            return Err(e);
+        }
+        Ok(self.opts)
+    }
+}
+
 /// 索引迭代器配置项
+#[derive(Default)]
 pub struct IteratorOptions {
     pub prefix: Vec<u8>,
     pub reverse: bool,
+    // 是否返回已经删除的 key，只对 iter_with_deleted 生效，索引中不保存删除的 key
+    pub include_deleted: bool,
 }
 
-impl Default for IteratorOptions {
-    fn default() -> Self {
-        Self {
-            prefix: Default::default(),
-            reverse: false,
+/// 校验用户传递过来的配置项
+pub(crate) fn check_options(opts: &Options) -> Option<Errors> {
+    let dir_path = opts.dir_path.to_str();
+    if dir_path.is_none() || dir_path.unwrap().is_empty() {
+        return Some(Errors::DirPathIsEmpty);
+    }
+
+    for data_dir in opts.data_dirs.iter() {
+        if data_dir.as_os_str().is_empty() {
+            return Some(Errors::DirPathIsEmpty);
+        }
+    }
+
+    if opts.data_subdir.as_ref().is_some_and(|s| s.is_empty()) {
+        return Some(Errors::DirPathIsEmpty);
+    }
+
+    if opts.data_file_size == 0 {
+        return Some(Errors::DataFileSizeTooSmall);
+    }
+
+    if opts.load_threads == Some(0) {
+        return Some(Errors::LoadThreadsIsZero);
+    }
+
+    if opts.inline_value_threshold > MAX_INLINE_VALUE_SIZE {
+        return Some(Errors::InlineValueThresholdTooLarge);
+    }
+
+    if !opts.record_alignment.is_power_of_two() || opts.record_alignment > MAX_RECORD_ALIGNMENT {
+        return Some(Errors::InvalidRecordAlignment);
+    }
+
+    if opts.max_data_files == 1 {
+        return Some(Errors::MaxDataFilesTooSmall);
+    }
+
+    if opts.write_shards == 0 {
+        return Some(Errors::WriteShardsIsZero);
+    }
+
+    // 达到上限时需要合并，合并的过程中不能有其他分片的写入
+    if opts.write_shards > 1 && opts.max_data_files > 0 {
+        return Some(Errors::MaxDataFilesWithWriteShards);
+    }
+
+    if matches!(opts.index_type, IndexType::SkipList) {
+        return Some(Errors::UnsupportedIndexType);
+    }
+
+    // 哈希索引按照字节查找 key，和比较函数认为相等的 key 不一致
+    if opts.comparator.is_some() && matches!(opts.index_type, IndexType::HashMap) {
+        return Some(Errors::ComparatorWithHashMapIndex);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_builder() {
+        // 1.正常构造配置项
+        let opts_res1 = Options::builder()
+            .dir_path(PathBuf::from("/tmp/bitcask-rs-builder"))
+            .data_file_size(64 * 1024 * 1024)
+            .sync_writes(true)
+            .index_type(IndexType::BTree)
+            .build();
+        assert!(opts_res1.is_ok());
+        let opts1 = opts_res1.unwrap();
+        assert_eq!(opts1.dir_path, PathBuf::from("/tmp/bitcask-rs-builder"));
+        assert_eq!(opts1.data_file_size, 64 * 1024 * 1024);
+        assert!(opts1.sync_writes);
+
+        // 2.数据库目录为空
+        let opts_res2 = Options::builder().dir_path(PathBuf::new()).build();
+        assert_eq!(Errors::DirPathIsEmpty, opts_res2.err().unwrap());
+
+        // 3.数据文件大小为 0
+        let opts_res3 = Options::builder().data_file_size(0).build();
+        assert_eq!(Errors::DataFileSizeTooSmall, opts_res3.err().unwrap());
+
+        // 4.数据文件目录为空
+        let opts_res4 = Options::builder().data_dirs(vec![PathBuf::new()]).build();
+        assert_eq!(Errors::DirPathIsEmpty, opts_res4.err().unwrap());
+
+        // 5.加载线程数量为 0
+        let opts_res5 = Options::builder().load_threads(0).build();
+        assert_eq!(Errors::LoadThreadsIsZero, opts_res5.err().unwrap());
+
+        // 6.内联 value 的长度超过上限
+        let opts_res6 = Options::builder()
+            .inline_value_threshold(MAX_INLINE_VALUE_SIZE + 1)
+            .build();
+        assert_eq!(
+            Errors::InlineValueThresholdTooLarge,
+            opts_res6.err().unwrap()
+        );
+
+        // 7.数据文件子目录为空
+        let opts_res7 = Options::builder().data_subdir(String::new()).build();
+        assert_eq!(Errors::DirPathIsEmpty, opts_res7.err().unwrap());
+
+        // 8.记录对齐的大小不是 2 的幂或者超过上限
+        for alignment in [0, 3, MAX_RECORD_ALIGNMENT * 2] {
+            let opts_res8 = Options::builder().record_alignment(alignment).build();
+            assert_eq!(Errors::InvalidRecordAlignment, opts_res8.err().unwrap());
         }
+
+        // 9.数据文件数量的上限为 1
+        let opts_res9 = Options::builder().max_data_files(1).build();
+        assert_eq!(Errors::MaxDataFilesTooSmall, opts_res9.err().unwrap());
+
+        // 10.写入分片的数量为 0，或者多个写入分片和数据文件数量的上限一起使用
+        let opts_res10 = Options::builder().write_shards(0).build();
+        assert_eq!(Errors::WriteShardsIsZero, opts_res10.err().unwrap());
+        let opts_res11 = Options::builder().write_shards(4).max_data_files(8).build();
+        assert_eq!(
+            Errors::MaxDataFilesWithWriteShards,
+            opts_res11.err().unwrap()
+        );
+
+        // 11.哈希索引和比较函数一起使用
+        let comparator: Comparator = Arc::new(|a: &[u8], b: &[u8]| b.cmp(a));
+        let opts_res12 = Options::builder()
+            .index_type(IndexType::HashMap)
+            .comparator(comparator.clone())
+            .build();
+        assert_eq!(
+            Errors::ComparatorWithHashMapIndex,
+            opts_res12.err().unwrap()
+        );
+        let opts_res13 = Options::builder()
+            .index_type(IndexType::BTree)
+            .comparator(comparator)
+            .build();
+        assert!(opts_res13.is_ok());
+
+        // 12.还没有实现的跳表索引
+        let opts_res14 = Options::builder().index_type(IndexType::SkipList).build();
+        assert_eq!(Errors::UnsupportedIndexType, opts_res14.err().unwrap());
     }
 }
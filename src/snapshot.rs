@@ -0,0 +1,258 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use bytes::Bytes;
+
+use crate::{
+    data::log_record::LogRecordPos,
+    db::Engine,
+    errors::{Errors, Result},
+    file_pins::FilePin,
+    index::{
+        btree::{BTreeIterator, IndexKey, KeyOrder},
+        IndexIterator,
+    },
+    options::IteratorOptions,
+};
+
+/// 数据库某一时刻的只读视图，之后的写入、覆盖写入和删除都不会影响快照中读取到的数据
+///
+/// 快照只保存了创建时的索引，value 在读取时才根据索引中的位置从数据文件中读取，
+/// 数据文件是追加写入的，快照引用的记录一直有效。快照和快照的迭代器释放之前，
+/// merge_partial 以及达到 max_data_files 时自动触发的合并不会删除快照引用的数据文件，
+/// 调用 clear 之后读取会返回 DataFileNotFound
+pub struct Snapshot<'a> {
+    tree: Arc<BTreeMap<IndexKey, LogRecordPos>>, // 创建快照时的索引
+    order: KeyOrder,                             // key 的排序方式，查找和遍历快照中的 key 时使用
+    engine: &'a Engine,
+    pin: FilePin<'a>, // 保留快照创建之后被合并的数据文件
+}
+
+/// 快照的迭代器，按照 key 的顺序遍历快照中的数据
+pub struct SnapshotIter<'a> {
+    index_iter: Box<dyn IndexIterator>,
+    engine: &'a Engine,
+    _pin: FilePin<'a>, // 和快照使用相同的版本，快照释放之后仍然可以读取
+}
+
+impl Engine {
+    /// 创建当前数据的快照，使用 SnapshotBTree 索引时不需要拷贝索引，其他索引会拷贝一份当前的索引
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        // 先记录版本再读取索引，快照中的位置所在的数据文件都会被保留
+        let pin = self.file_pins.pin();
+        let (tree, order) = self.index.snapshot();
+        Snapshot {
+            tree,
+            order,
+            engine: self,
+            pin,
+        }
+    }
+}
+
+impl<'a> Snapshot<'a> {
+    /// 读取快照中 key 对应的 value
+    pub fn get(&self, key: Bytes) -> Result<Bytes> {
+        // 判断 key 的有效性
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        let key = self.order.key(key.to_vec());
+        match self.tree.get(&key) {
+            Some(pos) => self.engine.get_value_by_position(pos),
+            None => Err(Errors::KeyNotFound),
+        }
+    }
+
+    /// 获取快照的迭代器
+    pub fn iter(&self, options: IteratorOptions) -> SnapshotIter<'a> {
+        SnapshotIter {
+            index_iter: Box::new(BTreeIterator::new(
+                self.tree.clone(),
+                options,
+                self.order.clone(),
+            )),
+            engine: self.engine,
+            _pin: self.pin.clone(),
+        }
+    }
+
+    /// 快照中 key 的数量
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// 快照中是否没有数据
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+}
+
+impl std::iter::Iterator for SnapshotIter<'_> {
+    type Item = Result<(Bytes, Bytes)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, pos) = self.index_iter.next()?;
+        let key = Bytes::copy_from_slice(key);
+        let pos = *pos;
+        Some(
+            self.engine
+                .get_value_by_position(&pos)
+                .map(|value| (key, value)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        data::data_file::get_data_file_name,
+        options::{IndexType, Options},
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        for index_type in [
+            IndexType::BTree,
+            IndexType::SnapshotBTree,
+            IndexType::HashMap,
+        ] {
+            let opts = Options {
+                dir_path: PathBuf::from("/tmp/bitcask-rs-snapshot"),
+                index_type,
+                ..Default::default()
+            };
+            let engine = Engine::open(opts.clone()).expect("failed to open engine");
+            for i in 0..10 {
+                let res = engine.put(get_test_key(i), get_test_value(i));
+                assert!(res.is_ok());
+            }
+
+            let snapshot = engine.snapshot();
+
+            // 创建快照之后写入新的 key、覆盖写入和删除
+            for i in 10..20 {
+                let res = engine.put(get_test_key(i), get_test_value(i));
+                assert!(res.is_ok());
+            }
+            let res1 = engine.put(get_test_key(1), get_test_value(100));
+            assert!(res1.is_ok());
+            let res2 = engine.delete(get_test_key(2));
+            assert!(res2.is_ok());
+
+            // 快照中看不到之后的修改
+            assert_eq!(10, snapshot.len());
+            assert_eq!(
+                Errors::KeyNotFound,
+                snapshot.get(get_test_key(10)).err().unwrap()
+            );
+            assert_eq!(get_test_value(1), snapshot.get(get_test_key(1)).unwrap());
+            assert_eq!(get_test_value(2), snapshot.get(get_test_key(2)).unwrap());
+            let items: Vec<(Bytes, Bytes)> = snapshot
+                .iter(IteratorOptions::default())
+                .map(|item| item.unwrap())
+                .collect();
+            assert_eq!(10, items.len());
+            for (i, (key, value)) in items.into_iter().enumerate() {
+                assert_eq!(get_test_key(i), key);
+                assert_eq!(get_test_value(i), value);
+            }
+
+            // 数据库中是最新的数据
+            assert_eq!(19, engine.list_keys().unwrap().len());
+            assert_eq!(get_test_value(100), engine.get(get_test_key(1)).unwrap());
+
+            // 删除测试的文件夹
+            std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        }
+    }
+
+    #[test]
+    fn test_snapshot_after_merge() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-snapshot-after-merge"),
+            data_file_size: 4 * 1024,
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..100 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        // 覆盖写入之后旧的数据文件中的记录全部失效
+        for i in 0..100 {
+            let res = engine.put(get_test_key(i), get_test_value(i + 100));
+            assert!(res.is_ok());
+        }
+        let pos = engine.index.get(get_test_key(0).to_vec()).unwrap();
+
+        let file_name = get_data_file_name(opts.get_data_dir(pos.file_id), pos.file_id);
+
+        let snapshot = engine.snapshot();
+        let iter = engine.iter(IteratorOptions::default());
+        let res1 = engine.merge_partial(usize::MAX);
+        assert!(res1.unwrap() > 0);
+        assert!(!engine.older_files.read().contains_key(&pos.file_id));
+
+        // 快照和迭代器还没有释放，合并之后的数据文件仍然保留在磁盘上，读取到的是创建时的数据
+        assert!(file_name.exists());
+        assert_eq!(100, snapshot.len());
+        assert_eq!(get_test_value(100), snapshot.get(get_test_key(0)).unwrap());
+        assert!(snapshot
+            .iter(IteratorOptions::default())
+            .all(|item| item.is_ok()));
+        let mut count = 0;
+        while let Some((_, value)) = iter.next() {
+            assert!(!value.is_empty());
+            count += 1;
+        }
+        assert_eq!(100, count);
+
+        // 数据库中的数据不受影响，合并之后创建的快照可以正常读取
+        assert_eq!(get_test_value(100), engine.get(get_test_key(0)).unwrap());
+        let snapshot2 = engine.snapshot();
+        assert_eq!(get_test_value(100), snapshot2.get(get_test_key(0)).unwrap());
+
+        // 合并之前创建的快照和迭代器全部释放之后才删除数据文件
+        std::mem::drop(iter);
+        assert!(file_name.exists());
+        std::mem::drop(snapshot);
+        assert!(!file_name.exists());
+        std::mem::drop(snapshot2);
+
+        // 清空之后新建的数据文件不会复用文件 id，快照读取时找不到数据文件，释放时也不会删除新的数据文件
+        for i in 0..100 {
+            let res = engine.put(get_test_key(i), get_test_value(i + 200));
+            assert!(res.is_ok());
+        }
+        let snapshot3 = engine.snapshot();
+        let res2 = engine.merge_partial(usize::MAX);
+        assert!(res2.unwrap() > 0);
+        let res3 = engine.clear();
+        assert!(res3.is_ok());
+        for i in 0..100 {
+            let res = engine.put(get_test_key(i), get_test_value(i + 300));
+            assert!(res.is_ok());
+        }
+        for i in 0..100 {
+            let res = snapshot3.get(get_test_key(i));
+            assert_eq!(Errors::DataFileNotFound, res.err().unwrap());
+        }
+        std::mem::drop(snapshot3);
+        for i in 0..100 {
+            assert_eq!(
+                get_test_value(i + 300),
+                engine.get(get_test_key(i)).unwrap()
+            );
+        }
+
+        // 删除测试的文件夹
+        std::mem::drop(engine);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,116 @@
+use bytes::Bytes;
+use log::error;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+impl Engine {
+    /// 存储可以序列化的 key/value 数据，key 和 value 使用 bincode 编码
+    pub fn put_serde<K: Serialize, V: Serialize>(&self, key: &K, value: &V) -> Result<()> {
+        self.put(serialize(key)?, serialize(value)?)
+    }
+
+    /// 根据可以序列化的 key 获取数据，并将 value 反序列化为 V
+    pub fn get_serde<K: Serialize, V: DeserializeOwned>(&self, key: &K) -> Result<V> {
+        let value = self.get(serialize(key)?)?;
+        match bincode::deserialize(&value) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                error!("failed to deserialize value: {}", e);
+                Err(Errors::SerializationFailed)
+            }
+        }
+    }
+
+    /// 根据可以序列化的 key 删除数据
+    pub fn delete_serde<K: Serialize>(&self, key: &K) -> Result<()> {
+        self.delete(serialize(key)?)
+    }
+}
+
+fn serialize<T: Serialize>(v: &T) -> Result<Bytes> {
+    match bincode::serialize(v) {
+        Ok(buf) => Ok(Bytes::from(buf)),
+        Err(e) => {
+            error!("failed to serialize data: {}", e);
+            Err(Errors::SerializationFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde::Deserialize;
+
+    use crate::options::Options;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct UserKey {
+        tenant: String,
+        id: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct User {
+        name: String,
+        age: u32,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_put_and_get_serde() {
+        let opts = Options {
+            dir_path: PathBuf::from("/tmp/bitcask-rs-serde"),
+            ..Default::default()
+        };
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let key = UserKey {
+            tenant: "bitcask".to_string(),
+            id: 1,
+        };
+        let user = User {
+            name: "rust".to_string(),
+            age: 8,
+            tags: vec!["db".to_string(), "kv".to_string()],
+        };
+        let res1 = engine.put_serde(&key, &user);
+        assert!(res1.is_ok());
+        assert_eq!(user, engine.get_serde::<_, User>(&key).unwrap());
+
+        // 不同的 key 互不影响
+        let other = UserKey {
+            tenant: "bitcask".to_string(),
+            id: 2,
+        };
+        assert_eq!(
+            Errors::KeyNotFound,
+            engine.get_serde::<_, User>(&other).err().unwrap()
+        );
+
+        // value 的类型不匹配时返回错误
+        let res2 = engine.put_serde(&other, &1u8);
+        assert!(res2.is_ok());
+        assert_eq!(
+            Errors::SerializationFailed,
+            engine.get_serde::<_, User>(&other).err().unwrap()
+        );
+
+        let res3 = engine.delete_serde(&key);
+        assert!(res3.is_ok());
+        assert_eq!(
+            Errors::KeyNotFound,
+            engine.get_serde::<_, User>(&key).err().unwrap()
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
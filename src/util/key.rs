@@ -0,0 +1,25 @@
+/// 把 u64 编码为 8 字节的大端序 key，编码后的字节顺序和数值顺序一致
+/// 作为 key 存储时，迭代器、scan 等按照 key 顺序遍历的接口会按照数值从小到大返回
+pub fn encode_u64_be(n: u64) -> [u8; 8] {
+    n.to_be_bytes()
+}
+
+/// 把 encode_u64_be 编码的 key 解码为 u64，长度不是 8 字节时返回 None
+pub fn decode_u64_be(buf: &[u8]) -> Option<u64> {
+    let buf: [u8; 8] = buf.try_into().ok()?;
+    Some(u64::from_be_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_u64_be() {
+        for n in [0, 1, 256, 65536, u64::MAX] {
+            assert_eq!(Some(n), decode_u64_be(&encode_u64_be(n)));
+        }
+        assert!(encode_u64_be(255) < encode_u64_be(256));
+        assert_eq!(None, decode_u64_be(&[1, 2, 3]));
+    }
+}
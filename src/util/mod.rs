@@ -1 +1,3 @@
+pub mod key;
+#[cfg(test)]
 pub mod rand_kv;